@@ -42,9 +42,15 @@ pub struct FloatingEvent {
     pub(crate) goal_relationships: Vec<GoalRelationship>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct EventId(pub u32);
 
+impl std::fmt::Display for EventId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Event {
     BlockEvent(BlockEvent),
@@ -84,6 +90,15 @@ impl Event {
         })
     }
 
+    /// When the event is over. Floating events have no definite end.
+    pub fn end(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Event::BlockEvent(event) => Some(event.start + event.duration),
+            Event::InstantEvent(event) => Some(event.time),
+            Event::FloatingEvent(_) => None,
+        }
+    }
+
     pub fn goal_relationships(&self) -> &Vec<GoalRelationship> {
         match self {
             Event::BlockEvent(event) => &event.goal_relationships,
@@ -1,26 +1,78 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::event::EventId;
+use crate::{
+    event::EventId,
+    profile::goal_traversal::{
+        traverse_populated_goal_children, visit_populated_goal_children, GoalChildIndexPath,
+    },
+    query::TimeOfDayConfiguration,
+};
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
 pub enum GoalRelationship {
     Requires(GoalId),
     Ends(GoalId),
     WorksOn(GoalId),
     Starts(GoalId),
+    /// Like [WorksOn](GoalRelationship::WorksOn), but `effort` is added to the goal
+    /// once the event has ended and a
+    /// [ProcessDateTime](crate::request::GoalRequest::ProcessDateTime) request past the
+    /// end is handled. This is a separate variant so that existing relationships
+    /// deserialize unchanged.
+    WorksOnWithEffort {
+        goal_id: GoalId,
+        effort: u32,
+    },
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl GoalRelationship {
+    pub fn goal_id(&self) -> GoalId {
+        match self {
+            GoalRelationship::Requires(id) => *id,
+            GoalRelationship::Ends(id) => *id,
+            GoalRelationship::WorksOn(id) => *id,
+            GoalRelationship::Starts(id) => *id,
+            GoalRelationship::WorksOnWithEffort { goal_id, .. } => *goal_id,
+        }
+    }
+
+    pub fn goal_id_mut(&mut self) -> &mut GoalId {
+        match self {
+            GoalRelationship::Requires(id) => id,
+            GoalRelationship::Ends(id) => id,
+            GoalRelationship::WorksOn(id) => id,
+            GoalRelationship::Starts(id) => id,
+            GoalRelationship::WorksOnWithEffort { goal_id, .. } => goal_id,
+        }
+    }
+
+    /// The effort expected to be put into the related goal by the event.
+    pub fn expected_effort(&self) -> Option<u32> {
+        match self {
+            GoalRelationship::WorksOnWithEffort { effort, .. } => Some(*effort),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum GoalEvent {
     AddEffort {
         goal_id: GoalId,
         effort: u32,
+        /// When the effort was logged. Events recorded before effort events carried a
+        /// timestamp deserialize with the Unix epoch.
+        #[serde(default)]
+        datetime: DateTime<Utc>,
     },
     RemoveEffort {
         goal_id: GoalId,
         effort: u32,
+        #[serde(default)]
+        datetime: DateTime<Utc>,
     },
     Focus {
         focus_root_id: GoalId,
@@ -41,14 +93,25 @@ pub enum GoalEvent {
         goal_id: GoalId,
         new_effort_to_complete: u32,
         original_effort_to_complete: u32,
+        /// Effort to date removed by clamping it to the new effort to complete.
+        #[serde(default)]
+        clamped_effort: u32,
     },
     Add {
         goal_id: GoalId,
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        effort_to_complete: u32,
     },
     Refine {
         parent_goal_id: GoalId,
         parent_effort_removed: u32,
         new_child_goal_id: GoalId,
+        #[serde(default)]
+        child_name: String,
+        #[serde(default)]
+        child_effort_to_complete: u32,
     },
     Delete {
         deleted_goal_tree: PopulatedGoal,
@@ -56,21 +119,295 @@ pub enum GoalEvent {
     Rename {
         goal_id: GoalId,
         old_name: String,
+        #[serde(default)]
+        new_name: String,
+    },
+    Tag {
+        goal_id: GoalId,
+        tag: String,
+    },
+    Untag {
+        goal_id: GoalId,
+        tag: String,
     },
+    TimeOfDayConfigChanged {
+        old_config: TimeOfDayConfiguration,
+        new_config: TimeOfDayConfiguration,
+    },
+    /// Emitted alongside the event that caused an unfinished goal to become finished.
+    Finished {
+        goal_id: GoalId,
+    },
+    /// The source goal was merged into the target goal. `source_goal` is the source
+    /// goal as it was before the merge, and `moved_relationship_events` are the events
+    /// that had relationships moved from the source goal to the target goal. The
+    /// remaining fields record enough of the state before the merge to undo it.
+    Merge {
+        source_goal_id: GoalId,
+        target_goal_id: GoalId,
+        source_goal: Goal,
+        source_parent_goal_id: Option<GoalId>,
+        source_was_focused: bool,
+        moved_relationship_events: Vec<EventId>,
+        /// The index of the source goal among the children of its parent.
+        #[serde(default)]
+        source_child_index: Option<usize>,
+        #[serde(default)]
+        source_was_collapsed: bool,
+        /// The relationships of each of the `moved_relationship_events` before the
+        /// merge. Relationships moved to the target that duplicate one the event
+        /// already had with the target are dropped by the merge.
+        #[serde(default)]
+        moved_event_relationships: Vec<(EventId, Vec<GoalRelationship>)>,
+    },
+    SetNotes {
+        goal_id: GoalId,
+        old_notes: Option<String>,
+        #[serde(default)]
+        notes: Option<String>,
+    },
+    SetDeadline {
+        goal_id: GoalId,
+        old_deadline: Option<DateTime<Utc>>,
+        #[serde(default)]
+        deadline: Option<DateTime<Utc>>,
+    },
+    /// Every goal that was not already focused being focused.
+    FocusAll {
+        focused_goals: HashSet<GoalId>,
+    },
+    /// Every focused goal being unfocused.
+    UnfocusAll {
+        unfocused_goals: HashSet<GoalId>,
+    },
+    /// Two children of the same parent swapping positions.
+    SwapSiblings {
+        parent_goal_id: GoalId,
+        goal_id: GoalId,
+        other_goal_id: GoalId,
+    },
+    SetPriority {
+        goal_id: GoalId,
+        old_priority: Priority,
+        priority: Priority,
+    },
+    /// The effort to date of a goal being set directly rather than changed by an
+    /// amount.
+    SetEffort {
+        goal_id: GoalId,
+        old_effort: u32,
+        new_effort: u32,
+        datetime: DateTime<Utc>,
+    },
+    EffortUnitChanged {
+        old_unit: String,
+        new_unit: String,
+    },
+}
+
+impl GoalEvent {
+    /// Whether this event records a goal being completed. Frontends that want to give
+    /// feedback when a goal is finished should scan the events returned from
+    /// [handle_request](crate::request::GoalRequestHandler::handle_request) for
+    /// completions after each request.
+    pub fn is_completion(&self) -> bool {
+        matches!(
+            self,
+            GoalEvent::Finished { .. } | GoalEvent::RescopeByFinish { .. }
+        )
+    }
+
+    /// The goal, signed effort change and time of an [AddEffort](GoalEvent::AddEffort)
+    /// or [RemoveEffort](GoalEvent::RemoveEffort) event.
+    pub fn effort_change(&self) -> Option<(GoalId, i64, DateTime<Utc>)> {
+        match self {
+            GoalEvent::AddEffort {
+                goal_id,
+                effort,
+                datetime,
+            } => Some((*goal_id, *effort as i64, *datetime)),
+            GoalEvent::RemoveEffort {
+                goal_id,
+                effort,
+                datetime,
+            } => Some((*goal_id, -(*effort as i64), *datetime)),
+            _ => None,
+        }
+    }
+
+    fn from_effort_change(
+        goal_id: GoalId,
+        effort_change: i64,
+        datetime: DateTime<Utc>,
+    ) -> Option<GoalEvent> {
+        match effort_change.cmp(&0) {
+            std::cmp::Ordering::Greater => Some(GoalEvent::AddEffort {
+                goal_id,
+                effort: effort_change.min(u32::MAX as i64) as u32,
+                datetime,
+            }),
+            std::cmp::Ordering::Less => Some(GoalEvent::RemoveEffort {
+                goal_id,
+                effort: (-effort_change).min(u32::MAX as i64) as u32,
+                datetime,
+            }),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+}
+
+/// Fold each run of consecutive [AddEffort](GoalEvent::AddEffort) and
+/// [RemoveEffort](GoalEvent::RemoveEffort) events on the same goal into a single
+/// event with the net effort change, dropping runs that cancel out entirely. Events
+/// are never moved past each other, so the order relative to events on other goals
+/// is preserved.
+///
+/// Since removing effort saturates at zero, the compacted history only results in
+/// the same effort as the original if none of the removals in a run saturated.
+pub fn compact_effort_events(history: Vec<GoalEvent>) -> Vec<GoalEvent> {
+    let mut compacted = Vec::with_capacity(history.len());
+    let mut current_run: Option<(GoalId, i64, DateTime<Utc>)> = None;
+
+    for event in history {
+        match (current_run, event.effort_change()) {
+            (Some((run_goal_id, run_effort, _)), Some((goal_id, effort, datetime)))
+                if run_goal_id == goal_id =>
+            {
+                current_run = Some((goal_id, run_effort + effort, datetime));
+            }
+            (_, effort_change) => {
+                if let Some((run_goal_id, run_effort, run_datetime)) = current_run.take() {
+                    compacted.extend(GoalEvent::from_effort_change(
+                        run_goal_id,
+                        run_effort,
+                        run_datetime,
+                    ));
+                }
+
+                if let Some((goal_id, effort, datetime)) = effort_change {
+                    // A run that cancelled out can leave two runs on the same goal adjacent
+                    let previous_effort = match compacted.last().and_then(GoalEvent::effort_change)
+                    {
+                        Some((previous_goal_id, previous_effort, _))
+                            if previous_goal_id == goal_id =>
+                        {
+                            compacted.pop();
+                            previous_effort
+                        }
+                        _ => 0,
+                    };
+
+                    current_run = Some((goal_id, previous_effort + effort, datetime));
+                } else {
+                    compacted.push(event);
+                }
+            }
+        }
+    }
+
+    if let Some((run_goal_id, run_effort, run_datetime)) = current_run {
+        compacted.extend(GoalEvent::from_effort_change(
+            run_goal_id,
+            run_effort,
+            run_datetime,
+        ));
+    }
+
+    compacted
+}
+
+/// A [GoalEvent](GoalEvent) paired with the time it occurred at.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TimedGoalEvent {
+    pub at: DateTime<Utc>,
+    pub event: GoalEvent,
+}
+
+/// Interleave two histories, each ordered by time, into a single history ordered
+/// by time. Events from each history keep their relative order, and events at the
+/// same time are ordered with those from `a` first. An event in `b` that is identical
+/// to an event in `a` at the same time is treated as the same event and only kept once.
+pub fn merge_histories(a: Vec<TimedGoalEvent>, b: Vec<TimedGoalEvent>) -> Vec<TimedGoalEvent> {
+    let mut merged = Vec::with_capacity(a.len() + b.len());
+
+    let mut a = a.into_iter().peekable();
+    let mut b = b.into_iter().peekable();
+
+    loop {
+        let at = match (a.peek(), b.peek()) {
+            (Some(a_event), Some(b_event)) => a_event.at.min(b_event.at),
+            (Some(a_event), None) => a_event.at,
+            (None, Some(b_event)) => b_event.at,
+            (None, None) => break,
+        };
+
+        let mut a_events_at: Vec<TimedGoalEvent> = vec![];
+        while let Some(a_event) = a.next_if(|a_event| a_event.at == at) {
+            a_events_at.push(a_event);
+        }
+
+        let mut unmatched_a_events_at: Vec<&TimedGoalEvent> = a_events_at.iter().collect();
+        let mut b_events_at: Vec<TimedGoalEvent> = vec![];
+        while let Some(b_event) = b.next_if(|b_event| b_event.at == at) {
+            if let Some(index) = unmatched_a_events_at
+                .iter()
+                .position(|a_event| **a_event == b_event)
+            {
+                unmatched_a_events_at.remove(index);
+            } else {
+                b_events_at.push(b_event);
+            }
+        }
+
+        merged.extend(a_events_at);
+        merged.extend(b_events_at);
+    }
+
+    merged
 }
 
 #[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct GoalId(pub u32);
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl std::fmt::Display for GoalId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// How important a goal is, independent of its effort. Priorities are ordered from
+/// lowest to highest.
+#[derive(
+    Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default,
+)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Goal {
     name: String,
     effort_to_date: u32,
     effort_to_complete: u32,
     children: Vec<GoalId>,
+    #[serde(default)]
+    tags: HashSet<String>,
+    #[serde(default)]
+    effort_from_parent: u32,
+    #[serde(default)]
+    last_effort_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    deadline: Option<DateTime<Utc>>,
+    #[serde(default)]
+    priority: Priority,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct PopulatedGoal {
     pub id: GoalId,
     #[serde(rename = "parentGoalId")]
@@ -85,16 +422,101 @@ pub struct PopulatedGoal {
     #[serde(rename = "maxChildLayerDepth")]
     pub max_child_depth: usize,
     pub children: Vec<PopulatedGoal>,
+    #[serde(default)]
+    pub selected: bool,
+    /// Whether the children of this goal are hidden. Children are still populated so
+    /// that summaries over the tree remain accurate.
+    #[serde(default)]
+    pub collapsed: bool,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub deadline: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub focused: bool,
+    #[serde(default)]
+    pub priority: Priority,
 }
 
-#[derive(thiserror::Error, Debug)]
+impl PopulatedGoal {
+    /// Flatten the goal tree into a list of goals paired with their depth, where
+    /// the root is at depth 0. Goals are listed in pre-order with children in the
+    /// same order as they are rendered, so the index of a goal in the list can be
+    /// mapped back to its goal child index path.
+    pub fn flatten(&self) -> Vec<(usize, &PopulatedGoal)> {
+        let mut paths: Vec<GoalChildIndexPath> = vec![vec![]];
+
+        visit_populated_goal_children(
+            self,
+            &mut |_, _, child_path, _| {
+                paths.push(child_path.clone());
+            },
+            (),
+        );
+
+        // Lexicographic ordering of index paths is exactly pre-order
+        paths.sort();
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let goal = traverse_populated_goal_children(self, &path)
+                    .expect("visited paths to be valid");
+                (path.len(), goal)
+            })
+            .collect()
+    }
+
+    /// Find the goal with the given id in this goal's child tree, including the goal
+    /// itself, along with the goal child index path to it from this goal.
+    pub fn find(&self, id: GoalId) -> Option<(&PopulatedGoal, GoalChildIndexPath)> {
+        if self.id == id {
+            return Some((self, vec![]));
+        }
+
+        let mut found_path: Option<GoalChildIndexPath> = None;
+
+        visit_populated_goal_children(
+            self,
+            &mut |_, _, child_path, child_goal| {
+                if found_path.is_none() && child_goal.id == id {
+                    found_path = Some(child_path.clone());
+                }
+            },
+            (),
+        );
+
+        found_path.map(|path| {
+            let goal =
+                traverse_populated_goal_children(self, &path).expect("visited path to be valid");
+            (goal, path)
+        })
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
 pub enum GoalOperationError {
-    #[error("adding goal `{1:?}` to `{0}` failed as it `{1:?}` is already a child of `{0}`")]
+    #[error("adding goal {1} to `{0}` failed as it is already a child of `{0}`")]
     CannotHaveDuplicateChildren(String, GoalId),
-    #[error("no child with id `{1:?}` on goal `{0}`")]
+    #[error("no child with id {1} on goal `{0}`")]
     NoSuchChild(String, GoalId),
 }
 
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("goal name `{0}` is empty or only whitespace")]
+pub struct InvalidNameError(pub String);
+
+/// Check that a name can be given to a goal. Names that are empty or only whitespace
+/// make goals impossible to see, so every [Profile](crate::profile::Profile) method
+/// that names a goal rejects them.
+pub fn check_goal_name(name: &str) -> Result<(), InvalidNameError> {
+    if name.trim().is_empty() {
+        Err(InvalidNameError(name.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
 impl Goal {
     pub fn new<S: Into<String>>(name: S, effort_to_complete: u32) -> Goal {
         Goal {
@@ -102,6 +524,12 @@ impl Goal {
             effort_to_date: 0,
             effort_to_complete,
             children: Vec::new(),
+            tags: HashSet::new(),
+            effort_from_parent: 0,
+            last_effort_at: None,
+            notes: None,
+            deadline: None,
+            priority: Priority::default(),
         }
     }
 
@@ -115,6 +543,52 @@ impl Goal {
         old_name
     }
 
+    /// Free-text description of the goal, beyond its one-line name.
+    pub fn notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
+
+    /// Set the notes of the goal, returning the previous notes.
+    pub fn set_notes(&mut self, notes: Option<String>) -> Option<String> {
+        std::mem::replace(&mut self.notes, notes)
+    }
+
+    /// Soft deadline of the goal, used for sorting and overdue detection without
+    /// needing an [Event](crate::event::Event).
+    pub fn deadline(&self) -> Option<DateTime<Utc>> {
+        self.deadline
+    }
+
+    /// Set the deadline of the goal, returning the previous deadline.
+    pub fn set_deadline(&mut self, deadline: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+        std::mem::replace(&mut self.deadline, deadline)
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Set the priority of the goal, returning the previous priority.
+    pub fn set_priority(&mut self, priority: Priority) -> Priority {
+        std::mem::replace(&mut self.priority, priority)
+    }
+
+    pub fn tags(&self) -> &HashSet<String> {
+        &self.tags
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    pub fn add_tag<S: Into<String>>(&mut self, tag: S) -> bool {
+        self.tags.insert(tag.into())
+    }
+
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        self.tags.remove(tag)
+    }
+
     pub fn add_effort(&mut self, effort: u32) {
         self.effort_to_date += effort
     }
@@ -123,6 +597,11 @@ impl Goal {
         self.effort_to_date = self.effort_to_date.saturating_sub(effort);
     }
 
+    /// Set the effort to date of the goal, returning the previous effort to date.
+    pub fn set_effort_to_date(&mut self, effort: u32) -> u32 {
+        std::mem::replace(&mut self.effort_to_date, effort)
+    }
+
     pub fn rescope(&mut self, new_effort: u32) {
         self.effort_to_complete = new_effort
     }
@@ -132,17 +611,24 @@ impl Goal {
         self.effort_to_complete = self.effort_to_date;
     }
 
-    pub fn refine(&mut self, child: GoalId, effort_removed: u32) -> Result<(), GoalOperationError> {
-        self.effort_to_complete = self.effort_to_complete.saturating_sub(effort_removed);
+    /// Adds `child` as a child of this goal, removing up to `effort_removed` from the
+    /// effort to complete. Returns the effort that was actually removed.
+    pub fn refine(
+        &mut self,
+        child: GoalId,
+        effort_removed: u32,
+    ) -> Result<u32, GoalOperationError> {
         if self.children.contains(&child) {
             return Err(GoalOperationError::CannotHaveDuplicateChildren(
                 self.name.clone(),
                 child,
             ));
         }
+        let effort_removed = effort_removed.min(self.effort_to_complete);
+        self.effort_to_complete -= effort_removed;
         self.children.push(child);
 
-        Ok(())
+        Ok(effort_removed)
     }
 
     pub fn remove_child(&mut self, child: GoalId) -> bool {
@@ -158,6 +644,30 @@ impl Goal {
         }
     }
 
+    pub(crate) fn remap_children(&mut self, id_mapping: &HashMap<GoalId, GoalId>) {
+        self.children = self
+            .children
+            .iter()
+            .filter_map(|child| id_mapping.get(child).copied())
+            .collect();
+    }
+
+    pub(crate) fn add_child(&mut self, child: GoalId) -> Result<(), GoalOperationError> {
+        if self.children.contains(&child) {
+            return Err(GoalOperationError::CannotHaveDuplicateChildren(
+                self.name.clone(),
+                child,
+            ));
+        }
+        self.children.push(child);
+
+        Ok(())
+    }
+
+    pub(crate) fn retain_children<P: FnMut(&GoalId) -> bool>(&mut self, predicate: P) {
+        self.children.retain(predicate);
+    }
+
     pub fn finished(&self) -> bool {
         self.effort_to_date >= self.effort_to_complete
     }
@@ -174,6 +684,31 @@ impl Goal {
         self.effort_to_date
     }
 
+    /// The effort left before the goal is finished, zero for finished goals.
+    pub fn effort_remaining(&self) -> u32 {
+        self.effort_to_complete.saturating_sub(self.effort_to_date)
+    }
+
+    /// The effort that was removed from this goal's parent when it was refined into
+    /// this goal, or zero for goals that were not created by refinement.
+    pub fn effort_from_parent(&self) -> u32 {
+        self.effort_from_parent
+    }
+
+    /// When effort was last added to or removed from this goal through a request, if
+    /// it ever was.
+    pub fn last_effort_at(&self) -> Option<DateTime<Utc>> {
+        self.last_effort_at
+    }
+
+    pub(crate) fn set_last_effort_at(&mut self, datetime: DateTime<Utc>) {
+        self.last_effort_at = Some(datetime);
+    }
+
+    pub(crate) fn set_effort_from_parent(&mut self, effort_from_parent: u32) {
+        self.effort_from_parent = effort_from_parent;
+    }
+
     pub fn children(&self) -> &Vec<GoalId> {
         &self.children
     }
@@ -196,3 +731,209 @@ impl Goal {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    use crate::{
+        goal::{compact_effort_events, merge_histories, Goal, GoalEvent, GoalId, TimedGoalEvent},
+        profile::Profile,
+    };
+
+    fn effort_events(history: &[GoalEvent]) -> Vec<(GoalId, i64)> {
+        history
+            .iter()
+            .map(|event| {
+                let (goal_id, effort, _) = event.effort_change().expect("only effort events");
+                (goal_id, effort)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compact_interleaved_effort_events() {
+        let (a, b) = (GoalId(0), GoalId(1));
+
+        let history = vec![
+            GoalEvent::AddEffort {
+                goal_id: a,
+                effort: 1,
+                datetime: DateTime::default(),
+            },
+            GoalEvent::AddEffort {
+                goal_id: a,
+                effort: 2,
+                datetime: DateTime::default(),
+            },
+            GoalEvent::RemoveEffort {
+                goal_id: a,
+                effort: 1,
+                datetime: DateTime::default(),
+            },
+            GoalEvent::AddEffort {
+                goal_id: b,
+                effort: 1,
+                datetime: DateTime::default(),
+            },
+            GoalEvent::RemoveEffort {
+                goal_id: b,
+                effort: 4,
+                datetime: DateTime::default(),
+            },
+            GoalEvent::AddEffort {
+                goal_id: a,
+                effort: 1,
+                datetime: DateTime::default(),
+            },
+            GoalEvent::AddEffort {
+                goal_id: b,
+                effort: 1,
+                datetime: DateTime::default(),
+            },
+            GoalEvent::RemoveEffort {
+                goal_id: b,
+                effort: 1,
+                datetime: DateTime::default(),
+            },
+            GoalEvent::AddEffort {
+                goal_id: a,
+                effort: 5,
+                datetime: DateTime::default(),
+            },
+        ];
+
+        assert_eq!(
+            effort_events(&compact_effort_events(history)),
+            vec![(a, 2), (b, -3), (a, 6)]
+        );
+    }
+
+    #[test]
+    fn compact_effort_events_across_other_events() {
+        let a = GoalId(0);
+
+        let history = vec![
+            GoalEvent::AddEffort {
+                goal_id: a,
+                effort: 1,
+                datetime: DateTime::default(),
+            },
+            GoalEvent::FocusSingle(a),
+            GoalEvent::AddEffort {
+                goal_id: a,
+                effort: 1,
+                datetime: DateTime::default(),
+            },
+            GoalEvent::AddEffort {
+                goal_id: a,
+                effort: 1,
+                datetime: DateTime::default(),
+            },
+        ];
+
+        let compacted = compact_effort_events(history);
+
+        assert_eq!(compacted.len(), 3);
+        assert!(matches!(compacted[1], GoalEvent::FocusSingle(goal_id) if goal_id == a));
+        assert_eq!(effort_events(&compacted[..1]), vec![(a, 1)]);
+        assert_eq!(effort_events(&compacted[2..]), vec![(a, 2)]);
+    }
+
+    #[test]
+    fn flatten_populated_goal() {
+        let mut profile = Profile::default();
+
+        let root_id = profile.add_goal(Goal::new("root", 0)).unwrap();
+        let first_child_id = profile
+            .refine_goal(Goal::new("first child", 0), root_id, 0)
+            .unwrap();
+        let first_grandchild_id = profile
+            .refine_goal(Goal::new("first grandchild", 0), first_child_id, 0)
+            .unwrap();
+        let second_child_id = profile
+            .refine_goal(Goal::new("second child", 0), root_id, 0)
+            .unwrap();
+
+        let populated_goals = profile.populate_goals();
+        let flattened: Vec<_> = populated_goals[0]
+            .flatten()
+            .into_iter()
+            .map(|(depth, goal)| (depth, goal.id))
+            .collect();
+
+        assert_eq!(
+            flattened,
+            vec![
+                (0, root_id),
+                (1, first_child_id),
+                (2, first_grandchild_id),
+                (1, second_child_id)
+            ]
+        );
+    }
+
+    #[test]
+    fn find_in_populated_goal() {
+        let mut profile = Profile::default();
+
+        let root_id = profile.add_goal(Goal::new("root", 0)).unwrap();
+        let first_child_id = profile
+            .refine_goal(Goal::new("first child", 0), root_id, 0)
+            .unwrap();
+        let second_child_id = profile
+            .refine_goal(Goal::new("second child", 0), root_id, 0)
+            .unwrap();
+        let grandchild_id = profile
+            .refine_goal(Goal::new("grandchild", 0), second_child_id, 0)
+            .unwrap();
+        let other_root_id = profile.add_goal(Goal::new("other root", 0)).unwrap();
+
+        let populated_goals = profile.populate_goals();
+        let root = populated_goals
+            .iter()
+            .find(|goal| goal.id == root_id)
+            .unwrap();
+
+        let find_path = |id| root.find(id).map(|(goal, path)| (goal.id, path));
+
+        assert_eq!(find_path(root_id), Some((root_id, vec![])));
+        assert_eq!(find_path(first_child_id), Some((first_child_id, vec![0])));
+        assert_eq!(find_path(grandchild_id), Some((grandchild_id, vec![1, 0])));
+        assert_eq!(find_path(other_root_id), None);
+    }
+
+    #[test]
+    fn merge_timed_histories() {
+        let start = Utc.with_ymd_and_hms(2022, 1, 1, 1, 0, 0).unwrap();
+        let timed_event = |minutes: i64, goal_id: u32| TimedGoalEvent {
+            at: start + Duration::minutes(minutes),
+            event: GoalEvent::Add {
+                goal_id: GoalId(goal_id),
+                name: String::new(),
+                effort_to_complete: 0,
+            },
+        };
+
+        let a = vec![timed_event(0, 0), timed_event(2, 1), timed_event(2, 2)];
+        let b = vec![
+            timed_event(0, 0),
+            timed_event(1, 3),
+            timed_event(2, 2),
+            timed_event(2, 4),
+            timed_event(3, 5),
+        ];
+
+        assert_eq!(
+            merge_histories(a, b),
+            vec![
+                timed_event(0, 0),
+                timed_event(1, 3),
+                timed_event(2, 1),
+                timed_event(2, 2),
+                timed_event(2, 4),
+                timed_event(3, 5),
+            ]
+        );
+    }
+}
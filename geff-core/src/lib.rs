@@ -6,4 +6,8 @@ pub mod query;
 pub mod request;
 
 pub use chrono::DateTime;
+pub use chrono::Duration;
+pub use chrono::FixedOffset;
+pub use chrono::NaiveTime;
+pub use chrono::TimeZone;
 pub use chrono::Utc;
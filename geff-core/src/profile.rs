@@ -1,18 +1,270 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{HashMap, HashSet, VecDeque},
+};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     event::{Event, EventId},
-    goal::{Goal, GoalId, PopulatedGoal},
-    query::TimeOfDayConfiguration,
+    goal::{
+        check_goal_name, Goal, GoalEvent, GoalId, GoalRelationship, InvalidNameError,
+        PopulatedGoal, Priority,
+    },
+    query::{event_query_helpers, ActiveGoalsCache, TimeOfDayConfiguration},
 };
 
 pub struct ProfileAndDateTime<'a>(pub &'a mut Profile, pub DateTime<Utc>);
 
+pub mod goal_predicates;
 pub mod goal_traversal;
-use goal_traversal::{get_root_goals, populate_goal_tree, visit_tree_with_predicate};
+pub mod validation;
+use goal_traversal::{
+    get_goal_parent_id, get_root_goals, populate_goal_tree, populate_goals_with_focus,
+    populate_partitioned_goal_tree, visit_goal_child_tree, visit_tree_with_predicate,
+    PartitionedPopulatedTree,
+};
+use validation::ProfileInconsistency;
+
+/// How effort is accounted for when refining a goal into a child goal.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RefineMode {
+    /// The effort removed from the parent and the effort to complete the child are
+    /// independent, allowing a refinement to rescope the work involved.
+    #[default]
+    Loose,
+    /// The effort removed from the parent must be equal to the effort to complete the
+    /// child, so that the total effort of a goal tree is conserved by refinement.
+    Strict,
+}
+
+/// Order to display sibling goals in, see [populate_goals_sorted](Profile::populate_goals_sorted).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// Least complete goals first, by the ratio of effort to date to effort to complete.
+    CompletionRatio,
+    /// Goals with the least effort remaining first.
+    RemainingEffort,
+    Name,
+    /// Goals with the earliest deadline first, followed by goals without a deadline.
+    Deadline,
+}
+
+impl SortKey {
+    fn compare(&self, a: &PopulatedGoal, b: &PopulatedGoal) -> std::cmp::Ordering {
+        match self {
+            SortKey::CompletionRatio => {
+                // Goals with nothing to complete are treated as fully complete
+                let ratio = |goal: &PopulatedGoal| {
+                    if goal.effort_to_complete == 0 {
+                        (1, 1)
+                    } else {
+                        (
+                            u64::from(goal.effort_to_date.min(goal.effort_to_complete)),
+                            u64::from(goal.effort_to_complete),
+                        )
+                    }
+                };
+                let ((a_done, a_total), (b_done, b_total)) = (ratio(a), ratio(b));
+
+                (a_done * b_total).cmp(&(b_done * a_total))
+            }
+            SortKey::RemainingEffort => {
+                let remaining = |goal: &PopulatedGoal| {
+                    goal.effort_to_complete.saturating_sub(goal.effort_to_date)
+                };
+
+                remaining(a).cmp(&remaining(b))
+            }
+            SortKey::Name => a.name.cmp(&b.name),
+            SortKey::Deadline => match (a.deadline, b.deadline) {
+                (Some(a_deadline), Some(b_deadline)) => a_deadline.cmp(&b_deadline),
+                (a_deadline, b_deadline) => b_deadline.is_some().cmp(&a_deadline.is_some()),
+            },
+        }
+    }
+
+    fn sort(&self, goals: &mut [PopulatedGoal]) {
+        let mut needs_sorting: Vec<&mut [PopulatedGoal]> = vec![goals];
+
+        while let Some(siblings) = needs_sorting.pop() {
+            siblings.sort_by(|a, b| self.compare(a, b).then(a.id.cmp(&b.id)));
+            needs_sorting.extend(siblings.iter_mut().map(|goal| goal.children.as_mut_slice()));
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum RefineError {
+    #[error("no parent goal with id {0}")]
+    NoSuchParent(GoalId),
+    #[error("refining would remove {parent_effort_removed} effort from the parent but it only has {parent_effort_to_complete} effort to complete")]
+    EffortExceedsParent {
+        parent_effort_removed: u32,
+        parent_effort_to_complete: u32,
+    },
+    #[error("refining would remove {parent_effort_removed} effort from the parent but the child requires {child_effort_to_complete} effort")]
+    EffortNotConserved {
+        parent_effort_removed: u32,
+        child_effort_to_complete: u32,
+    },
+    #[error(transparent)]
+    InvalidName(#[from] InvalidNameError),
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    #[error("no goal with id {0}")]
+    NoSuchGoal(GoalId),
+    #[error("cannot merge goal {0} into itself")]
+    MergeIntoSelf(GoalId),
+    #[error("cannot merge goal {source_goal_id} into its descendant {target_goal_id}")]
+    MergeIntoDescendant {
+        source_goal_id: GoalId,
+        target_goal_id: GoalId,
+    },
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum SplitError {
+    #[error("no goal with id {0}")]
+    NoSuchGoal(GoalId),
+    #[error("splitting would take {split_effort} effort from the goal but it only has {effort_remaining} effort remaining")]
+    EffortExceedsRemaining {
+        split_effort: u32,
+        effort_remaining: u32,
+    },
+    #[error(transparent)]
+    InvalidName(#[from] InvalidNameError),
+}
+
+/// A refine that would succeed but may surprise the user, see
+/// [refine_warning](Profile::refine_warning).
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum RefineWarning {
+    #[error("the parent already has {effort_to_date} effort recorded, which stays with the parent rather than moving to the child")]
+    ParentHasEffort {
+        effort_to_date: u32,
+        /// Whether the effort recorded on the parent will be enough to finish it once
+        /// the effort is removed from it.
+        finishes_parent: bool,
+    },
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    #[error("imported JSON is malformed: {0}")]
+    Malformed(String),
+    #[error("no parent goal with id {0}")]
+    NoSuchParent(GoalId),
+    #[error(transparent)]
+    InvalidName(#[from] InvalidNameError),
+    #[error("imported profile is inconsistent: {}", display_inconsistencies(.0))]
+    Inconsistent(Vec<ProfileInconsistency>),
+}
+
+fn display_inconsistencies(inconsistencies: &[ProfileInconsistency]) -> String {
+    inconsistencies
+        .iter()
+        .map(ProfileInconsistency::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// The goals that differ between two profiles, each sorted by id.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ProfileDiff {
+    /// Goals only in the other profile.
+    pub added: Vec<GoalId>,
+    /// Goals only in this profile.
+    pub removed: Vec<GoalId>,
+    /// Goals in both profiles whose name, effort or children differ.
+    pub modified: Vec<GoalId>,
+}
+
+impl ProfileDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// One line per changed goal, `+` for added, `-` for removed and `~` for modified.
+impl std::fmt::Display for ProfileDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (marker, goal_ids) in [
+            ("+", &self.added),
+            ("-", &self.removed),
+            ("~", &self.modified),
+        ] {
+            for goal_id in goal_ids {
+                writeln!(f, "{marker} {}", goal_id.0)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TimelineEntryKind {
+    Start,
+    WorkSession,
+    End,
+}
+
+/// An event related to a goal, placed on the goal's timeline.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct TimelineEntry {
+    #[serde(rename = "eventId")]
+    pub event_id: EventId,
+    pub kind: TimelineEntryKind,
+    /// When the event starts.
+    pub datetime: DateTime<Utc>,
+}
+
+/// Summary statistics over every goal in a profile.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProfileStats {
+    #[serde(rename = "goalCount")]
+    pub goal_count: usize,
+    #[serde(rename = "finishedCount")]
+    pub finished_count: usize,
+    #[serde(rename = "unfinishedCount")]
+    pub unfinished_count: usize,
+    #[serde(rename = "totalEffortToDate")]
+    pub total_effort_to_date: u64,
+    #[serde(rename = "totalEffortRemaining")]
+    pub total_effort_remaining: u64,
+    /// The number of goals on the longest path from a root goal to a leaf goal.
+    #[serde(rename = "maxTreeDepth")]
+    pub max_tree_depth: usize,
+    #[serde(rename = "focusedCount")]
+    pub focused_count: usize,
+}
+
+/// Summary of how a goal's effort has been divided among its children.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct EffortReport {
+    pub goal_id: GoalId,
+    /// The effort to complete of the goal before any of its children were refined
+    /// out of it.
+    pub original_effort_to_complete: u32,
+    pub current_effort_to_complete: u32,
+    /// The total effort removed from the goal by refining it into its children.
+    pub delegated_to_children: u32,
+    /// The total effort to complete of the goal's children.
+    pub child_scope: u32,
+}
+
+impl EffortReport {
+    /// Whether the children of the goal are scoped to exactly the effort that was
+    /// delegated to them.
+    pub fn balanced(&self) -> bool {
+        self.delegated_to_children == self.child_scope
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct Profile {
@@ -22,24 +274,51 @@ pub struct Profile {
     pub(crate) goals: HashMap<GoalId, Goal>,
     pub(crate) events: HashMap<EventId, Event>,
     pub(crate) time_of_day_config: TimeOfDayConfiguration,
+    #[serde(skip)]
+    pub(crate) active_goals_cache: RefCell<Option<ActiveGoalsCache>>,
+    #[serde(default)]
+    refine_mode: RefineMode,
+    #[serde(default)]
+    collapsed: HashSet<GoalId>,
+    /// Events that have already had the effort of their relationships added to goals.
+    #[serde(default)]
+    pub(crate) effort_applied_events: HashSet<EventId>,
+    /// The label frontends display effort in, such as "hrs" or "points".
+    #[serde(default)]
+    effort_unit: String,
 }
 
 impl Profile {
+    /// Must be called by anything that can change which goals are active.
+    pub(crate) fn invalidate_active_goals_cache(&mut self) {
+        *self.active_goals_cache.get_mut() = None;
+    }
+
     pub fn time_of_day_config(&self) -> &TimeOfDayConfiguration {
         &self.time_of_day_config
     }
 
-    pub fn set_time_of_day_config(&mut self, config: TimeOfDayConfiguration) {
-        self.time_of_day_config = config;
+    pub fn set_time_of_day_config(
+        &mut self,
+        config: TimeOfDayConfiguration,
+    ) -> TimeOfDayConfiguration {
+        self.invalidate_active_goals_cache();
+        std::mem::replace(&mut self.time_of_day_config, config)
+    }
+
+    pub fn effort_unit(&self) -> &str {
+        &self.effort_unit
+    }
+
+    /// Set the label effort is displayed in, returning the previous label.
+    pub fn set_effort_unit(&mut self, effort_unit: String) -> String {
+        std::mem::replace(&mut self.effort_unit, effort_unit)
     }
 
+    /// Focus a goal without its children. Returns false if the goal does not exist or
+    /// was already focused.
     pub fn focus_single_goal(&mut self, id: GoalId) -> bool {
-        if self.goals.contains_key(&id) {
-            self.focused_goals.insert(id);
-            true
-        } else {
-            false
-        }
+        self.goals.contains_key(&id) && self.focused_goals.insert(id)
     }
 
     pub fn unfocus_single_goal(&mut self, id: GoalId) -> bool {
@@ -51,17 +330,53 @@ impl Profile {
         }
     }
 
-    pub fn add_goal(&mut self, goal: Goal) -> GoalId {
-        let goal_id = GoalId(self.goal_id_count);
-        self.goal_id_count += 1;
+    /// Focus every goal, returning the goals that were not already focused.
+    pub fn focus_all(&mut self) -> HashSet<GoalId> {
+        let newly_focused: HashSet<GoalId> = self.unfocused_goals();
+        self.focused_goals.extend(newly_focused.iter().copied());
 
-        if self.goals.insert(goal_id, goal).is_some() {
-            panic!("not to have a goal id conflict due to monotonic counter");
+        newly_focused
+    }
+
+    /// Unfocus every goal, returning the goals that were focused.
+    pub fn unfocus_all(&mut self) -> HashSet<GoalId> {
+        std::mem::take(&mut self.focused_goals)
+    }
+
+    /// Take the next unused goal id from the counter. Ids already in use are skipped so
+    /// that a counter behind the existing goals, such as from a hand-edited profile,
+    /// can't cause a conflict.
+    fn next_goal_id(&mut self) -> GoalId {
+        while self.goals.contains_key(&GoalId(self.goal_id_count)) {
+            self.goal_id_count = self.goal_id_count.wrapping_add(1);
         }
 
+        let goal_id = GoalId(self.goal_id_count);
+        self.goal_id_count = self.goal_id_count.wrapping_add(1);
         goal_id
     }
 
+    /// Take the next unused event id from the counter, see
+    /// [next_goal_id](Profile::next_goal_id).
+    fn next_event_id(&mut self) -> EventId {
+        while self.events.contains_key(&EventId(self.event_id_count)) {
+            self.event_id_count = self.event_id_count.wrapping_add(1);
+        }
+
+        let event_id = EventId(self.event_id_count);
+        self.event_id_count = self.event_id_count.wrapping_add(1);
+        event_id
+    }
+
+    pub fn add_goal(&mut self, goal: Goal) -> Result<GoalId, InvalidNameError> {
+        check_goal_name(goal.name())?;
+        self.invalidate_active_goals_cache();
+        let goal_id = self.next_goal_id();
+        self.goals.insert(goal_id, goal);
+
+        Ok(goal_id)
+    }
+
     pub fn focus_goal(&mut self, goal_id: GoalId) -> Option<HashSet<GoalId>> {
         visit_tree_with_predicate(&self.goals, goal_id, &mut |child_id, _| -> bool {
             !self.focused_goals.contains(&child_id)
@@ -73,6 +388,12 @@ impl Profile {
             }
 
             child_ids_need_focusing.insert(goal_id);
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                ?goal_id,
+                focused_count = child_ids_need_focusing.len(),
+                "focused goal"
+            );
             child_ids_need_focusing
         })
     }
@@ -97,6 +418,7 @@ impl Profile {
     }
 
     pub fn rescope_goal(&mut self, goal_id: GoalId, new_effort_to_complete: u32) -> Option<u32> {
+        self.invalidate_active_goals_cache();
         if let Some(goal) = self.goals.get_mut(&goal_id) {
             let original_effort_to_complete = goal.effort_to_complete();
             goal.rescope(new_effort_to_complete);
@@ -106,204 +428,2390 @@ impl Profile {
         }
     }
 
-    pub fn rename_goal<S: Into<String>>(&mut self, goal_id: GoalId, new_name: S) -> Option<String> {
+    /// Rescope a goal like [rescope_goal](Profile::rescope_goal), also clamping its
+    /// effort to date down to the new effort to complete. Returns the original effort
+    /// to complete and the effort to date that was removed by clamping.
+    pub fn rescope_goal_clamped(
+        &mut self,
+        goal_id: GoalId,
+        new_effort_to_complete: u32,
+    ) -> Option<(u32, u32)> {
+        self.invalidate_active_goals_cache();
+        let goal = self.goals.get_mut(&goal_id)?;
+
+        let original_effort_to_complete = goal.effort_to_complete();
+        let clamped_effort = goal.effort_to_date().saturating_sub(new_effort_to_complete);
+        goal.rescope(new_effort_to_complete);
+        goal.remove_effort(clamped_effort);
+
+        Some((original_effort_to_complete, clamped_effort))
+    }
+
+    /// Rename a goal, returning its previous name if the goal exists.
+    pub fn rename_goal<S: Into<String>>(
+        &mut self,
+        goal_id: GoalId,
+        new_name: S,
+    ) -> Result<Option<String>, InvalidNameError> {
+        let new_name = new_name.into();
+        check_goal_name(&new_name)?;
+
+        Ok(self
+            .goals
+            .get_mut(&goal_id)
+            .map(|goal| goal.rename(new_name)))
+    }
+
+    /// Set the notes of a goal, returning the previous notes if the goal exists.
+    pub fn set_goal_notes(
+        &mut self,
+        goal_id: GoalId,
+        notes: Option<String>,
+    ) -> Option<Option<String>> {
         self.goals
             .get_mut(&goal_id)
-            .map(|goal| goal.rename(new_name))
+            .map(|goal| goal.set_notes(notes))
     }
 
-    pub fn refine_goal(
+    /// Set the deadline of a goal, returning the previous deadline if the goal exists.
+    pub fn set_goal_deadline(
         &mut self,
-        child_goal: Goal,
-        parent_goal_id: GoalId,
-        parent_effort_removed: u32,
-    ) -> Option<GoalId> {
-        let Some(parent_goal) = self.goals.get_mut(&parent_goal_id) else {
-            return None;
-        };
+        goal_id: GoalId,
+        deadline: Option<DateTime<Utc>>,
+    ) -> Option<Option<DateTime<Utc>>> {
+        self.goals
+            .get_mut(&goal_id)
+            .map(|goal| goal.set_deadline(deadline))
+    }
 
-        let child_goal_id = GoalId(self.goal_id_count);
-        self.goal_id_count += 1;
+    /// Move the deadline of a goal and every descendant that has one by `by`, which
+    /// pulls deadlines earlier if it is negative. Deadlines that would overflow are left
+    /// as they are. Returns the ids of the goals whose deadline changed, sorted.
+    pub fn shift_deadlines(&mut self, goal_id: GoalId, by: Duration) -> Vec<GoalId> {
+        let Some(descendants) =
+            visit_goal_child_tree(&self.goals, goal_id, &mut |_, _, _, _| {}, ())
+        else {
+            return vec![];
+        };
 
-        parent_goal
-            .refine(child_goal_id, parent_effort_removed)
-            .expect("newly generated goal id to be unique by definition");
+        let mut shifted_goal_ids = vec![];
+        for goal_id in std::iter::once(goal_id).chain(descendants) {
+            let goal = self
+                .goals
+                .get_mut(&goal_id)
+                .expect("subtree goals to be in the profile");
+            let Some(deadline) = goal.deadline() else {
+                continue;
+            };
 
-        if self.goals.insert(child_goal_id, child_goal).is_some() {
-            panic!("not to have a goal id conflict due to monotonic counter");
+            match deadline.checked_add_signed(by) {
+                Some(shifted_deadline) if shifted_deadline != deadline => {
+                    goal.set_deadline(Some(shifted_deadline));
+                    shifted_goal_ids.push(goal_id);
+                }
+                _ => {}
+            }
         }
+        shifted_goal_ids.sort();
 
-        Some(child_goal_id)
+        shifted_goal_ids
     }
 
-    fn remove_goals_from_event_relationships(&mut self, goal_ids: &HashSet<GoalId>) {
-        for event in &mut self.events.values_mut() {
-            event.goal_relationships_mut().retain(|goal| match goal {
-                crate::goal::GoalRelationship::Requires(id) => goal_ids.contains(id),
-                crate::goal::GoalRelationship::Ends(id) => goal_ids.contains(id),
-                crate::goal::GoalRelationship::WorksOn(id) => goal_ids.contains(id),
-                crate::goal::GoalRelationship::Starts(id) => goal_ids.contains(id),
-            })
-        }
+    /// Set the effort to date of a goal, returning the previous effort to date if the
+    /// goal exists.
+    pub fn set_effort(&mut self, goal_id: GoalId, effort: u32) -> Option<u32> {
+        self.invalidate_active_goals_cache();
+        self.goals
+            .get_mut(&goal_id)
+            .map(|goal| goal.set_effort_to_date(effort))
     }
 
-    pub fn remove_goal(&mut self, goal_id: GoalId) -> Option<PopulatedGoal> {
-        if let Some((populated_goal, child_ids_needing_removal)) =
-            populate_goal_tree(&self.goals, goal_id)
-        {
-            self.goals.remove(&goal_id);
-            self.focused_goals.remove(&goal_id);
+    /// Split `total` effort across the goals in `among` in proportion to their
+    /// remaining effort and add it to them, evenly if none of them have effort
+    /// remaining. Whatever rounding leaves over goes to the goal with the most
+    /// remaining effort, so the returned amounts sum to `total` unless none of the
+    /// goals exist. Goals that receive no effort are left out of the returned amounts.
+    pub fn distribute_effort(
+        &mut self,
+        total: u32,
+        among: &HashSet<GoalId>,
+    ) -> HashMap<GoalId, u32> {
+        self.invalidate_active_goals_cache();
+
+        // Weighted by remaining effort
+        let mut weights: Vec<(GoalId, u64)> = among
+            .iter()
+            .filter_map(|goal_id| {
+                self.goals
+                    .get(goal_id)
+                    .map(|goal| (*goal_id, u64::from(goal.effort_remaining())))
+            })
+            .collect();
+        // The goal with the most remaining effort first, taking the lowest id on ties
+        weights.sort_by_key(|(goal_id, weight)| (Reverse(*weight), *goal_id));
 
-            for goal_id in child_ids_needing_removal.iter() {
-                self.goals.remove(goal_id);
-                self.focused_goals.remove(goal_id);
-            }
+        let Some(&(largest_goal_id, _)) = weights.first() else {
+            return HashMap::new();
+        };
 
-            self.remove_goals_from_event_relationships(&child_ids_needing_removal);
-            if let Some(parent_goal_id) = populated_goal.parent_goal_id {
-                if let Some(parent_goal) = self.goals.get_mut(&parent_goal_id) {
-                    parent_goal.remove_child(goal_id);
-                }
+        let mut total_weight: u64 = weights.iter().map(|(_, weight)| weight).sum();
+        if total_weight == 0 {
+            for (_, weight) in weights.iter_mut() {
+                *weight = 1;
             }
-
-            Some(populated_goal)
-        } else {
-            None
+            total_weight = weights.len() as u64;
         }
-    }
 
-    pub fn add_event(&mut self, event: Event) -> EventId {
-        let event_id = EventId(self.event_id_count);
-        self.event_id_count += 1;
-
-        if self.events.insert(event_id, event).is_some() {
-            panic!("not to have an event id conflict due to monotonic counter");
+        let mut amounts: HashMap<GoalId, u32> = weights
+            .iter()
+            .map(|(goal_id, weight)| {
+                // Never more than `total` since `weight` is at most `total_weight`
+                (*goal_id, (u64::from(total) * weight / total_weight) as u32)
+            })
+            .collect();
+        let distributed: u32 = amounts.values().sum();
+        *amounts
+            .get_mut(&largest_goal_id)
+            .expect("largest goal to have an amount") += total - distributed;
+
+        amounts.retain(|_, amount| *amount > 0);
+        for (goal_id, amount) in amounts.iter() {
+            if let Some(goal) = self.goals.get_mut(goal_id) {
+                goal.add_effort(*amount);
+            }
         }
 
-        event_id
+        amounts
     }
 
-    pub fn remove_event(&mut self, event_id: EventId) -> Option<Event> {
-        self.events.remove(&event_id)
+    /// Set the priority of a goal, returning the previous priority if the goal exists.
+    pub fn set_goal_priority(&mut self, goal_id: GoalId, priority: Priority) -> Option<Priority> {
+        self.goals
+            .get_mut(&goal_id)
+            .map(|goal| goal.set_priority(priority))
     }
 
-    pub fn populate_goals(&self) -> Vec<PopulatedGoal> {
-        let root_goal_ids = get_root_goals(&self.goals);
-
-        root_goal_ids
-            .map(|root_goal_id| populate_goal_tree(&self.goals, root_goal_id).unwrap().0)
-            .collect()
+    pub fn tag_goal<S: Into<String>>(&mut self, goal_id: GoalId, tag: S) -> bool {
+        self.goals
+            .get_mut(&goal_id)
+            .is_some_and(|goal| goal.add_tag(tag))
     }
 
-    pub fn with_datetime(&mut self, datetime: DateTime<Utc>) -> ProfileAndDateTime {
-        ProfileAndDateTime(self, datetime)
+    pub fn untag_goal(&mut self, goal_id: GoalId, tag: &str) -> bool {
+        self.goals
+            .get_mut(&goal_id)
+            .is_some_and(|goal| goal.remove_tag(tag))
     }
 
-    pub fn get_event(&self, id: EventId) -> Option<&Event> {
-        self.events.get(&id)
+    pub fn goals_with_tag(&self, tag: &str) -> HashSet<GoalId> {
+        self.goals
+            .iter()
+            .filter(|(_, goal)| goal.has_tag(tag))
+            .map(|(&id, _)| id)
+            .collect()
     }
 
-    pub fn get_event_mut(&mut self, id: EventId) -> Option<&mut Event> {
-        self.events.get_mut(&id)
+    /// Get the goals that `predicate` holds for. Predicates only see the goal itself,
+    /// see [goal_predicates] for building them and for where to find queries that
+    /// depend on events.
+    ///
+    /// ```
+    /// # use geff_core::goal::Goal;
+    /// # use geff_core::profile::Profile;
+    /// use geff_core::profile::goal_predicates::{and, effort_remaining_over, unfinished};
+    ///
+    /// let mut profile = Profile::default();
+    /// let large_goal_id = profile.add_goal(Goal::new("large", 10)).unwrap();
+    /// profile.add_goal(Goal::new("small", 2)).unwrap();
+    /// let finished_goal_id = profile.add_goal(Goal::new("finished", 10)).unwrap();
+    /// profile.set_effort(finished_goal_id, 10);
+    ///
+    /// let goal_ids = profile.goals_matching(and(unfinished(), effort_remaining_over(5)));
+    /// assert_eq!(goal_ids, [large_goal_id].into());
+    /// ```
+    pub fn goals_matching<F: Fn(&Goal) -> bool>(&self, predicate: F) -> HashSet<GoalId> {
+        self.goals
+            .iter()
+            .filter(|(_, goal)| predicate(goal))
+            .map(|(&id, _)| id)
+            .collect()
     }
 
-    pub fn get_goal(&self, id: GoalId) -> Option<&Goal> {
-        self.goals.get(&id)
+    /// Get the goals that have every one of `tags`. Every goal matches an empty list.
+    pub fn goals_with_all_tags(&self, tags: &[&str]) -> HashSet<GoalId> {
+        self.goals
+            .iter()
+            .filter(|(_, goal)| tags.iter().all(|tag| goal.has_tag(tag)))
+            .map(|(&id, _)| id)
+            .collect()
     }
 
-    pub fn get_goal_mut(&mut self, id: GoalId) -> Option<&mut Goal> {
-        self.goals.get_mut(&id)
+    /// Get the goals that have at least one of `tags`. No goal matches an empty list.
+    pub fn goals_with_any_tag(&self, tags: &[&str]) -> HashSet<GoalId> {
+        self.goals
+            .iter()
+            .filter(|(_, goal)| tags.iter().any(|tag| goal.has_tag(tag)))
+            .map(|(&id, _)| id)
+            .collect()
     }
 
-    pub fn focused_goals(&self) -> &HashSet<GoalId> {
-        &self.focused_goals
+    fn find_goals_matching<P: Fn(&Goal) -> bool>(&self, predicate: P) -> Vec<GoalId> {
+        let mut goal_ids: Vec<GoalId> = self
+            .goals
+            .iter()
+            .filter(|(_, goal)| predicate(goal))
+            .map(|(&id, _)| id)
+            .collect();
+        goal_ids.sort();
+        goal_ids
     }
 
-    pub fn unfocused_goals(&self) -> HashSet<GoalId> {
-        self.goal_ids()
-            .difference(&self.focused_goals)
-            .copied()
-            .collect()
+    /// Find the goals with exactly the name `name`, sorted by id.
+    pub fn find_goals_by_name(&self, name: &str) -> Vec<GoalId> {
+        self.find_goals_matching(|goal| goal.name() == name)
     }
 
-    pub fn goal_ids(&self) -> HashSet<GoalId> {
-        self.goals.iter().map(|(&id, _)| id).collect()
+    /// Find the goals with a name starting with `prefix`, sorted by id.
+    pub fn find_goals_by_name_prefix(&self, prefix: &str) -> Vec<GoalId> {
+        self.find_goals_matching(|goal| goal.name().starts_with(prefix))
     }
-}
 
-impl<'a> ProfileAndDateTime<'a> {
-    pub fn get_event(&self, id: EventId) -> Option<&Event> {
-        self.0.events.get(&id)
+    /// Get the unfinished goals with the most effort remaining first, paired with the
+    /// effort they have remaining. Only leaf goals are considered, since the effort of
+    /// a goal with children does not include the work remaining in its children. Ties
+    /// are broken by goal id.
+    pub fn by_remaining_effort(&self) -> Vec<(GoalId, u32)> {
+        let mut remaining_efforts: Vec<(GoalId, u32)> = self
+            .goals
+            .iter()
+            .filter(|(_, goal)| goal.children().is_empty() && goal.unfinished())
+            .map(|(&id, goal)| (id, goal.effort_remaining()))
+            .collect();
+        remaining_efforts.sort_by_key(|&(id, remaining_effort)| (Reverse(remaining_effort), id));
+
+        remaining_efforts
     }
 
-    pub fn get_event_mut(&mut self, id: EventId) -> Option<&mut Event> {
-        self.0.events.get_mut(&id)
+    /// Find unfinished goals that have had effort put into them but no effort logged
+    /// within `idle` of `now`, sorted with the goals that have been dormant longest
+    /// first. Goals whose effort was never logged with a time are considered the most
+    /// dormant.
+    pub fn dormant_goals(&self, idle: Duration, now: DateTime<Utc>) -> Vec<GoalId> {
+        let mut dormant_goals: Vec<(Option<DateTime<Utc>>, GoalId)> = self
+            .goals
+            .iter()
+            .filter(|(_, goal)| goal.unfinished() && goal.effort_to_date() > 0)
+            .filter(|(_, goal)| {
+                goal.last_effort_at()
+                    .is_none_or(|last_effort_at| now - last_effort_at >= idle)
+            })
+            .map(|(&id, goal)| (goal.last_effort_at(), id))
+            .collect();
+        dormant_goals.sort();
+
+        dormant_goals.into_iter().map(|(_, id)| id).collect()
     }
 
-    pub fn get_goal(&self, id: GoalId) -> Option<&Goal> {
-        self.0.goals.get(&id)
+    pub fn refine_mode(&self) -> RefineMode {
+        self.refine_mode
     }
 
-    pub fn get_goal_mut(&mut self, id: GoalId) -> Option<&mut Goal> {
-        self.0.goals.get_mut(&id)
+    pub fn set_refine_mode(&mut self, refine_mode: RefineMode) {
+        self.refine_mode = refine_mode;
     }
-}
 
-#[cfg(test)]
-mod tests {
-    mod goal_query {
-        use std::collections::HashSet;
+    /// Check whether refining a goal with [refine_goal](Profile::refine_goal) would
+    /// succeed without modifying the profile.
+    pub fn check_refine(
+        &self,
+        parent_goal_id: GoalId,
+        parent_effort_removed: u32,
+        child_effort_to_complete: u32,
+    ) -> Result<(), RefineError> {
+        let Some(parent_goal) = self.goals.get(&parent_goal_id) else {
+            return Err(RefineError::NoSuchParent(parent_goal_id));
+        };
 
-        use chrono::{TimeZone, Utc};
+        if parent_effort_removed > parent_goal.effort_to_complete() {
+            return Err(RefineError::EffortExceedsParent {
+                parent_effort_removed,
+                parent_effort_to_complete: parent_goal.effort_to_complete(),
+            });
+        }
 
-        use crate::{goal::Goal, profile::Profile, query::GoalQueryEngine};
+        if self.refine_mode == RefineMode::Strict
+            && child_effort_to_complete != parent_effort_removed
+        {
+            return Err(RefineError::EffortNotConserved {
+                parent_effort_removed,
+                child_effort_to_complete,
+            });
+        }
 
-        #[test]
-        fn goal_finish_status() {
-            let mut profile = Profile::default();
+        Ok(())
+    }
 
-            let datetime = Utc.with_ymd_and_hms(2022, 1, 1, 1, 0, 0).unwrap();
-            let mut profile = profile.with_datetime(datetime);
+    /// Check whether refining a goal would succeed in a way the user may not expect,
+    /// so that frontends can ask for confirmation first. Refining only removes effort
+    /// to complete from the parent, so effort already recorded on the parent stays
+    /// there and can finish the parent outright.
+    pub fn refine_warning(
+        &self,
+        parent_goal_id: GoalId,
+        parent_effort_removed: u32,
+    ) -> Option<RefineWarning> {
+        let parent_goal = self.goals.get(&parent_goal_id)?;
+        let effort_to_date = parent_goal.effort_to_date();
+
+        (effort_to_date > 0).then(|| RefineWarning::ParentHasEffort {
+            effort_to_date,
+            finishes_parent: effort_to_date
+                >= parent_goal
+                    .effort_to_complete()
+                    .saturating_sub(parent_effort_removed),
+        })
+    }
 
-            let goal_id = profile.0.add_goal(Goal::new("test goal", 2));
+    pub fn refine_goal(
+        &mut self,
+        mut child_goal: Goal,
+        parent_goal_id: GoalId,
+        parent_effort_removed: u32,
+    ) -> Result<GoalId, RefineError> {
+        check_goal_name(child_goal.name())?;
+        self.invalidate_active_goals_cache();
+        self.check_refine(
+            parent_goal_id,
+            parent_effort_removed,
+            child_goal.effort_to_complete(),
+        )?;
+        let child_goal_id = self.next_goal_id();
+        let parent_goal = self
+            .goals
+            .get_mut(&parent_goal_id)
+            .expect("parent goal to exist since refine was checked");
+
+        let effort_from_parent = parent_goal
+            .refine(child_goal_id, parent_effort_removed)
+            .expect("newly generated goal id to be unique by definition");
+        child_goal.set_effort_from_parent(effort_from_parent);
+        self.goals.insert(child_goal_id, child_goal);
 
-            assert!(!profile.get_goal(goal_id).unwrap().finished());
-            assert_eq!(profile.unfinished_goals(), HashSet::from([goal_id]));
-            assert_eq!(profile.finished_goals(), HashSet::from([]));
+        Ok(child_goal_id)
+    }
 
-            profile.get_goal_mut(goal_id).unwrap().add_effort(1);
-            assert!(!profile.get_goal(goal_id).unwrap().finished());
-            assert_eq!(profile.unfinished_goals(), HashSet::from([goal_id]));
-            assert_eq!(profile.finished_goals(), HashSet::from([]));
+    /// Export the whole profile as JSON that can be read back with
+    /// [import_json](Profile::import_json).
+    pub fn export_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("profiles to be serializable")
+    }
 
-            profile.get_goal_mut(goal_id).unwrap().add_effort(1);
-            assert!(profile.get_goal(goal_id).unwrap().finished());
-            assert_eq!(profile.unfinished_goals(), HashSet::from([]));
-            assert_eq!(profile.finished_goals(), HashSet::from([goal_id]));
+    /// Read a profile exported with [export_json](Profile::export_json). Profiles that
+    /// [validate](Profile::validate) finds inconsistent are rejected, since hand-edited
+    /// JSON can refer to goals that don't exist.
+    pub fn import_json(json: &str) -> Result<Profile, ImportError> {
+        let profile: Profile =
+            serde_json::from_str(json).map_err(|e| ImportError::Malformed(e.to_string()))?;
+        for goal in profile.goals.values() {
+            check_goal_name(goal.name())?;
+        }
 
-            profile.get_goal_mut(goal_id).unwrap().rescope(4);
-            assert!(!profile.get_goal(goal_id).unwrap().finished());
-            assert_eq!(profile.unfinished_goals(), HashSet::from([goal_id]));
-            assert_eq!(profile.finished_goals(), HashSet::from([]));
+        let inconsistencies = profile.validate();
+        if !inconsistencies.is_empty() {
+            return Err(ImportError::Inconsistent(inconsistencies));
+        }
 
-            profile.get_goal_mut(goal_id).unwrap().add_effort(1);
-            assert!(!profile.get_goal(goal_id).unwrap().finished());
-            assert_eq!(profile.unfinished_goals(), HashSet::from([goal_id]));
-            assert_eq!(profile.finished_goals(), HashSet::from([]));
+        Ok(profile)
+    }
 
-            profile.get_goal_mut(goal_id).unwrap().add_effort(1);
-            assert!(profile.get_goal(goal_id).unwrap().finished());
-            assert_eq!(profile.unfinished_goals(), HashSet::from([]));
-            assert_eq!(profile.finished_goals(), HashSet::from([goal_id]));
+    /// Export a goal and its descendants as JSON that can be imported into any profile
+    /// with [import_subtree](Profile::import_subtree). Returns None if no goal exists
+    /// with the provided `goal_id`.
+    pub fn export_subtree(&self, goal_id: GoalId) -> Option<String> {
+        let (populated_goal, _) = populate_goal_tree(&self.goals, goal_id)?;
+
+        Some(serde_json::to_string(&populated_goal).expect("populated goals to be serializable"))
+    }
+
+    /// Import a goal subtree exported with [export_subtree](Profile::export_subtree),
+    /// giving every goal a fresh id. The root of the subtree is added as a child of
+    /// `parent` without removing effort from it, or as a root goal if there is no
+    /// parent. Returns the id of the new root of the subtree.
+    pub fn import_subtree(
+        &mut self,
+        json: &str,
+        parent: Option<GoalId>,
+        reset_effort_to_date: bool,
+    ) -> Result<GoalId, ImportError> {
+        let populated_goal: PopulatedGoal =
+            serde_json::from_str(json).map_err(|e| ImportError::Malformed(e.to_string()))?;
+        if let Some(parent_goal_id) = parent {
+            if !self.goals.contains_key(&parent_goal_id) {
+                return Err(ImportError::NoSuchParent(parent_goal_id));
+            }
+        }
+        // Check every name up front so that nothing is imported if any is invalid
+        let mut to_check = vec![&populated_goal];
+        while let Some(populated_goal) = to_check.pop() {
+            check_goal_name(&populated_goal.name)?;
+            to_check.extend(populated_goal.children.iter());
         }
 
-        #[test]
-        fn goal_deletion() {
-            let mut profile = Profile::default();
+        // Goals are added breadth first so that children keep their order
+        let mut root_goal_id = None;
+        let mut to_import: VecDeque<(&PopulatedGoal, Option<GoalId>)> =
+            VecDeque::from([(&populated_goal, parent)]);
+        while let Some((populated_goal, parent_goal_id)) = to_import.pop_front() {
+            let mut goal = Goal::new(
+                populated_goal.name.clone(),
+                populated_goal.effort_to_complete,
+            );
+            if !reset_effort_to_date {
+                goal.add_effort(populated_goal.effort_to_date);
+            }
+            goal.set_notes(populated_goal.notes.clone());
+            goal.set_deadline(populated_goal.deadline);
+            goal.set_priority(populated_goal.priority);
+
+            let goal_id = self
+                .add_goal(goal)
+                .expect("goal names to be valid since they were checked");
+            if let Some(parent_goal_id) = parent_goal_id {
+                self.goals
+                    .get_mut(&parent_goal_id)
+                    .expect("parent goal to exist since it was checked or just added")
+                    .add_child(goal_id)
+                    .expect("newly generated goal id to be unique by definition");
+            }
+            root_goal_id.get_or_insert(goal_id);
+
+            to_import.extend(
+                populated_goal
+                    .children
+                    .iter()
+                    .map(|child| (child, Some(goal_id))),
+            );
+        }
 
-            let datetime = Utc.with_ymd_and_hms(2022, 1, 1, 1, 0, 0).unwrap();
+        Ok(root_goal_id.expect("subtree to have a root"))
+    }
 
-            let profile = profile.with_datetime(datetime);
+    /// Check whether merging goals with [merge_goal_into](Profile::merge_goal_into)
+    /// would succeed without modifying the profile.
+    pub fn check_merge(
+        &self,
+        source_goal_id: GoalId,
+        target_goal_id: GoalId,
+    ) -> Result<(), MergeError> {
+        for goal_id in [source_goal_id, target_goal_id] {
+            if !self.goals.contains_key(&goal_id) {
+                return Err(MergeError::NoSuchGoal(goal_id));
+            }
+        }
+
+        if source_goal_id == target_goal_id {
+            return Err(MergeError::MergeIntoSelf(source_goal_id));
+        }
+
+        if self
+            .descendants(source_goal_id)
+            .is_some_and(|descendants| descendants.contains(&target_goal_id))
+        {
+            return Err(MergeError::MergeIntoDescendant {
+                source_goal_id,
+                target_goal_id,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Merge the source goal into the target goal. The children of the source goal are
+    /// moved under the target goal, the effort to date of the source goal is added to
+    /// the target goal and event relationships with the source goal are moved to the
+    /// target goal before the source goal is removed. Moved relationships that would
+    /// duplicate one the event already has with the target are dropped.
+    pub fn merge_goal_into(
+        &mut self,
+        source_goal_id: GoalId,
+        target_goal_id: GoalId,
+    ) -> Result<(), MergeError> {
+        self.invalidate_active_goals_cache();
+        self.check_merge(source_goal_id, target_goal_id)?;
+
+        if let Some(parent_goal_id) = get_goal_parent_id(&self.goals, source_goal_id) {
+            if let Some(parent_goal) = self.goals.get_mut(&parent_goal_id) {
+                parent_goal.remove_child(source_goal_id);
+            }
+        }
+
+        let source_goal = self
+            .goals
+            .remove(&source_goal_id)
+            .expect("source goal to exist since merge was checked");
+        self.focused_goals.remove(&source_goal_id);
+        self.collapsed.remove(&source_goal_id);
+
+        let target_goal = self
+            .goals
+            .get_mut(&target_goal_id)
+            .expect("target goal to exist since merge was checked");
+        target_goal.add_effort(source_goal.effort_to_date());
+        for child_id in source_goal.children() {
+            target_goal
+                .add_child(*child_id)
+                .expect("target to not be a descendant of the source since merge was checked");
+        }
+
+        for event in &mut self.events.values_mut() {
+            let relationships = event.goal_relationships_mut();
+            if !relationships
+                .iter()
+                .any(|relationship| relationship.goal_id() == source_goal_id)
+            {
+                continue;
+            }
+
+            for relationship in relationships.iter_mut() {
+                if relationship.goal_id() == source_goal_id {
+                    *relationship.goal_id_mut() = target_goal_id;
+                }
+            }
+
+            let mut kept_relationships = vec![];
+            relationships.retain(|relationship| {
+                let duplicate = kept_relationships.contains(relationship);
+                kept_relationships.push(*relationship);
+                !duplicate
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Check whether splitting a goal with [split_goal](Profile::split_goal) would
+    /// succeed without modifying the profile.
+    pub fn check_split(&self, goal_id: GoalId, split_effort: u32) -> Result<(), SplitError> {
+        let Some(goal) = self.goals.get(&goal_id) else {
+            return Err(SplitError::NoSuchGoal(goal_id));
+        };
+
+        if split_effort > goal.effort_remaining() {
+            return Err(SplitError::EffortExceedsRemaining {
+                split_effort,
+                effort_remaining: goal.effort_remaining(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Move `split_effort` of a goal's remaining effort into a new goal named
+    /// `new_name`. The new goal is added as a sibling of the goal, or as a root goal if
+    /// the goal has no parent. Returns the id of the new goal.
+    pub fn split_goal(
+        &mut self,
+        goal_id: GoalId,
+        split_effort: u32,
+        new_name: &str,
+    ) -> Result<GoalId, SplitError> {
+        check_goal_name(new_name)?;
+        self.invalidate_active_goals_cache();
+        self.check_split(goal_id, split_effort)?;
+
+        let goal = self
+            .goals
+            .get_mut(&goal_id)
+            .expect("goal to exist since split was checked");
+        goal.rescope(goal.effort_to_complete() - split_effort);
+
+        let new_goal_id = self.next_goal_id();
+        if let Some(parent_goal_id) = get_goal_parent_id(&self.goals, goal_id) {
+            self.goals
+                .get_mut(&parent_goal_id)
+                .expect("parent goal to exist")
+                .add_child(new_goal_id)
+                .expect("newly generated goal id to be unique by definition");
+        }
+        self.goals
+            .insert(new_goal_id, Goal::new(new_name.to_string(), split_effort));
+
+        Ok(new_goal_id)
+    }
+
+    fn remove_goals_from_event_relationships(&mut self, goal_ids: &HashSet<GoalId>) {
+        for event in &mut self.events.values_mut() {
+            event
+                .goal_relationships_mut()
+                .retain(|relationship| !goal_ids.contains(&relationship.goal_id()))
+        }
+    }
+
+    /// Remove every event relationship pointing at a goal that no longer exists,
+    /// returning the number of relationships removed. Used to repair profiles saved
+    /// while deleting goals left their relationships behind.
+    pub fn prune_dangling_relationships(&mut self) -> usize {
+        let mut removed_count = 0;
+        for event in self.events.values_mut() {
+            let relationships = event.goal_relationships_mut();
+            let original_count = relationships.len();
+            relationships.retain(|relationship| self.goals.contains_key(&relationship.goal_id()));
+            removed_count += original_count - relationships.len();
+        }
+
+        if removed_count > 0 {
+            self.invalidate_active_goals_cache();
+        }
+
+        removed_count
+    }
+
+    pub fn remove_goal(&mut self, goal_id: GoalId) -> Option<PopulatedGoal> {
+        self.invalidate_active_goals_cache();
+        if let Some((populated_goal, child_ids_needing_removal)) =
+            populate_goal_tree(&self.goals, goal_id)
+        {
+            self.goals.remove(&goal_id);
+            self.focused_goals.remove(&goal_id);
+            self.collapsed.remove(&goal_id);
+
+            for goal_id in child_ids_needing_removal.iter() {
+                self.goals.remove(goal_id);
+                self.focused_goals.remove(goal_id);
+                self.collapsed.remove(goal_id);
+            }
+
+            let mut removed_goal_ids = child_ids_needing_removal;
+            removed_goal_ids.insert(goal_id);
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                ?goal_id,
+                removed_count = removed_goal_ids.len(),
+                "removed goal"
+            );
+            self.remove_goals_from_event_relationships(&removed_goal_ids);
+            if let Some(parent_goal_id) = populated_goal.parent_goal_id {
+                if let Some(parent_goal) = self.goals.get_mut(&parent_goal_id) {
+                    parent_goal.remove_child(goal_id);
+                }
+            }
+
+            Some(populated_goal)
+        } else {
+            None
+        }
+    }
+
+    pub fn add_event(&mut self, event: Event) -> EventId {
+        self.invalidate_active_goals_cache();
+        let event_id = self.next_event_id();
+        self.events.insert(event_id, event);
+
+        event_id
+    }
+
+    pub fn remove_event(&mut self, event_id: EventId) -> Option<Event> {
+        self.invalidate_active_goals_cache();
+        self.effort_applied_events.remove(&event_id);
+        self.events.remove(&event_id)
+    }
+
+    /// Renumber all goals to the dense range `0..n`, preserving the relative order
+    /// of the existing ids, and reset the goal id counter to `n`. Children, focused
+    /// goals and event relationships are rewritten to the new ids. Event relationships
+    /// pointing at goals that no longer exist are dropped since their ids could
+    /// otherwise alias a renumbered goal.
+    ///
+    /// Returns the mapping from old to new ids. This invalidates any goal ids stored
+    /// outside of the profile, such as a goal event history, cursors or ids held by a
+    /// frontend, and callers are responsible for rewriting or discarding them.
+    pub fn compact_ids(&mut self) -> HashMap<GoalId, GoalId> {
+        self.invalidate_active_goals_cache();
+        let mut old_ids: Vec<GoalId> = self.goals.keys().copied().collect();
+        old_ids.sort();
+
+        let id_mapping: HashMap<GoalId, GoalId> = old_ids
+            .into_iter()
+            .enumerate()
+            .map(|(new_id, old_id)| (old_id, GoalId(new_id as u32)))
+            .collect();
+
+        let goals = self
+            .goals
+            .drain()
+            .map(|(old_id, mut goal)| {
+                goal.remap_children(&id_mapping);
+                (id_mapping[&old_id], goal)
+            })
+            .collect();
+        self.goals = goals;
+
+        self.focused_goals = self
+            .focused_goals
+            .iter()
+            .filter_map(|id| id_mapping.get(id).copied())
+            .collect();
+        self.collapsed = self
+            .collapsed
+            .iter()
+            .filter_map(|id| id_mapping.get(id).copied())
+            .collect();
+
+        for event in self.events.values_mut() {
+            let relationships = event.goal_relationships_mut();
+            relationships.retain(|relationship| id_mapping.contains_key(&relationship.goal_id()));
+            for relationship in relationships.iter_mut() {
+                let goal_id = relationship.goal_id_mut();
+                *goal_id = id_mapping[goal_id];
+            }
+        }
+
+        self.goal_id_count = id_mapping.len() as u32;
+
+        id_mapping
+    }
+
+    pub fn populate_goals(&self) -> Vec<PopulatedGoal> {
+        fn mark_collapsed(goal: &mut PopulatedGoal, collapsed: &HashSet<GoalId>) {
+            goal.collapsed = collapsed.contains(&goal.id);
+            for child in goal.children.iter_mut() {
+                mark_collapsed(child, collapsed);
+            }
+        }
+
+        let root_goal_ids = get_root_goals(&self.goals);
+
+        root_goal_ids
+            .map(|root_goal_id| {
+                let mut root_goal =
+                    populate_goals_with_focus(&self.goals, root_goal_id, &self.focused_goals)
+                        .unwrap()
+                        .0;
+                mark_collapsed(&mut root_goal, &self.collapsed);
+                root_goal
+            })
+            .collect()
+    }
+
+    /// Populate only the focused goals. Unfocused goals are pruned from the trees, and
+    /// focused goals whose parent is unfocused are promoted to roots so that goals
+    /// focused individually under an unfocused parent are still shown. Layout data such
+    /// as [max_child_depth](PopulatedGoal::max_child_depth) is not populated.
+    pub fn populate_focused_goals(&self) -> Vec<PopulatedGoal> {
+        let parents: HashMap<GoalId, GoalId> = self
+            .goals
+            .iter()
+            .flat_map(|(&parent_id, goal)| goal.children().iter().map(move |&id| (id, parent_id)))
+            .collect();
+
+        let mut focused_root_ids: Vec<GoalId> = self
+            .focused_goals
+            .iter()
+            .filter(|id| {
+                parents
+                    .get(id)
+                    .is_none_or(|parent_id| !self.focused_goals.contains(parent_id))
+            })
+            .copied()
+            .collect();
+        focused_root_ids.sort();
+
+        focused_root_ids
+            .into_iter()
+            .filter_map(|root_goal_id| {
+                populate_partitioned_goal_tree(&self.goals, root_goal_id, &|child_id, _| {
+                    self.focused_goals.contains(&child_id)
+                })
+            })
+            .map(|PartitionedPopulatedTree { populated_tree, .. }| {
+                let mut root_goal = populated_tree;
+
+                let mut needs_pruning = vec![&mut root_goal];
+                while let Some(goal) = needs_pruning.pop() {
+                    goal.children
+                        .retain(|child| self.focused_goals.contains(&child.id));
+                    goal.collapsed = self.collapsed.contains(&goal.id);
+                    goal.focused = true;
+                    needs_pruning.extend(goal.children.iter_mut());
+                }
+
+                root_goal
+            })
+            .collect()
+    }
+
+    /// Populate goals like [populate_goals](Profile::populate_goals), with the root
+    /// goals and the children of every goal sorted by `key`. Cursors should be resolved
+    /// against the same sorted goals that are displayed.
+    pub fn populate_goals_sorted(&self, key: SortKey) -> Vec<PopulatedGoal> {
+        let mut populated_goals = self.populate_goals();
+        key.sort(&mut populated_goals);
+
+        populated_goals
+    }
+
+    pub fn collapsed_goals(&self) -> &HashSet<GoalId> {
+        &self.collapsed
+    }
+
+    pub fn is_collapsed(&self, goal_id: GoalId) -> bool {
+        self.collapsed.contains(&goal_id)
+    }
+
+    /// Hide the children of a goal. Returns false if the goal does not exist or is
+    /// already collapsed.
+    pub fn collapse_goal(&mut self, goal_id: GoalId) -> bool {
+        self.goals.contains_key(&goal_id) && self.collapsed.insert(goal_id)
+    }
+
+    /// Show the children of a collapsed goal. Returns false if the goal was not
+    /// collapsed.
+    pub fn expand_goal(&mut self, goal_id: GoalId) -> bool {
+        self.collapsed.remove(&goal_id)
+    }
+
+    /// Report how the effort of a goal has been delegated to its children. Returns
+    /// None if no goal exists with the provided `goal_id`.
+    pub fn effort_report(&self, goal_id: GoalId) -> Option<EffortReport> {
+        let goal = self.goals.get(&goal_id)?;
+        let children = goal
+            .children()
+            .iter()
+            .filter_map(|child_id| self.goals.get(child_id));
+
+        let (delegated_to_children, child_scope) =
+            children.fold((0, 0), |(delegated, scope), child| {
+                (
+                    delegated + child.effort_from_parent(),
+                    scope + child.effort_to_complete(),
+                )
+            });
+
+        Some(EffortReport {
+            goal_id,
+            original_effort_to_complete: goal.effort_to_complete() + delegated_to_children,
+            current_effort_to_complete: goal.effort_to_complete(),
+            delegated_to_children,
+            child_scope,
+        })
+    }
+
+    /// Get the ids of every goal below a goal, not including the goal itself. Returns
+    /// None if no goal exists with the provided `goal_id`.
+    pub fn descendants(&self, goal_id: GoalId) -> Option<HashSet<GoalId>> {
+        visit_goal_child_tree(&self.goals, goal_id, &mut |_, _, _, _| {}, ())
+    }
+
+    /// Get the ids of the goals above a goal, starting with its parent and ending with
+    /// the root of its goal tree. Root goals and nonexistent goals have no ancestors.
+    pub fn ancestors(&self, goal_id: GoalId) -> Vec<GoalId> {
+        let mut ancestors = vec![];
+        let mut current_goal_id = goal_id;
+
+        while let Some(parent_goal_id) = get_goal_parent_id(&self.goals, current_goal_id) {
+            // Guard against looping forever on a malformed profile with a cycle
+            if parent_goal_id == goal_id || ancestors.contains(&parent_goal_id) {
+                break;
+            }
+
+            ancestors.push(parent_goal_id);
+            current_goal_id = parent_goal_id;
+        }
+
+        ancestors
+    }
+
+    /// The effort remaining in a goal and all of its descendants, saturating at
+    /// [u32::MAX]. Returns None if no goal exists with the provided `goal_id`.
+    pub fn subtree_effort_remaining(&self, goal_id: GoalId) -> Option<u32> {
+        let mut effort_remaining = self.goals.get(&goal_id)?.effort_remaining();
+        visit_goal_child_tree(
+            &self.goals,
+            goal_id,
+            &mut |_, _, _, child_goal| {
+                effort_remaining = effort_remaining.saturating_add(child_goal.effort_remaining());
+            },
+            (),
+        )?;
+
+        Some(effort_remaining)
+    }
+
+    /// The earliest deadline of a goal and all of its descendants, so that parents
+    /// without a deadline of their own can show the one they inherit from their
+    /// children. Returns None if no goal in the subtree has a deadline or no goal
+    /// exists with the provided `goal_id`.
+    pub fn effective_deadline(&self, goal_id: GoalId) -> Option<DateTime<Utc>> {
+        let mut earliest_deadline = self.goals.get(&goal_id)?.deadline();
+        visit_goal_child_tree(
+            &self.goals,
+            goal_id,
+            &mut |_, _, _, child_goal| {
+                earliest_deadline = match (earliest_deadline, child_goal.deadline()) {
+                    (Some(earliest), Some(deadline)) => Some(earliest.min(deadline)),
+                    (earliest, deadline) => earliest.or(deadline),
+                };
+            },
+            (),
+        )?;
+
+        earliest_deadline
+    }
+
+    /// Find the path from a goal down to a leaf goal with the largest summed
+    /// effort to complete, returning the ids along the path and its total effort.
+    /// Ties are broken in favour of the earlier child. Returns None if no goal
+    /// exists with the provided `goal_id`.
+    pub fn effort_critical_path(&self, goal_id: GoalId) -> Option<(Vec<GoalId>, u32)> {
+        let mut visit_order = vec![goal_id];
+        visit_goal_child_tree(
+            &self.goals,
+            goal_id,
+            &mut |_, _, child_id, _| visit_order.push(child_id),
+            (),
+        )?;
+
+        // Children are always visited after their parents, so iterating in reverse
+        // ensures the best path of every child is known before its parent.
+        let mut best_paths: HashMap<GoalId, (u32, Option<GoalId>)> = HashMap::new();
+        for id in visit_order.iter().rev() {
+            let goal = &self.goals[id];
+
+            let mut best_child: Option<(u32, GoalId)> = None;
+            for child_id in goal.children() {
+                let (child_effort, _) = best_paths[child_id];
+                if best_child.is_none_or(|(best_effort, _)| child_effort > best_effort) {
+                    best_child = Some((child_effort, *child_id));
+                }
+            }
+
+            best_paths.insert(
+                *id,
+                (
+                    goal.effort_to_complete()
+                        .saturating_add(best_child.map_or(0, |(effort, _)| effort)),
+                    best_child.map(|(_, child_id)| child_id),
+                ),
+            );
+        }
+
+        let (total_effort, mut next_id) = best_paths[&goal_id];
+        let mut path = vec![goal_id];
+        while let Some(id) = next_id {
+            path.push(id);
+            next_id = best_paths[&id].1;
+        }
+
+        Some((path, total_effort))
+    }
+
+    pub fn with_datetime(&mut self, datetime: DateTime<Utc>) -> ProfileAndDateTime {
+        ProfileAndDateTime(self, datetime)
+    }
+
+    pub fn get_event(&self, id: EventId) -> Option<&Event> {
+        self.events.get(&id)
+    }
+
+    /// Pairs of block events whose time ranges overlap, each with the smaller event id
+    /// first and sorted. Events that only touch, with one ending as the next starts, do
+    /// not overlap.
+    pub fn overlapping_block_events(&self) -> Vec<(EventId, EventId)> {
+        let mut block_events: Vec<(DateTime<Utc>, DateTime<Utc>, EventId)> = self
+            .events
+            .iter()
+            .filter_map(|(&event_id, event)| match event {
+                Event::BlockEvent(block_event) => Some((
+                    block_event.start,
+                    block_event.start + block_event.duration,
+                    event_id,
+                )),
+                _ => None,
+            })
+            .collect();
+        block_events.sort();
+
+        // Sweep through events by start time, keeping the events that have not ended
+        // yet. Each event overlaps exactly the events still active when it starts.
+        let mut active: Vec<(DateTime<Utc>, EventId)> = vec![];
+        let mut overlapping = vec![];
+        for (start, end, event_id) in block_events {
+            active.retain(|(active_end, _)| *active_end > start);
+            for (_, active_event_id) in active.iter() {
+                overlapping.push((
+                    (*active_event_id).min(event_id),
+                    (*active_event_id).max(event_id),
+                ));
+            }
+            active.push((end, event_id));
+        }
+        overlapping.sort();
+
+        overlapping
+    }
+
+    /// Every relationship pointing at a goal along with the event it is on, ordered by
+    /// event id.
+    pub fn events_for_goal(&self, goal_id: GoalId) -> Vec<(EventId, &Event, &GoalRelationship)> {
+        let mut events_for_goal: Vec<_> = self
+            .events
+            .iter()
+            .flat_map(|(&event_id, event)| {
+                event
+                    .goal_relationships()
+                    .iter()
+                    .filter(move |relationship| relationship.goal_id() == goal_id)
+                    .map(move |relationship| (event_id, event, relationship))
+            })
+            .collect();
+        events_for_goal.sort_by_key(|(event_id, _, _)| *event_id);
+
+        events_for_goal
+    }
+
+    /// When an event starts. Floating events start at the start of their time of day
+    /// in the local timezone.
+    pub fn event_start(&self, id: EventId) -> Option<DateTime<Utc>> {
+        Some(match self.events.get(&id)? {
+            Event::BlockEvent(event) => event.start,
+            Event::InstantEvent(event) => event.time,
+            Event::FloatingEvent(event) => {
+                event_query_helpers::floating_event_start(&self.time_of_day_config, event)?
+            }
+        })
+    }
+
+    /// The events starting, working on or ending a goal in the order they start, with
+    /// ties broken by event id. Requirements are not part of a goal's timeline.
+    pub fn goal_timeline(&self, goal_id: GoalId) -> Vec<TimelineEntry> {
+        let mut timeline: Vec<TimelineEntry> = self
+            .events_for_goal(goal_id)
+            .into_iter()
+            .filter_map(|(event_id, _, relationship)| {
+                let kind = match relationship {
+                    GoalRelationship::Starts(_) => TimelineEntryKind::Start,
+                    GoalRelationship::WorksOn(_) | GoalRelationship::WorksOnWithEffort { .. } => {
+                        TimelineEntryKind::WorkSession
+                    }
+                    GoalRelationship::Ends(_) => TimelineEntryKind::End,
+                    GoalRelationship::Requires(_) => return None,
+                };
+
+                Some(TimelineEntry {
+                    event_id,
+                    kind,
+                    datetime: self.event_start(event_id)?,
+                })
+            })
+            .collect();
+        timeline.sort_by_key(|entry| (entry.datetime, entry.event_id));
+
+        timeline
+    }
+
+    pub fn get_event_mut(&mut self, id: EventId) -> Option<&mut Event> {
+        self.invalidate_active_goals_cache();
+        self.events.get_mut(&id)
+    }
+
+    pub fn get_goal(&self, id: GoalId) -> Option<&Goal> {
+        self.goals.get(&id)
+    }
+
+    pub fn get_goal_parent_id(&self, id: GoalId) -> Option<GoalId> {
+        get_goal_parent_id(&self.goals, id)
+    }
+
+    pub fn get_goal_mut(&mut self, id: GoalId) -> Option<&mut Goal> {
+        self.invalidate_active_goals_cache();
+        self.goals.get_mut(&id)
+    }
+
+    pub fn focused_goals(&self) -> &HashSet<GoalId> {
+        &self.focused_goals
+    }
+
+    pub fn unfocused_goals(&self) -> HashSet<GoalId> {
+        self.goal_ids()
+            .difference(&self.focused_goals)
+            .copied()
+            .collect()
+    }
+
+    pub fn goal_ids(&self) -> HashSet<GoalId> {
+        self.goals.iter().map(|(&id, _)| id).collect()
+    }
+
+    pub fn goal_count(&self) -> usize {
+        self.goals.len()
+    }
+
+    pub fn statistics(&self) -> ProfileStats {
+        let finished_count = self.goals.values().filter(|goal| goal.finished()).count();
+
+        let max_tree_depth = get_root_goals(&self.goals)
+            .filter_map(|root_goal_id| populate_goal_tree(&self.goals, root_goal_id))
+            .map(|(root_goal, _)| root_goal.max_child_depth + 1)
+            .max()
+            .unwrap_or(0);
+
+        ProfileStats {
+            goal_count: self.goals.len(),
+            finished_count,
+            unfinished_count: self.goals.len() - finished_count,
+            total_effort_to_date: self
+                .goals
+                .values()
+                .map(|goal| u64::from(goal.effort_to_date()))
+                .sum(),
+            total_effort_remaining: self
+                .goals
+                .values()
+                .map(|goal| u64::from(goal.effort_remaining()))
+                .sum(),
+            max_tree_depth,
+            focused_count: self.focused_goals.len(),
+        }
+    }
+
+    /// Compare the goals of this profile to another, matching goals by id.
+    pub fn diff(&self, other: &Profile) -> ProfileDiff {
+        let goal_ids = self.goal_ids();
+        let other_goal_ids = other.goal_ids();
+
+        let sorted = |goal_ids: HashSet<GoalId>| {
+            let mut goal_ids: Vec<GoalId> = goal_ids.into_iter().collect();
+            goal_ids.sort();
+            goal_ids
+        };
+
+        let modified = goal_ids
+            .intersection(&other_goal_ids)
+            .filter(|goal_id| {
+                let goal = &self.goals[goal_id];
+                let other_goal = &other.goals[goal_id];
+
+                goal.name() != other_goal.name()
+                    || goal.effort_to_date() != other_goal.effort_to_date()
+                    || goal.effort_to_complete() != other_goal.effort_to_complete()
+                    || goal.children() != other_goal.children()
+            })
+            .copied()
+            .collect();
+
+        ProfileDiff {
+            added: sorted(other_goal_ids.difference(&goal_ids).copied().collect()),
+            removed: sorted(goal_ids.difference(&other_goal_ids).copied().collect()),
+            modified: sorted(modified),
+        }
+    }
+
+    /// Reconstruct a profile by applying a goal event history, as returned from
+    /// [handle_request](crate::request::GoalRequestHandler::handle_request), to an empty profile.
+    pub fn replay(events: &[GoalEvent]) -> Profile {
+        let mut profile = Profile::default();
+        for event in events {
+            profile.apply_goal_event(event);
+        }
+
+        profile
+    }
+
+    /// Apply a goal event forward. Events that refer to goals that do not exist are
+    /// ignored.
+    fn apply_goal_event(&mut self, event: &GoalEvent) {
+        self.invalidate_active_goals_cache();
+        match event {
+            GoalEvent::AddEffort {
+                goal_id,
+                effort,
+                datetime,
+            } => {
+                if let Some(goal) = self.goals.get_mut(goal_id) {
+                    goal.add_effort(*effort);
+                    goal.set_last_effort_at(*datetime);
+                }
+            }
+            GoalEvent::RemoveEffort {
+                goal_id,
+                effort,
+                datetime,
+            } => {
+                if let Some(goal) = self.goals.get_mut(goal_id) {
+                    goal.remove_effort(*effort);
+                    goal.set_last_effort_at(*datetime);
+                }
+            }
+            GoalEvent::Focus {
+                focus_root_id,
+                focused_children,
+            } => {
+                self.focused_goals.insert(*focus_root_id);
+                self.focused_goals.extend(focused_children.iter().copied());
+            }
+            GoalEvent::Unfocus {
+                unfocus_root_id,
+                unfocused_children,
+            } => {
+                self.focused_goals.remove(unfocus_root_id);
+                for child_id in unfocused_children {
+                    self.focused_goals.remove(child_id);
+                }
+            }
+            GoalEvent::FocusSingle(goal_id) => {
+                self.focus_single_goal(*goal_id);
+            }
+            GoalEvent::FocusAll { focused_goals } => {
+                self.focused_goals.extend(focused_goals.iter().copied());
+            }
+            GoalEvent::UnfocusAll { .. } => {
+                self.unfocus_all();
+            }
+            GoalEvent::SwapSiblings {
+                parent_goal_id,
+                goal_id,
+                other_goal_id,
+            } => {
+                if let Some(parent_goal) = self.goals.get_mut(parent_goal_id) {
+                    let _ = parent_goal.swap_children(*goal_id, *other_goal_id);
+                }
+            }
+            GoalEvent::UnfocusSingle(goal_id) => {
+                self.unfocus_single_goal(*goal_id);
+            }
+            GoalEvent::RescopeByFinish {
+                goal_id,
+                effort_done_at_time_of_finish,
+                ..
+            } => {
+                if let Some(goal) = self.goals.get_mut(goal_id) {
+                    goal.rescope_by_finish(*effort_done_at_time_of_finish);
+                }
+            }
+            GoalEvent::Rescope {
+                goal_id,
+                new_effort_to_complete,
+                clamped_effort,
+                ..
+            } => {
+                if let Some(goal) = self.goals.get_mut(goal_id) {
+                    goal.rescope(*new_effort_to_complete);
+                    goal.remove_effort(*clamped_effort);
+                }
+            }
+            GoalEvent::Add {
+                goal_id,
+                name,
+                effort_to_complete,
+            } => {
+                // Follow the counter the same way adding the goal moved it, which wraps
+                // after the largest id
+                self.goal_id_count = goal_id.0.wrapping_add(1);
+                self.goals
+                    .insert(*goal_id, Goal::new(name.clone(), *effort_to_complete));
+            }
+            GoalEvent::Refine {
+                parent_goal_id,
+                parent_effort_removed,
+                new_child_goal_id,
+                child_name,
+                child_effort_to_complete,
+            } => {
+                let Some(parent_goal) = self.goals.get_mut(parent_goal_id) else {
+                    return;
+                };
+                let Ok(effort_from_parent) =
+                    parent_goal.refine(*new_child_goal_id, *parent_effort_removed)
+                else {
+                    return;
+                };
+
+                let mut child_goal = Goal::new(child_name.clone(), *child_effort_to_complete);
+                child_goal.set_effort_from_parent(effort_from_parent);
+
+                self.goal_id_count = new_child_goal_id.0.wrapping_add(1);
+                self.goals.insert(*new_child_goal_id, child_goal);
+            }
+            GoalEvent::Delete { deleted_goal_tree } => {
+                self.remove_goal(deleted_goal_tree.id);
+            }
+            GoalEvent::Rename {
+                goal_id, new_name, ..
+            } => {
+                // Histories recorded before blank names were rejected may still rename
+                // goals to one, which replay keeps like it does for added goals
+                if let Some(goal) = self.goals.get_mut(goal_id) {
+                    goal.rename(new_name);
+                }
+            }
+            GoalEvent::Tag { goal_id, tag } => {
+                self.tag_goal(*goal_id, tag);
+            }
+            GoalEvent::Untag { goal_id, tag } => {
+                self.untag_goal(*goal_id, tag);
+            }
+            GoalEvent::TimeOfDayConfigChanged { new_config, .. } => {
+                self.set_time_of_day_config(*new_config);
+            }
+            GoalEvent::EffortUnitChanged { new_unit, .. } => {
+                self.set_effort_unit(new_unit.clone());
+            }
+            GoalEvent::Finished { .. } => {}
+            GoalEvent::Merge {
+                source_goal_id,
+                target_goal_id,
+                ..
+            } => {
+                let _ = self.merge_goal_into(*source_goal_id, *target_goal_id);
+            }
+            GoalEvent::SetNotes { goal_id, notes, .. } => {
+                self.set_goal_notes(*goal_id, notes.clone());
+            }
+            GoalEvent::SetDeadline {
+                goal_id, deadline, ..
+            } => {
+                self.set_goal_deadline(*goal_id, *deadline);
+            }
+            GoalEvent::SetPriority {
+                goal_id, priority, ..
+            } => {
+                self.set_goal_priority(*goal_id, *priority);
+            }
+            GoalEvent::SetEffort {
+                goal_id,
+                new_effort,
+                datetime,
+                ..
+            } => {
+                if let Some(goal) = self.goals.get_mut(goal_id) {
+                    goal.set_effort_to_date(*new_effort);
+                    goal.set_last_effort_at(*datetime);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> ProfileAndDateTime<'a> {
+    pub fn get_event(&self, id: EventId) -> Option<&Event> {
+        self.0.events.get(&id)
+    }
+
+    pub fn get_event_mut(&mut self, id: EventId) -> Option<&mut Event> {
+        self.0.get_event_mut(id)
+    }
+
+    pub fn get_goal(&self, id: GoalId) -> Option<&Goal> {
+        self.0.goals.get(&id)
+    }
+
+    pub fn get_goal_mut(&mut self, id: GoalId) -> Option<&mut Goal> {
+        self.0.get_goal_mut(id)
+    }
+
+    /// Estimate when a goal and its descendants will be complete if effort is put into
+    /// them at `velocity_per_day`. Returns None if the goal does not exist or the
+    /// velocity is not positive.
+    pub fn estimated_completion(
+        &self,
+        goal_id: GoalId,
+        velocity_per_day: f64,
+    ) -> Option<DateTime<Utc>> {
+        if velocity_per_day.is_nan() || velocity_per_day <= 0.0 {
+            return None;
+        }
+
+        let remaining_effort = self.0.subtree_effort_remaining(goal_id)?;
+        let remaining_days = remaining_effort as f64 / velocity_per_day;
+
+        self.1.checked_add_signed(Duration::milliseconds(
+            (remaining_days * Duration::days(1).num_milliseconds() as f64).round() as i64,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod goal_query {
+        use std::collections::HashSet;
+
+        use chrono::{Duration, FixedOffset, NaiveTime, TimeZone, Utc};
+
+        use crate::{
+            event::{Event, TimeOfDay},
+            goal::{Goal, GoalEvent, GoalId, GoalOperationError, GoalRelationship, Priority},
+            profile::{
+                goal_predicates::{and, not, or, priority_at_least, tagged, unfinished},
+                goal_traversal::populate_goal_tree,
+                ImportError, Profile, RefineError, SortKey, SplitError,
+            },
+            query::{EventQueryEngine, GoalQueryEngine, TimeOfDayConfiguration},
+            request::{GoalRequest, GoalRequestHandler, RequestError},
+        };
+
+        #[test]
+        fn goals_matching_combined_predicates() {
+            let mut profile = Profile::default();
+            let tagged_id = profile.add_goal(Goal::new("tagged", 1)).unwrap();
+            profile.tag_goal(tagged_id, "work");
+            let high_priority_id = profile.add_goal(Goal::new("high priority", 1)).unwrap();
+            profile.set_goal_priority(high_priority_id, Priority::High);
+            let finished_id = profile.add_goal(Goal::new("finished", 0)).unwrap();
+            profile.tag_goal(finished_id, "work");
+
+            assert_eq!(
+                profile.goals_matching(and(
+                    unfinished(),
+                    or(tagged("work"), priority_at_least(Priority::High))
+                )),
+                HashSet::from([tagged_id, high_priority_id])
+            );
+            assert_eq!(
+                profile.goals_matching(not(tagged("work"))),
+                HashSet::from([high_priority_id])
+            );
+        }
+
+        #[test]
+        fn blank_names_are_rejected() {
+            let mut profile = Profile::default();
+            let goal_id = profile.add_goal(Goal::new("goal", 2)).unwrap();
+            let mut profile = profile.with_datetime(Utc::now());
+
+            let requests = [
+                GoalRequest::Create {
+                    name: " ".to_string(),
+                    effort_to_complete: 1,
+                },
+                GoalRequest::Refine {
+                    parent_goal_id: goal_id,
+                    parent_effort_removed: 0,
+                    child_name: "".to_string(),
+                    child_effort_to_complete: 0,
+                },
+                GoalRequest::Rename {
+                    goal_id,
+                    new_name: "\t\n".to_string(),
+                },
+                GoalRequest::Split {
+                    goal_id,
+                    split_effort: 1,
+                    new_name: "  ".to_string(),
+                },
+            ];
+            for request in requests {
+                assert!(matches!(
+                    profile.preview_request(&request),
+                    Err(RequestError::InvalidName(_))
+                ));
+                assert!(profile.handle_request(request).is_empty());
+            }
+
+            assert_eq!(profile.0.goal_count(), 1);
+            assert_eq!(profile.0.get_goal(goal_id).unwrap().name(), "goal");
+            assert!(profile
+                .preview_request(&GoalRequest::Rename {
+                    goal_id,
+                    new_name: " padded ".to_string(),
+                })
+                .is_ok());
+
+            let profile = profile.0;
+            assert!(profile.add_goal(Goal::new("", 1)).is_err());
+            assert!(matches!(
+                profile.refine_goal(Goal::new(" ", 0), goal_id, 0),
+                Err(RefineError::InvalidName(_))
+            ));
+            assert!(profile.rename_goal(goal_id, "\t").is_err());
+            assert!(matches!(
+                profile.split_goal(goal_id, 1, ""),
+                Err(SplitError::InvalidName(_))
+            ));
+
+            let mut blank_subtree = profile.populate_goals().remove(0);
+            blank_subtree.children.push({
+                let mut blank_child = blank_subtree.clone();
+                blank_child.name = " ".to_string();
+                blank_child
+            });
+            let blank_subtree = serde_json::to_string(&blank_subtree).unwrap();
+            assert!(matches!(
+                profile.import_subtree(&blank_subtree, None, false),
+                Err(ImportError::InvalidName(_))
+            ));
+
+            let mut blank_profile = Profile::default();
+            let blank_goal_id = blank_profile.add_goal(Goal::new("goal", 1)).unwrap();
+            blank_profile
+                .goals
+                .get_mut(&blank_goal_id)
+                .unwrap()
+                .rename("");
+            assert!(matches!(
+                Profile::import_json(&blank_profile.export_json()),
+                Err(ImportError::InvalidName(_))
+            ));
+
+            assert_eq!(profile.goal_count(), 1);
+            assert_eq!(profile.get_goal(goal_id).unwrap().name(), "goal");
+        }
+
+        #[test]
+        fn batch_applies_every_request() {
+            let mut profile = Profile::default();
+            let goal_id = profile.add_goal(Goal::new("goal", 2)).unwrap();
+            let mut profile = profile.with_datetime(Utc::now());
+
+            let batch = GoalRequest::Batch(vec![
+                GoalRequest::AddEffort { goal_id, effort: 1 },
+                GoalRequest::Rename {
+                    goal_id,
+                    new_name: "renamed".to_string(),
+                },
+            ]);
+            assert!(profile.preview_request(&batch).is_ok());
+            let events = profile.handle_request(batch);
+
+            assert!(matches!(
+                events[..],
+                [GoalEvent::AddEffort { .. }, GoalEvent::Rename { .. }]
+            ));
+            let goal = profile.0.get_goal(goal_id).unwrap();
+            assert_eq!(goal.effort_to_date(), 1);
+            assert_eq!(goal.name(), "renamed");
+        }
+
+        #[test]
+        fn batch_with_failing_request_is_not_applied() {
+            let mut profile = Profile::default();
+            let goal_id = profile.add_goal(Goal::new("goal", 2)).unwrap();
+            let other_goal_id = profile.add_goal(Goal::new("other goal", 2)).unwrap();
+            let mut profile = profile.with_datetime(Utc::now());
+
+            // Each request is valid on its own, but the goal no longer exists by the
+            // time effort is added to it
+            let batch = GoalRequest::Batch(vec![
+                GoalRequest::AddEffort {
+                    goal_id: other_goal_id,
+                    effort: 1,
+                },
+                GoalRequest::Delete(goal_id),
+                GoalRequest::AddEffort { goal_id, effort: 1 },
+            ]);
+            assert_eq!(
+                profile.preview_request(&batch),
+                Err(RequestError::Batch {
+                    index: 2,
+                    error: Box::new(RequestError::NoSuchGoal(goal_id)),
+                })
+            );
+            assert!(profile.handle_request(batch).is_empty());
+
+            assert!(profile.0.get_goal(goal_id).is_some());
+            assert_eq!(
+                profile.0.get_goal(other_goal_id).unwrap().effort_to_date(),
+                0
+            );
+        }
+
+        #[test]
+        fn populate_deep_goal_chain() {
+            const DEPTH: usize = 5000;
+
+            let mut profile = Profile::default();
+
+            let root_id = profile.add_goal(Goal::new("0", 0)).unwrap();
+            let mut leaf_id = root_id;
+            for depth in 1..DEPTH {
+                leaf_id = profile
+                    .refine_goal(Goal::new(depth.to_string(), 0), leaf_id, 0)
+                    .unwrap();
+            }
+
+            let (populated_goal, visited_ids) =
+                populate_goal_tree(&profile.goals, root_id).unwrap();
+
+            assert_eq!(visited_ids.len(), DEPTH - 1);
+            assert!(visited_ids.contains(&leaf_id));
+            assert_eq!(populated_goal.max_child_depth, DEPTH - 1);
+            assert_eq!(populated_goal.max_child_layer_width, 1);
+
+            let mut current = &populated_goal;
+            while let Some(child) = current.children.first() {
+                assert_eq!(child.parent_goal_id, Some(current.id));
+                current = child;
+            }
+            assert_eq!(current.id, leaf_id);
+            assert_eq!(current.max_child_depth, 0);
+        }
+
+        #[test]
+        fn focus_single_goal_only_emits_on_change() {
+            let mut profile = Profile::default();
+            let goal_id = profile.add_goal(Goal::new("goal", 0)).unwrap();
+
+            let mut profile = profile.with_datetime(Utc::now());
+            assert_eq!(
+                profile.handle_request(GoalRequest::FocusSingle(goal_id)),
+                vec![GoalEvent::FocusSingle(goal_id)]
+            );
+            assert!(profile
+                .handle_request(GoalRequest::FocusSingle(goal_id))
+                .is_empty());
+        }
+
+        #[test]
+        fn tag_requests_only_emit_on_change() {
+            let mut profile = Profile::default();
+            let goal_id = profile.add_goal(Goal::new("goal", 0)).unwrap();
+
+            let mut profile = profile.with_datetime(Utc::now());
+            let tag_request = || GoalRequest::Tag {
+                goal_id,
+                tag: "work".to_string(),
+            };
+            let untag_request = || GoalRequest::Untag {
+                goal_id,
+                tag: "work".to_string(),
+            };
+
+            assert!(profile.handle_request(untag_request()).is_empty());
+            assert_eq!(
+                profile.handle_request(tag_request()),
+                vec![GoalEvent::Tag {
+                    goal_id,
+                    tag: "work".to_string()
+                }]
+            );
+            assert!(profile.0.get_goal(goal_id).unwrap().tags().contains("work"));
+            assert!(profile.handle_request(tag_request()).is_empty());
+
+            assert_eq!(
+                profile.handle_request(untag_request()),
+                vec![GoalEvent::Untag {
+                    goal_id,
+                    tag: "work".to_string()
+                }]
+            );
+            assert!(profile.0.get_goal(goal_id).unwrap().tags().is_empty());
+            assert!(profile.handle_request(untag_request()).is_empty());
+
+            assert!(profile
+                .handle_request(GoalRequest::Tag {
+                    goal_id: GoalId(100),
+                    tag: "work".to_string(),
+                })
+                .is_empty());
+        }
+
+        #[test]
+        fn time_of_day_config_request_records_old_and_new_config() {
+            let mut profile = Profile::default();
+            let old_config = *profile.time_of_day_config();
+            let new_config = TimeOfDayConfiguration::from_start_of_midday_and_evening(
+                NaiveTime::from_hms_opt(11, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(18, 30, 0).unwrap(),
+            )
+            .unwrap();
+            let offset_config = new_config.with_utc_offset(FixedOffset::east_opt(3600));
+
+            let mut profile = profile.with_datetime(Utc::now());
+            assert_eq!(
+                profile.handle_request(GoalRequest::SetTimeOfDayConfig(new_config)),
+                vec![GoalEvent::TimeOfDayConfigChanged {
+                    old_config,
+                    new_config
+                }]
+            );
+            assert_eq!(*profile.0.time_of_day_config(), new_config);
+            assert!(profile
+                .handle_request(GoalRequest::SetTimeOfDayConfig(new_config))
+                .is_empty());
+
+            assert_eq!(
+                profile.handle_request(GoalRequest::SetTimeOfDayConfig(offset_config)),
+                vec![GoalEvent::TimeOfDayConfigChanged {
+                    old_config: new_config,
+                    new_config: offset_config
+                }]
+            );
+        }
+
+        #[test]
+        fn goals_with_tags() {
+            let mut profile = Profile::default();
+
+            let urgent_work_id = profile.add_goal(Goal::new("urgent work", 0)).unwrap();
+            let work_id = profile.add_goal(Goal::new("work", 0)).unwrap();
+            let untagged_id = profile.add_goal(Goal::new("untagged", 0)).unwrap();
+
+            profile.tag_goal(urgent_work_id, "urgent");
+            profile.tag_goal(urgent_work_id, "work");
+            profile.tag_goal(work_id, "work");
+
+            assert_eq!(
+                profile.goals_with_all_tags(&["urgent", "work"]),
+                HashSet::from([urgent_work_id])
+            );
+            assert_eq!(
+                profile.goals_with_any_tag(&["urgent", "work"]),
+                HashSet::from([urgent_work_id, work_id])
+            );
+            assert!(profile.goals_with_all_tags(&[]).contains(&untagged_id));
+            assert!(profile.goals_with_any_tag(&[]).is_empty());
+        }
+
+        #[test]
+        fn set_notes() {
+            let mut profile = Profile::default();
+            let goal_id = profile.add_goal(Goal::new("goal", 0)).unwrap();
+
+            let set_notes = GoalRequest::SetNotes {
+                goal_id,
+                notes: Some("remember to stretch".to_string()),
+            };
+
+            let mut profile = profile.with_datetime(Utc::now());
+            assert_eq!(
+                profile.handle_request(set_notes.clone()),
+                vec![GoalEvent::SetNotes {
+                    goal_id,
+                    old_notes: None,
+                    notes: Some("remember to stretch".to_string()),
+                }]
+            );
+            assert!(profile.handle_request(set_notes).is_empty());
+
+            let (populated_goal, _) = populate_goal_tree(&profile.0.goals, goal_id).unwrap();
+            assert_eq!(populated_goal.notes.as_deref(), Some("remember to stretch"));
+        }
+
+        #[test]
+        fn descendants_and_ancestors() {
+            let mut profile = Profile::default();
+
+            let root_id = profile.add_goal(Goal::new("root", 0)).unwrap();
+            let child_id = profile
+                .refine_goal(Goal::new("child", 0), root_id, 0)
+                .unwrap();
+            let grandchild_id = profile
+                .refine_goal(Goal::new("grandchild", 0), child_id, 0)
+                .unwrap();
+
+            assert_eq!(
+                profile.descendants(root_id),
+                Some(HashSet::from([child_id, grandchild_id]))
+            );
+            assert_eq!(profile.descendants(grandchild_id), Some(HashSet::new()));
+            assert_eq!(profile.descendants(GoalId(100)), None);
+
+            assert_eq!(profile.ancestors(grandchild_id), vec![child_id, root_id]);
+            assert!(profile.ancestors(root_id).is_empty());
+        }
+
+        #[test]
+        fn blocked_goals() {
+            let mut profile = Profile::default();
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let requirement_id = profile.add_goal(Goal::new("requirement", 1)).unwrap();
+            let blocked_id = profile.add_goal(Goal::new("blocked", 1)).unwrap();
+            profile.add_event(Event::instant_event(
+                start,
+                vec![
+                    GoalRelationship::Starts(blocked_id),
+                    GoalRelationship::Requires(requirement_id),
+                ],
+            ));
+
+            let mut profile = profile.with_datetime(start);
+            assert_eq!(profile.blocked_goals(), HashSet::from([blocked_id]));
+
+            profile.handle_request(GoalRequest::AddEffort {
+                goal_id: requirement_id,
+                effort: 1,
+            });
+            assert!(profile.blocked_goals().is_empty());
+        }
+
+        #[test]
+        fn populate_goals_sorted() {
+            let mut profile = Profile::default();
+            let now = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let root_id = profile.add_goal(Goal::new("root", 10)).unwrap();
+            let half_done_id = profile
+                .refine_goal(Goal::new("b half done", 4), root_id, 0)
+                .unwrap();
+            let untouched_id = profile
+                .refine_goal(Goal::new("c untouched", 2), root_id, 0)
+                .unwrap();
+            let almost_done_id = profile
+                .refine_goal(Goal::new("a almost done", 4), root_id, 0)
+                .unwrap();
+            profile.get_goal_mut(half_done_id).unwrap().add_effort(2);
+            profile.get_goal_mut(almost_done_id).unwrap().add_effort(3);
+            profile.set_goal_deadline(untouched_id, Some(now));
+            profile.set_goal_deadline(almost_done_id, Some(now + Duration::days(1)));
+
+            let child_order = |key| -> Vec<GoalId> {
+                profile.populate_goals_sorted(key)[0]
+                    .children
+                    .iter()
+                    .map(|goal| goal.id)
+                    .collect()
+            };
+
+            assert_eq!(
+                child_order(SortKey::CompletionRatio),
+                vec![untouched_id, half_done_id, almost_done_id]
+            );
+            assert_eq!(
+                child_order(SortKey::RemainingEffort),
+                vec![almost_done_id, half_done_id, untouched_id]
+            );
+            assert_eq!(
+                child_order(SortKey::Name),
+                vec![almost_done_id, half_done_id, untouched_id]
+            );
+            assert_eq!(
+                child_order(SortKey::Deadline),
+                vec![untouched_id, almost_done_id, half_done_id]
+            );
+        }
+
+        #[test]
+        fn populate_focused_goals() {
+            let mut profile = Profile::default();
+
+            let root_id = profile.add_goal(Goal::new("root", 0)).unwrap();
+            let child_id = profile
+                .refine_goal(Goal::new("child", 0), root_id, 0)
+                .unwrap();
+            let grandchild_id = profile
+                .refine_goal(Goal::new("grandchild", 0), child_id, 0)
+                .unwrap();
+            let sibling_id = profile
+                .refine_goal(Goal::new("sibling", 0), root_id, 0)
+                .unwrap();
+            profile.add_goal(Goal::new("unfocused root", 0)).unwrap();
+
+            profile.focus_goal(root_id);
+            profile.unfocus_single_goal(child_id);
+
+            let focused_goals = profile.populate_focused_goals();
+            let roots: Vec<(GoalId, Vec<GoalId>)> = focused_goals
+                .iter()
+                .map(|goal| {
+                    (
+                        goal.id,
+                        goal.children.iter().map(|child| child.id).collect(),
+                    )
+                })
+                .collect();
+
+            assert_eq!(
+                roots,
+                vec![(root_id, vec![sibling_id]), (grandchild_id, vec![])]
+            );
+        }
+
+        #[test]
+        fn populate_goals_with_focus() {
+            let mut profile = Profile::default();
+
+            let root_id = profile.add_goal(Goal::new("root", 0)).unwrap();
+            let child_id = profile
+                .refine_goal(Goal::new("child", 0), root_id, 0)
+                .unwrap();
+            profile.add_goal(Goal::new("unfocused root", 0)).unwrap();
+            profile.focus_single_goal(child_id);
+
+            let populated_goals = profile.populate_goals();
+            let focused_ids: Vec<GoalId> = populated_goals
+                .iter()
+                .flat_map(|goal| goal.flatten())
+                .filter(|(_, goal)| goal.focused)
+                .map(|(_, goal)| goal.id)
+                .collect();
+            assert_eq!(focused_ids, vec![child_id]);
+
+            let (populated_goal, _) = populate_goal_tree(&profile.goals, child_id).unwrap();
+            assert!(!populated_goal.focused);
+        }
+
+        #[test]
+        fn overdue_goals() {
+            let mut profile = Profile::default();
+            let now = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let overdue_id = profile.add_goal(Goal::new("overdue", 1)).unwrap();
+            let finished_id = profile.add_goal(Goal::new("finished", 0)).unwrap();
+            let upcoming_id = profile.add_goal(Goal::new("upcoming", 1)).unwrap();
+            profile.add_goal(Goal::new("no deadline", 1)).unwrap();
+
+            let mut profile = profile.with_datetime(now);
+            for (goal_id, deadline) in [
+                (overdue_id, now - Duration::hours(1)),
+                (finished_id, now - Duration::hours(1)),
+                (upcoming_id, now + Duration::hours(1)),
+            ] {
+                assert_eq!(
+                    profile.handle_request(GoalRequest::SetDeadline {
+                        goal_id,
+                        deadline: Some(deadline)
+                    }),
+                    vec![GoalEvent::SetDeadline {
+                        goal_id,
+                        old_deadline: None,
+                        deadline: Some(deadline),
+                    }]
+                );
+            }
+
+            assert_eq!(profile.overdue_goals(), HashSet::from([overdue_id]));
+        }
+
+        #[test]
+        fn effective_deadline() {
+            let mut profile = Profile::default();
+            let now = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let root_id = profile.add_goal(Goal::new("root", 3)).unwrap();
+            let child_id = profile
+                .refine_goal(Goal::new("child", 2), root_id, 2)
+                .unwrap();
+            let grandchild_id = profile
+                .refine_goal(Goal::new("grandchild", 1), child_id, 1)
+                .unwrap();
+            let other_root_id = profile.add_goal(Goal::new("other root", 1)).unwrap();
+
+            assert_eq!(profile.effective_deadline(root_id), None);
+            assert_eq!(profile.effective_deadline(GoalId(100)), None);
+
+            profile.set_goal_deadline(child_id, Some(now + Duration::days(2)));
+            profile.set_goal_deadline(grandchild_id, Some(now + Duration::days(1)));
+            profile.set_goal_deadline(other_root_id, Some(now));
+            assert_eq!(
+                profile.effective_deadline(root_id),
+                Some(now + Duration::days(1))
+            );
+            assert_eq!(
+                profile.effective_deadline(child_id),
+                Some(now + Duration::days(1))
+            );
+
+            profile.set_goal_deadline(root_id, Some(now + Duration::days(3)));
+            assert_eq!(
+                profile.effective_deadline(root_id),
+                Some(now + Duration::days(1))
+            );
+            profile.set_goal_deadline(root_id, Some(now + Duration::hours(1)));
+            assert_eq!(
+                profile.effective_deadline(root_id),
+                Some(now + Duration::hours(1))
+            );
+        }
+
+        #[test]
+        fn shift_deadlines() {
+            let mut profile = Profile::default();
+            let now = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let root_id = profile.add_goal(Goal::new("root", 3)).unwrap();
+            let child_id = profile
+                .refine_goal(Goal::new("child", 2), root_id, 2)
+                .unwrap();
+            let no_deadline_id = profile
+                .refine_goal(Goal::new("no deadline", 1), child_id, 1)
+                .unwrap();
+            let other_root_id = profile.add_goal(Goal::new("other root", 1)).unwrap();
+            profile.set_goal_deadline(root_id, Some(now));
+            profile.set_goal_deadline(child_id, Some(now + Duration::days(1)));
+            profile.set_goal_deadline(other_root_id, Some(now));
+
+            assert_eq!(
+                profile.shift_deadlines(root_id, Duration::days(2)),
+                vec![root_id, child_id]
+            );
+            assert_eq!(
+                profile.get_goal(root_id).unwrap().deadline(),
+                Some(now + Duration::days(2))
+            );
+            assert_eq!(
+                profile.get_goal(child_id).unwrap().deadline(),
+                Some(now + Duration::days(3))
+            );
+            assert_eq!(profile.get_goal(no_deadline_id).unwrap().deadline(), None);
+            assert_eq!(
+                profile.get_goal(other_root_id).unwrap().deadline(),
+                Some(now)
+            );
+
+            assert_eq!(
+                profile.shift_deadlines(child_id, -Duration::days(4)),
+                vec![child_id]
+            );
+            assert_eq!(
+                profile.get_goal(child_id).unwrap().deadline(),
+                Some(now - Duration::days(1))
+            );
+
+            assert!(profile
+                .shift_deadlines(root_id, Duration::zero())
+                .is_empty());
+            assert!(profile
+                .shift_deadlines(GoalId(100), Duration::days(1))
+                .is_empty());
+        }
+
+        #[test]
+        fn shift_deadlines_request() {
+            let mut profile = Profile::default();
+            let now = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let root_id = profile.add_goal(Goal::new("root", 2)).unwrap();
+            let child_id = profile
+                .refine_goal(Goal::new("child", 1), root_id, 1)
+                .unwrap();
+            profile.set_goal_deadline(root_id, Some(now));
+            profile.set_goal_deadline(child_id, Some(now + Duration::hours(1)));
+
+            let mut profile = profile.with_datetime(now);
+            let events = profile.handle_request(GoalRequest::ShiftDeadlines {
+                goal_id: root_id,
+                by: -Duration::hours(2),
+            });
+            assert_eq!(
+                events,
+                vec![
+                    GoalEvent::SetDeadline {
+                        goal_id: root_id,
+                        old_deadline: Some(now),
+                        deadline: Some(now - Duration::hours(2)),
+                    },
+                    GoalEvent::SetDeadline {
+                        goal_id: child_id,
+                        old_deadline: Some(now + Duration::hours(1)),
+                        deadline: Some(now - Duration::hours(1)),
+                    },
+                ]
+            );
+            assert!(matches!(
+                profile.preview_request(&GoalRequest::ShiftDeadlines {
+                    goal_id: GoalId(100),
+                    by: Duration::hours(1),
+                }),
+                Err(RequestError::NoSuchGoal(_))
+            ));
+        }
+
+        #[test]
+        fn goals_by_priority() {
+            let mut profile = Profile::default();
+
+            let high_id = profile.add_goal(Goal::new("high", 1)).unwrap();
+            let normal_id = profile.add_goal(Goal::new("normal", 1)).unwrap();
+            let low_id = profile.add_goal(Goal::new("low", 1)).unwrap();
+            let other_high_id = profile.add_goal(Goal::new("other high", 1)).unwrap();
+
+            let mut profile = profile.with_datetime(Utc::now());
+            for (goal_id, priority) in [
+                (high_id, Priority::High),
+                (low_id, Priority::Low),
+                (other_high_id, Priority::High),
+            ] {
+                assert_eq!(
+                    profile.handle_request(GoalRequest::SetPriority { goal_id, priority }),
+                    vec![GoalEvent::SetPriority {
+                        goal_id,
+                        old_priority: Priority::Normal,
+                        priority,
+                    }]
+                );
+            }
+            assert!(profile
+                .handle_request(GoalRequest::SetPriority {
+                    goal_id: normal_id,
+                    priority: Priority::Normal,
+                })
+                .is_empty());
+
+            let goals_by_priority: Vec<(Priority, Vec<GoalId>)> =
+                profile.goals_by_priority().into_iter().collect();
+            assert_eq!(
+                goals_by_priority,
+                vec![
+                    (Priority::Low, vec![low_id]),
+                    (Priority::Normal, vec![normal_id]),
+                    (Priority::High, vec![high_id, other_high_id]),
+                ]
+            );
+        }
+
+        #[test]
+        fn estimated_completion() {
+            let mut profile = Profile::default();
+            let now = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let goal_id = profile.add_goal(Goal::new("goal", 6)).unwrap();
+            let child_id = profile
+                .refine_goal(Goal::new("child", 4), goal_id, 4)
+                .unwrap();
+            profile.get_goal_mut(goal_id).unwrap().add_effort(1);
+            profile.get_goal_mut(child_id).unwrap().add_effort(1);
+
+            let profile = profile.with_datetime(now);
+            assert_eq!(profile.0.subtree_effort_remaining(goal_id), Some(1 + 3));
+            assert_eq!(profile.0.subtree_effort_remaining(child_id), Some(3));
+            assert_eq!(profile.0.subtree_effort_remaining(GoalId(100)), None);
+            assert_eq!(
+                profile.estimated_completion(goal_id, 2.0),
+                Some(now + Duration::days(2))
+            );
+            assert_eq!(
+                profile.estimated_completion(child_id, 4.0),
+                Some(now + Duration::hours(18))
+            );
+            assert_eq!(profile.estimated_completion(goal_id, 0.0), None);
+            assert_eq!(profile.estimated_completion(goal_id, f64::NAN), None);
+            assert_eq!(profile.estimated_completion(GoalId(100), 1.0), None);
+        }
+
+        #[test]
+        fn focus_all() {
+            let mut profile = Profile::default();
+
+            let goal_id = profile.add_goal(Goal::new("goal", 2)).unwrap();
+            let child_id = profile
+                .refine_goal(Goal::new("child", 1), goal_id, 1)
+                .unwrap();
+            let other_goal_id = profile.add_goal(Goal::new("other goal", 1)).unwrap();
+            profile.focus_single_goal(child_id);
+
+            let mut profile = profile.with_datetime(Utc::now());
+            assert_eq!(
+                profile.handle_request(GoalRequest::FocusAll),
+                vec![GoalEvent::FocusAll {
+                    focused_goals: HashSet::from([goal_id, other_goal_id])
+                }]
+            );
+            assert!(profile.handle_request(GoalRequest::FocusAll).is_empty());
+
+            assert_eq!(
+                profile.handle_request(GoalRequest::UnfocusAll),
+                vec![GoalEvent::UnfocusAll {
+                    unfocused_goals: HashSet::from([goal_id, child_id, other_goal_id])
+                }]
+            );
+            assert!(profile.0.focused_goals().is_empty());
+            assert!(profile.handle_request(GoalRequest::UnfocusAll).is_empty());
+        }
+
+        #[test]
+        fn swap_with_sibling() {
+            let mut profile = Profile::default();
+
+            let goal_id = profile.add_goal(Goal::new("goal", 3)).unwrap();
+            let first_child_id = profile
+                .refine_goal(Goal::new("first", 1), goal_id, 1)
+                .unwrap();
+            let second_child_id = profile
+                .refine_goal(Goal::new("second", 1), goal_id, 1)
+                .unwrap();
+            let other_goal_id = profile.add_goal(Goal::new("other goal", 1)).unwrap();
+
+            let mut profile = profile.with_datetime(Utc::now());
+            assert_eq!(
+                profile.preview_request(&GoalRequest::SwapWithSibling {
+                    goal_id: first_child_id,
+                    other_goal_id,
+                }),
+                Err(RequestError::GoalOperation(
+                    GoalOperationError::NoSuchChild("goal".to_string(), other_goal_id)
+                ))
+            );
+            assert_eq!(
+                profile.preview_request(&GoalRequest::SwapWithSibling {
+                    goal_id,
+                    other_goal_id,
+                }),
+                Err(RequestError::NoParent(goal_id))
+            );
+
+            assert_eq!(
+                profile.handle_request(GoalRequest::SwapWithSibling {
+                    goal_id: first_child_id,
+                    other_goal_id: second_child_id,
+                }),
+                vec![GoalEvent::SwapSiblings {
+                    parent_goal_id: goal_id,
+                    goal_id: first_child_id,
+                    other_goal_id: second_child_id,
+                }]
+            );
+            assert_eq!(
+                profile.0.get_goal(goal_id).unwrap().children(),
+                &vec![second_child_id, first_child_id]
+            );
+        }
+
+        #[test]
+        fn next_event() {
+            let mut profile = Profile::default();
+            let now = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            profile.add_event(Event::block_event(
+                now - Duration::hours(1),
+                Duration::minutes(30),
+                vec![],
+            ));
+            let instant_id =
+                profile.add_event(Event::instant_event(now + Duration::hours(2), vec![]));
+            let floating_id = profile.add_event(Event::floating_event(
+                (now + Duration::days(2)).date_naive(),
+                TimeOfDay::Midday,
+                vec![],
+            ));
+            let block_id = profile.add_event(Event::block_event(
+                now + Duration::hours(2),
+                Duration::hours(1),
+                vec![],
+            ));
+
+            assert_eq!(profile.with_datetime(now).next_event(), Some(instant_id));
+            profile.remove_event(instant_id);
+            assert_eq!(profile.with_datetime(now).next_event(), Some(block_id));
+            profile.remove_event(block_id);
+            assert_eq!(profile.with_datetime(now).next_event(), Some(floating_id));
+            profile.remove_event(floating_id);
+            assert_eq!(profile.with_datetime(now).next_event(), None);
+        }
+
+        #[test]
+        fn process_elapsed_works_on_effort() {
+            let mut profile = Profile::default();
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let goal_id = profile.add_goal(Goal::new("goal", 10)).unwrap();
+            profile.add_event(Event::block_event(
+                start,
+                Duration::hours(2),
+                vec![GoalRelationship::WorksOnWithEffort { goal_id, effort: 3 }],
+            ));
+
+            let mut process = |datetime| {
+                profile
+                    .with_datetime(datetime)
+                    .handle_request(GoalRequest::ProcessDateTime { datetime })
+            };
+
+            assert!(process(start + Duration::hours(1)).is_empty());
+            assert_eq!(process(start + Duration::hours(2)).len(), 1);
+            assert!(process(start + Duration::hours(3)).is_empty());
+
+            assert_eq!(profile.get_goal(goal_id).unwrap().effort_to_date(), 3);
+        }
+
+        #[test]
+        fn dormant_goals() {
+            let mut profile = Profile::default();
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+
+            let never_started_id = profile.add_goal(Goal::new("never started", 10)).unwrap();
+            let abandoned_early_id = profile.add_goal(Goal::new("abandoned early", 10)).unwrap();
+            let abandoned_late_id = profile.add_goal(Goal::new("abandoned late", 10)).unwrap();
+            let active_id = profile.add_goal(Goal::new("active", 10)).unwrap();
+            let finished_id = profile.add_goal(Goal::new("finished", 1)).unwrap();
+
+            for (goal_id, days) in [
+                (abandoned_early_id, 0),
+                (finished_id, 0),
+                (abandoned_late_id, 2),
+                (active_id, 9),
+            ] {
+                profile
+                    .with_datetime(start + Duration::days(days))
+                    .handle_request(GoalRequest::AddEffort { goal_id, effort: 1 });
+            }
+
+            let dormant_goals =
+                profile.dormant_goals(Duration::days(7), start + Duration::days(10));
+            assert_eq!(dormant_goals, vec![abandoned_early_id, abandoned_late_id]);
+            assert!(!dormant_goals.contains(&never_started_id));
+        }
+
+        #[test]
+        fn goal_finish_status() {
+            let mut profile = Profile::default();
+
+            let datetime = Utc.with_ymd_and_hms(2022, 1, 1, 1, 0, 0).unwrap();
+            let mut profile = profile.with_datetime(datetime);
+
+            let goal_id = profile.0.add_goal(Goal::new("test goal", 2)).unwrap();
+
+            assert!(!profile.get_goal(goal_id).unwrap().finished());
+            assert_eq!(profile.unfinished_goals(), HashSet::from([goal_id]));
+            assert_eq!(profile.finished_goals(), HashSet::from([]));
+
+            profile.get_goal_mut(goal_id).unwrap().add_effort(1);
+            assert!(!profile.get_goal(goal_id).unwrap().finished());
+            assert_eq!(profile.unfinished_goals(), HashSet::from([goal_id]));
+            assert_eq!(profile.finished_goals(), HashSet::from([]));
+
+            profile.get_goal_mut(goal_id).unwrap().add_effort(1);
+            assert!(profile.get_goal(goal_id).unwrap().finished());
+            assert_eq!(profile.unfinished_goals(), HashSet::from([]));
+            assert_eq!(profile.finished_goals(), HashSet::from([goal_id]));
+
+            profile.get_goal_mut(goal_id).unwrap().rescope(4);
+            assert!(!profile.get_goal(goal_id).unwrap().finished());
+            assert_eq!(profile.unfinished_goals(), HashSet::from([goal_id]));
+            assert_eq!(profile.finished_goals(), HashSet::from([]));
+
+            profile.get_goal_mut(goal_id).unwrap().add_effort(1);
+            assert!(!profile.get_goal(goal_id).unwrap().finished());
+            assert_eq!(profile.unfinished_goals(), HashSet::from([goal_id]));
+            assert_eq!(profile.finished_goals(), HashSet::from([]));
+
+            profile.get_goal_mut(goal_id).unwrap().add_effort(1);
+            assert!(profile.get_goal(goal_id).unwrap().finished());
+            assert_eq!(profile.unfinished_goals(), HashSet::from([]));
+            assert_eq!(profile.finished_goals(), HashSet::from([goal_id]));
+        }
+
+        #[test]
+        fn active_goals_cache_invalidation() {
+            let mut profile = Profile::default();
+
+            let datetime = Utc.with_ymd_and_hms(2022, 1, 1, 1, 0, 0).unwrap();
+
+            let goal_id = profile.add_goal(Goal::new("test goal", 1)).unwrap();
+            profile.add_event(Event::instant_event(
+                datetime - Duration::hours(2),
+                vec![GoalRelationship::Starts(goal_id)],
+            ));
+            profile.add_event(Event::instant_event(
+                datetime - Duration::hours(1),
+                vec![GoalRelationship::Ends(goal_id)],
+            ));
+
+            let mut profile = profile.with_datetime(datetime);
+            let active_goals = profile.active_goals();
+            assert_eq!(profile.active_goals_cached(), active_goals);
+
+            profile.get_goal_mut(goal_id).unwrap().add_effort(1);
+            assert_ne!(profile.active_goals(), active_goals);
+            assert_eq!(profile.active_goals_cached(), profile.active_goals());
+        }
+
+        #[test]
+        fn goal_deletion() {
+            let mut profile = Profile::default();
+
+            let datetime = Utc.with_ymd_and_hms(2022, 1, 1, 1, 0, 0).unwrap();
+
+            let profile = profile.with_datetime(datetime);
 
             let root_goal = Goal::new("root", 0);
             let first_child_goal = Goal::new("first_child", 0);
@@ -311,7 +2819,7 @@ mod tests {
             let first_grandchild_goal = Goal::new("first_grandchild_child", 0);
             let second_grandchild_goal = Goal::new("second_grandchild_child", 0);
 
-            let root_id = profile.0.add_goal(root_goal.clone());
+            let root_id = profile.0.add_goal(root_goal.clone()).unwrap();
             let first_child_id = profile
                 .0
                 .refine_goal(first_child_goal.clone(), root_id, 0)
@@ -357,4 +2865,1181 @@ mod tests {
             assert_eq!(goals_after_deletion, profile.goal_ids());
         }
     }
+
+    mod goal_effort {
+        use std::collections::{HashMap, HashSet};
+
+        use chrono::Utc;
+
+        use crate::{
+            goal::{Goal, GoalEvent, GoalId},
+            profile::{EffortReport, Profile, RefineError, RefineMode, RefineWarning, SplitError},
+            request::{GoalRequest, GoalRequestHandler, RequestError},
+        };
+
+        #[test]
+        fn set_effort() {
+            let mut profile = Profile::default();
+            let goal_id = profile.add_goal(Goal::new("goal", 5)).unwrap();
+
+            let now = Utc::now();
+            let mut profile = profile.with_datetime(now);
+            assert_eq!(
+                profile.handle_request(GoalRequest::SetEffort { goal_id, effort: 3 }),
+                vec![GoalEvent::SetEffort {
+                    goal_id,
+                    old_effort: 0,
+                    new_effort: 3,
+                    datetime: now,
+                }]
+            );
+            assert!(profile
+                .handle_request(GoalRequest::SetEffort { goal_id, effort: 3 })
+                .is_empty());
+            assert_eq!(
+                profile.handle_request(GoalRequest::SetEffort { goal_id, effort: 5 }),
+                vec![
+                    GoalEvent::SetEffort {
+                        goal_id,
+                        old_effort: 3,
+                        new_effort: 5,
+                        datetime: now,
+                    },
+                    GoalEvent::Finished { goal_id }
+                ]
+            );
+            assert_eq!(profile.0.get_goal(goal_id).unwrap().effort_to_date(), 5);
+            assert_eq!(profile.0.set_effort(GoalId(100), 1), None);
+        }
+
+        #[test]
+        fn distribute_effort() {
+            let mut profile = Profile::default();
+            let large_id = profile.add_goal(Goal::new("large", 8)).unwrap();
+            let medium_id = profile.add_goal(Goal::new("medium", 3)).unwrap();
+            let small_id = profile.add_goal(Goal::new("small", 1)).unwrap();
+            profile.get_goal_mut(large_id).unwrap().add_effort(2);
+
+            let goal_ids = HashSet::from([large_id, medium_id, small_id, GoalId(100)]);
+            assert_eq!(
+                profile.distribute_effort(7, &goal_ids),
+                HashMap::from([(large_id, 5), (medium_id, 2)])
+            );
+            assert_eq!(profile.get_goal(large_id).unwrap().effort_to_date(), 7);
+            assert_eq!(profile.get_goal(medium_id).unwrap().effort_to_date(), 2);
+            assert_eq!(profile.get_goal(small_id).unwrap().effort_to_date(), 0);
+            assert!(profile.distribute_effort(3, &HashSet::new()).is_empty());
+
+            let mut profile = profile.with_datetime(Utc::now());
+            let events = profile.handle_request(GoalRequest::DistributeEffort {
+                effort: 2,
+                goal_ids: HashSet::from([large_id, small_id]),
+            });
+            assert!(events.contains(&GoalEvent::Finished { goal_id: small_id }));
+            assert_eq!(profile.0.get_goal(large_id).unwrap().effort_to_date(), 8);
+
+            // Without any remaining effort the effort is split evenly
+            assert_eq!(
+                profile
+                    .0
+                    .distribute_effort(3, &HashSet::from([large_id, small_id])),
+                HashMap::from([(large_id, 2), (small_id, 1)])
+            );
+        }
+
+        #[test]
+        fn split_goal_into_sibling() {
+            let mut profile = Profile::default();
+            let parent_id = profile.add_goal(Goal::new("parent", 0)).unwrap();
+            let goal_id = profile
+                .refine_goal(Goal::new("goal", 10), parent_id, 0)
+                .unwrap();
+            profile.get_goal_mut(goal_id).unwrap().add_effort(4);
+
+            let mut profile = profile.with_datetime(Utc::now());
+            assert_eq!(
+                profile.preview_request(&GoalRequest::Split {
+                    goal_id,
+                    split_effort: 7,
+                    new_name: "split".to_string(),
+                }),
+                Err(RequestError::Split(SplitError::EffortExceedsRemaining {
+                    split_effort: 7,
+                    effort_remaining: 6,
+                }))
+            );
+
+            let events = profile.handle_request(GoalRequest::Split {
+                goal_id,
+                split_effort: 6,
+                new_name: "split".to_string(),
+            });
+            let split_id = profile.0.get_goal_parent_id(goal_id).and_then(|parent_id| {
+                profile
+                    .0
+                    .get_goal(parent_id)
+                    .unwrap()
+                    .children()
+                    .last()
+                    .copied()
+            });
+            let split_id = split_id.unwrap();
+            assert_eq!(
+                events,
+                vec![
+                    GoalEvent::Rescope {
+                        goal_id,
+                        new_effort_to_complete: 4,
+                        original_effort_to_complete: 10,
+                        clamped_effort: 0,
+                    },
+                    GoalEvent::Finished { goal_id },
+                    GoalEvent::Refine {
+                        parent_goal_id: parent_id,
+                        parent_effort_removed: 0,
+                        new_child_goal_id: split_id,
+                        child_name: "split".to_string(),
+                        child_effort_to_complete: 6,
+                    },
+                ]
+            );
+            assert_eq!(
+                profile.0.get_goal(parent_id).unwrap().children(),
+                &vec![goal_id, split_id]
+            );
+            assert_eq!(
+                profile.0.get_goal(split_id).unwrap().effort_to_complete(),
+                6
+            );
+        }
+
+        #[test]
+        fn refining_goal_with_effort_keeps_effort_on_parent() {
+            let mut profile = Profile::default();
+
+            let parent_id = profile.add_goal(Goal::new("parent", 5)).unwrap();
+            assert_eq!(profile.refine_warning(parent_id, 2), None);
+
+            profile.get_goal_mut(parent_id).unwrap().add_effort(3);
+            assert_eq!(
+                profile.refine_warning(parent_id, 1),
+                Some(RefineWarning::ParentHasEffort {
+                    effort_to_date: 3,
+                    finishes_parent: false,
+                })
+            );
+            assert_eq!(
+                profile.refine_warning(parent_id, 2),
+                Some(RefineWarning::ParentHasEffort {
+                    effort_to_date: 3,
+                    finishes_parent: true,
+                })
+            );
+
+            // The warning is advisory, refining still succeeds and finishes the parent
+            let child_id = profile
+                .refine_goal(Goal::new("child", 2), parent_id, 2)
+                .unwrap();
+            let parent = profile.get_goal(parent_id).unwrap();
+            assert_eq!(parent.effort_to_date(), 3);
+            assert_eq!(parent.effort_to_complete(), 3);
+            assert!(parent.finished());
+            assert_eq!(profile.get_goal(child_id).unwrap().effort_to_date(), 0);
+        }
+
+        #[test]
+        fn strict_refine_conserves_effort() {
+            let mut profile = Profile::default();
+            profile.set_refine_mode(RefineMode::Strict);
+
+            let root_id = profile.add_goal(Goal::new("root", 10)).unwrap();
+
+            assert_eq!(
+                profile.refine_goal(Goal::new("child", 3), root_id, 10),
+                Err(RefineError::EffortNotConserved {
+                    parent_effort_removed: 10,
+                    child_effort_to_complete: 3
+                })
+            );
+            assert_eq!(profile.get_goal(root_id).unwrap().effort_to_complete(), 10);
+
+            assert!(profile
+                .refine_goal(Goal::new("child", 3), root_id, 3)
+                .is_ok());
+            assert_eq!(profile.get_goal(root_id).unwrap().effort_to_complete(), 7);
+        }
+
+        #[test]
+        fn completion_events() {
+            let mut profile = Profile::default();
+            let goal_id = profile.add_goal(Goal::new("goal", 3)).unwrap();
+
+            let mut profile = profile.with_datetime(Utc::now());
+            let add_effort = GoalRequest::AddEffort { goal_id, effort: 2 };
+
+            assert!(!profile
+                .handle_request(add_effort.clone())
+                .iter()
+                .any(GoalEvent::is_completion));
+            assert!(profile
+                .handle_request(add_effort.clone())
+                .contains(&GoalEvent::Finished { goal_id }));
+            assert!(!profile
+                .handle_request(add_effort)
+                .iter()
+                .any(GoalEvent::is_completion));
+        }
+
+        #[test]
+        fn rescope_clamped() {
+            let mut profile = Profile::default();
+            let goal_id = profile.add_goal(Goal::new("goal", 10)).unwrap();
+            profile.get_goal_mut(goal_id).unwrap().add_effort(8);
+
+            let mut profile = profile.with_datetime(Utc::now());
+            assert_eq!(
+                profile.handle_request(GoalRequest::Rescope {
+                    goal_id,
+                    new_effort_to_complete: 5,
+                    clamp_effort: true,
+                }),
+                vec![
+                    GoalEvent::Rescope {
+                        goal_id,
+                        new_effort_to_complete: 5,
+                        original_effort_to_complete: 10,
+                        clamped_effort: 3,
+                    },
+                    GoalEvent::Finished { goal_id }
+                ]
+            );
+
+            let goal = profile.get_goal(goal_id).unwrap();
+            assert_eq!(goal.effort_to_date(), 5);
+            assert_eq!(goal.effort_to_complete(), 5);
+
+            profile.handle_request(GoalRequest::Rescope {
+                goal_id,
+                new_effort_to_complete: 2,
+                clamp_effort: false,
+            });
+            assert_eq!(profile.get_goal(goal_id).unwrap().effort_to_date(), 5);
+        }
+
+        #[test]
+        fn by_remaining_effort() {
+            let mut profile = Profile::default();
+
+            let root_id = profile.add_goal(Goal::new("root", 20)).unwrap();
+            let small_id = profile
+                .refine_goal(Goal::new("small", 2), root_id, 2)
+                .unwrap();
+            let large_id = profile
+                .refine_goal(Goal::new("large", 8), root_id, 8)
+                .unwrap();
+            let finished_id = profile.add_goal(Goal::new("finished", 0)).unwrap();
+            profile.get_goal_mut(large_id).unwrap().add_effort(3);
+
+            assert_eq!(
+                profile.by_remaining_effort(),
+                vec![(large_id, 5), (small_id, 2)]
+            );
+            assert!(!profile
+                .by_remaining_effort()
+                .iter()
+                .any(|&(id, _)| id == finished_id || id == root_id));
+        }
+
+        #[test]
+        fn preview_refine_request() {
+            let mut profile = Profile::default();
+            profile.set_refine_mode(RefineMode::Strict);
+
+            let root_id = profile.add_goal(Goal::new("root", 10)).unwrap();
+            let refine_request = |parent_effort_removed| GoalRequest::Refine {
+                parent_goal_id: root_id,
+                parent_effort_removed,
+                child_name: "child".to_string(),
+                child_effort_to_complete: 3,
+            };
+
+            let profile = profile.with_datetime(Utc::now());
+            assert_eq!(
+                profile.preview_request(&refine_request(10)),
+                Err(RequestError::Refine(RefineError::EffortNotConserved {
+                    parent_effort_removed: 10,
+                    child_effort_to_complete: 3
+                }))
+            );
+            assert_eq!(profile.preview_request(&refine_request(3)), Ok(()));
+            assert_eq!(
+                profile.preview_request(&GoalRequest::Delete(GoalId(100))),
+                Err(RequestError::NoSuchGoal(GoalId(100)))
+            );
+            assert_eq!(profile.get_goal(root_id).unwrap().effort_to_complete(), 10);
+        }
+
+        #[test]
+        fn refine_rejects_removing_more_than_parent_effort() {
+            let mut profile = Profile::default();
+
+            let root_id = profile.add_goal(Goal::new("root", 10)).unwrap();
+
+            assert_eq!(
+                profile.refine_goal(Goal::new("child", 100), root_id, 11),
+                Err(RefineError::EffortExceedsParent {
+                    parent_effort_removed: 11,
+                    parent_effort_to_complete: 10
+                })
+            );
+            assert_eq!(profile.get_goal(root_id).unwrap().effort_to_complete(), 10);
+            assert!(profile.get_goal(root_id).unwrap().children().is_empty());
+
+            assert!(profile
+                .refine_goal(Goal::new("child", 100), root_id, 10)
+                .is_ok());
+            assert_eq!(profile.get_goal(root_id).unwrap().effort_to_complete(), 0);
+        }
+
+        #[test]
+        fn effort_report() {
+            let mut profile = Profile::default();
+
+            let root_id = profile.add_goal(Goal::new("root", 10)).unwrap();
+            profile
+                .refine_goal(Goal::new("first child", 5), root_id, 4)
+                .unwrap();
+            profile
+                .refine_goal(Goal::new("second child", 3), root_id, 6)
+                .unwrap();
+
+            let report = profile.effort_report(root_id).unwrap();
+            assert_eq!(
+                report,
+                EffortReport {
+                    goal_id: root_id,
+                    original_effort_to_complete: 10,
+                    current_effort_to_complete: 0,
+                    delegated_to_children: 10,
+                    child_scope: 8,
+                }
+            );
+            assert!(!report.balanced());
+            assert_eq!(profile.effort_report(GoalId(100)), None);
+        }
+
+        #[test]
+        fn effort_critical_path() {
+            let mut profile = Profile::default();
+
+            let root_id = profile.add_goal(Goal::new("root", 1)).unwrap();
+            let left_id = profile
+                .refine_goal(Goal::new("left", 5), root_id, 0)
+                .unwrap();
+            let right_id = profile
+                .refine_goal(Goal::new("right", 2), root_id, 0)
+                .unwrap();
+            let right_grandchild_id = profile
+                .refine_goal(Goal::new("right grandchild", 4), right_id, 0)
+                .unwrap();
+
+            assert_eq!(
+                profile.effort_critical_path(root_id),
+                Some((vec![root_id, right_id, right_grandchild_id], 7))
+            );
+            assert_eq!(
+                profile.effort_critical_path(left_id),
+                Some((vec![left_id], 5))
+            );
+            assert_eq!(profile.effort_critical_path(GoalId(100)), None);
+        }
+    }
+
+    mod goal_ids {
+        use std::collections::HashSet;
+
+        use chrono::{Duration, TimeZone, Utc};
+
+        use crate::{
+            event::Event,
+            goal::{Goal, GoalEvent, GoalId, GoalRelationship},
+            profile::{
+                goal_traversal::populate_goal_tree, validation::ProfileInconsistency, ImportError,
+                MergeError, PopulatedGoal, Profile, ProfileDiff, ProfileStats, TimelineEntry,
+                TimelineEntryKind,
+            },
+            request::{GoalRequest, GoalRequestHandler},
+        };
+
+        #[test]
+        fn ids_counter_behind_existing_ids() {
+            let mut profile = Profile::default();
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let goal_id = profile.add_goal(Goal::new("goal", 2)).unwrap();
+            let other_goal_id = profile.add_goal(Goal::new("other goal", 2)).unwrap();
+            let event_id = profile.add_event(Event::instant_event(start, vec![]));
+
+            // As if the counters were hand-edited in a saved profile
+            let mut serialized: serde_json::Value = serde_json::to_value(&profile).unwrap();
+            serialized["goal_id_count"] = 0.into();
+            serialized["event_id_count"] = 0.into();
+            let mut profile: Profile = serde_json::from_value(serialized).unwrap();
+
+            let new_goal_id = profile.add_goal(Goal::new("new goal", 1)).unwrap();
+            let child_goal_id = profile
+                .refine_goal(Goal::new("child", 1), goal_id, 1)
+                .unwrap();
+            let new_event_id = profile.add_event(Event::instant_event(start, vec![]));
+
+            let goal_ids = HashSet::from([goal_id, other_goal_id, new_goal_id, child_goal_id]);
+            assert_eq!(goal_ids.len(), 4);
+            assert_eq!(profile.goal_count(), 4);
+            assert_ne!(new_event_id, event_id);
+            assert_eq!(profile.events.len(), 2);
+        }
+
+        #[test]
+        fn replay_largest_goal_id() {
+            let root_id = GoalId(u32::MAX - 1);
+            let child_id = GoalId(u32::MAX);
+            let mut profile = Profile::replay(&[
+                GoalEvent::Add {
+                    goal_id: root_id,
+                    name: "root".to_string(),
+                    effort_to_complete: 2,
+                },
+                GoalEvent::Refine {
+                    parent_goal_id: root_id,
+                    parent_effort_removed: 1,
+                    new_child_goal_id: child_id,
+                    child_name: "child".to_string(),
+                    child_effort_to_complete: 1,
+                },
+            ]);
+
+            assert_eq!(
+                profile.get_goal(root_id).unwrap().children(),
+                &vec![child_id]
+            );
+            assert_eq!(
+                profile.add_goal(Goal::new("wrapped", 1)).unwrap(),
+                GoalId(0)
+            );
+        }
+
+        #[test]
+        fn replay_rename_to_blank_name() {
+            let goal_id = GoalId(0);
+            let profile = Profile::replay(&[
+                GoalEvent::Add {
+                    goal_id,
+                    name: "goal".to_string(),
+                    effort_to_complete: 1,
+                },
+                GoalEvent::Rename {
+                    goal_id,
+                    old_name: "goal".to_string(),
+                    new_name: " ".to_string(),
+                },
+            ]);
+
+            assert_eq!(profile.get_goal(goal_id).unwrap().name(), " ");
+        }
+
+        #[test]
+        fn remove_goal_removes_event_relationships() {
+            let mut profile = Profile::default();
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let goal_id = profile.add_goal(Goal::new("goal", 2)).unwrap();
+            let child_goal_id = profile
+                .refine_goal(Goal::new("child", 1), goal_id, 1)
+                .unwrap();
+            let kept_goal_id = profile.add_goal(Goal::new("kept", 1)).unwrap();
+            let event_id = profile.add_event(Event::block_event(
+                start,
+                Duration::hours(1),
+                vec![
+                    GoalRelationship::Starts(goal_id),
+                    GoalRelationship::WorksOn(child_goal_id),
+                    GoalRelationship::WorksOn(kept_goal_id),
+                ],
+            ));
+
+            assert!(profile.remove_goal(goal_id).is_some());
+
+            assert!(matches!(
+                profile.get_event(event_id).unwrap().goal_relationships()[..],
+                [GoalRelationship::WorksOn(goal_id)] if goal_id == kept_goal_id
+            ));
+        }
+
+        #[test]
+        fn prune_dangling_relationships() {
+            let mut profile = Profile::default();
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let goal_id = profile.add_goal(Goal::new("goal", 1)).unwrap();
+            let event_id = profile.add_event(Event::block_event(
+                start,
+                Duration::hours(1),
+                vec![
+                    GoalRelationship::WorksOn(goal_id),
+                    GoalRelationship::Ends(GoalId(100)),
+                    GoalRelationship::Requires(GoalId(101)),
+                ],
+            ));
+
+            assert_eq!(profile.prune_dangling_relationships(), 2);
+            assert_eq!(profile.prune_dangling_relationships(), 0);
+            assert!(matches!(
+                profile.get_event(event_id).unwrap().goal_relationships()[..],
+                [GoalRelationship::WorksOn(id)] if id == goal_id
+            ));
+        }
+
+        #[test]
+        fn overlapping_block_events() {
+            let mut profile = Profile::default();
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let first_id = profile.add_event(Event::block_event(start, Duration::hours(2), vec![]));
+            let overlaps_first_id = profile.add_event(Event::block_event(
+                start + Duration::hours(1),
+                Duration::hours(2),
+                vec![],
+            ));
+            let touches_first_id = profile.add_event(Event::block_event(
+                start + Duration::hours(2),
+                Duration::minutes(30),
+                vec![],
+            ));
+            profile.add_event(Event::block_event(
+                start + Duration::hours(5),
+                Duration::hours(1),
+                vec![],
+            ));
+            profile.add_event(Event::instant_event(start + Duration::hours(1), vec![]));
+
+            assert_eq!(
+                profile.overlapping_block_events(),
+                vec![
+                    (first_id, overlaps_first_id),
+                    (overlaps_first_id, touches_first_id)
+                ]
+            );
+        }
+
+        #[test]
+        fn export_and_import_subtree() {
+            let mut profile = Profile::default();
+
+            let goal_id = profile.add_goal(Goal::new("template", 5)).unwrap();
+            let first_child_id = profile
+                .refine_goal(Goal::new("first", 2), goal_id, 2)
+                .unwrap();
+            profile
+                .refine_goal(Goal::new("second", 1), goal_id, 1)
+                .unwrap();
+            profile
+                .refine_goal(Goal::new("grandchild", 1), first_child_id, 1)
+                .unwrap();
+            profile.get_goal_mut(first_child_id).unwrap().add_effort(1);
+            profile.set_goal_notes(goal_id, Some("shared".to_string()));
+
+            let json = profile.export_subtree(goal_id).unwrap();
+            assert!(profile.export_subtree(GoalId(100)).is_none());
+
+            let mut other_profile = Profile::default();
+            let parent_id = other_profile.add_goal(Goal::new("parent", 1)).unwrap();
+            assert_eq!(
+                other_profile.import_subtree(&json, Some(GoalId(100)), false),
+                Err(ImportError::NoSuchParent(GoalId(100)))
+            );
+            assert!(matches!(
+                other_profile.import_subtree("{", None, false),
+                Err(ImportError::Malformed(_))
+            ));
+
+            let kept_id = other_profile
+                .import_subtree(&json, Some(parent_id), false)
+                .unwrap();
+            let reset_id = other_profile.import_subtree(&json, None, true).unwrap();
+
+            let parent = other_profile.get_goal(parent_id).unwrap();
+            assert_eq!(parent.children(), &vec![kept_id]);
+            assert_eq!(parent.effort_to_complete(), 1);
+
+            let flatten = |root_id| {
+                let (populated_goal, _) =
+                    populate_goal_tree(&other_profile.goals, root_id).unwrap();
+                populated_goal
+                    .flatten()
+                    .into_iter()
+                    .map(|(depth, goal)| (depth, goal.name.clone(), goal.effort_to_date))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                flatten(kept_id),
+                vec![
+                    (0, "template".to_string(), 0),
+                    (1, "first".to_string(), 1),
+                    (2, "grandchild".to_string(), 0),
+                    (1, "second".to_string(), 0),
+                ]
+            );
+            assert!(flatten(reset_id).iter().all(|(_, _, effort)| *effort == 0));
+            assert_eq!(
+                other_profile.get_goal(reset_id).unwrap().notes(),
+                Some("shared")
+            );
+        }
+
+        #[test]
+        fn export_and_import_json() {
+            let mut profile = Profile::default();
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let goal_id = profile.add_goal(Goal::new("goal", 2)).unwrap();
+            profile
+                .refine_goal(Goal::new("child", 1), goal_id, 1)
+                .unwrap();
+            profile.focus_goal(goal_id);
+            profile.add_event(Event::instant_event(
+                start,
+                vec![GoalRelationship::Requires(goal_id)],
+            ));
+
+            let imported_profile = Profile::import_json(&profile.export_json()).unwrap();
+            assert!(profile.diff(&imported_profile).is_empty());
+            assert_eq!(imported_profile.focused_goals(), profile.focused_goals());
+            assert_eq!(imported_profile.events.len(), 1);
+
+            assert!(matches!(
+                Profile::import_json("{"),
+                Err(ImportError::Malformed(_))
+            ));
+        }
+
+        #[test]
+        fn import_inconsistent_json() {
+            let mut profile = Profile::default();
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let goal_id = profile.add_goal(Goal::new("goal", 2)).unwrap();
+            let event_id = profile.add_event(Event::instant_event(
+                start,
+                vec![GoalRelationship::Requires(goal_id)],
+            ));
+
+            // As if the exported JSON was edited by hand
+            profile
+                .goals
+                .get_mut(&goal_id)
+                .unwrap()
+                .add_child(GoalId(100))
+                .unwrap();
+            profile
+                .events
+                .get_mut(&event_id)
+                .unwrap()
+                .goal_relationships_mut()
+                .push(GoalRelationship::WorksOn(GoalId(101)));
+
+            let Err(ImportError::Inconsistent(inconsistencies)) =
+                Profile::import_json(&profile.export_json())
+            else {
+                panic!("expected the import to be rejected as inconsistent");
+            };
+            assert!(
+                inconsistencies.contains(&ProfileInconsistency::MissingChild {
+                    parent_goal_id: goal_id,
+                    child_goal_id: GoalId(100),
+                })
+            );
+            assert!(
+                inconsistencies.contains(&ProfileInconsistency::DanglingEventRelationship {
+                    event_id,
+                    goal_id: GoalId(101),
+                })
+            );
+        }
+
+        #[test]
+        fn effort_unit() {
+            let mut profile = Profile::default();
+            assert_eq!(profile.effort_unit(), "");
+
+            let mut profile_at = profile.with_datetime(Utc::now());
+            let events = profile_at.handle_request(GoalRequest::SetEffortUnit("hrs".to_string()));
+            assert_eq!(
+                events,
+                vec![GoalEvent::EffortUnitChanged {
+                    old_unit: String::new(),
+                    new_unit: "hrs".to_string(),
+                }]
+            );
+            assert!(profile_at
+                .handle_request(GoalRequest::SetEffortUnit("hrs".to_string()))
+                .is_empty());
+            assert_eq!(Profile::replay(&events).effort_unit(), "hrs");
+
+            // Profiles saved before the unit existed load without one
+            let mut serialized: serde_json::Value = serde_json::to_value(&profile).unwrap();
+            serialized.as_object_mut().unwrap().remove("effort_unit");
+            let profile: Profile = serde_json::from_value(serialized).unwrap();
+            assert_eq!(profile.effort_unit(), "");
+        }
+
+        #[test]
+        fn diff() {
+            let mut profile = Profile::default();
+
+            let unchanged_id = profile.add_goal(Goal::new("unchanged", 1)).unwrap();
+            let renamed_id = profile.add_goal(Goal::new("renamed", 1)).unwrap();
+            let refined_id = profile.add_goal(Goal::new("refined", 2)).unwrap();
+            let removed_id = profile.add_goal(Goal::new("removed", 1)).unwrap();
+
+            let mut other_profile = profile.clone();
+            other_profile.rename_goal(renamed_id, "new name").unwrap();
+            let added_id = other_profile
+                .refine_goal(Goal::new("child", 1), refined_id, 1)
+                .unwrap();
+            other_profile.remove_goal(removed_id);
+
+            assert!(profile.diff(&profile).is_empty());
+
+            let diff = profile.diff(&other_profile);
+            assert_eq!(
+                diff,
+                ProfileDiff {
+                    added: vec![added_id],
+                    removed: vec![removed_id],
+                    modified: vec![renamed_id, refined_id],
+                }
+            );
+            assert!(!diff.modified.contains(&unchanged_id));
+            assert_eq!(diff.to_string(), "+ 4\n- 3\n~ 1\n~ 2\n");
+        }
+
+        #[test]
+        fn goal_timeline() {
+            let mut profile = Profile::default();
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let goal_id = profile.add_goal(Goal::new("goal", 1)).unwrap();
+            let end_id = profile.add_event(Event::instant_event(
+                start + Duration::hours(4),
+                vec![GoalRelationship::Ends(goal_id)],
+            ));
+            let work_id = profile.add_event(Event::block_event(
+                start + Duration::hours(1),
+                Duration::hours(1),
+                vec![
+                    GoalRelationship::WorksOnWithEffort { goal_id, effort: 1 },
+                    GoalRelationship::Requires(goal_id),
+                ],
+            ));
+            let start_id = profile.add_event(Event::block_event(
+                start,
+                Duration::hours(1),
+                vec![GoalRelationship::Starts(goal_id)],
+            ));
+
+            assert_eq!(
+                profile.goal_timeline(goal_id),
+                vec![
+                    TimelineEntry {
+                        event_id: start_id,
+                        kind: TimelineEntryKind::Start,
+                        datetime: start,
+                    },
+                    TimelineEntry {
+                        event_id: work_id,
+                        kind: TimelineEntryKind::WorkSession,
+                        datetime: start + Duration::hours(1),
+                    },
+                    TimelineEntry {
+                        event_id: end_id,
+                        kind: TimelineEntryKind::End,
+                        datetime: start + Duration::hours(4),
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn events_for_goal() {
+            let mut profile = Profile::default();
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let goal_id = profile.add_goal(Goal::new("goal", 1)).unwrap();
+            let other_goal_id = profile.add_goal(Goal::new("other goal", 1)).unwrap();
+            let start_event_id = profile.add_event(Event::block_event(
+                start,
+                Duration::hours(1),
+                vec![
+                    GoalRelationship::Starts(goal_id),
+                    GoalRelationship::WorksOn(other_goal_id),
+                ],
+            ));
+            let end_event_id = profile.add_event(Event::block_event(
+                start + Duration::hours(1),
+                Duration::hours(1),
+                vec![
+                    GoalRelationship::WorksOn(goal_id),
+                    GoalRelationship::Ends(goal_id),
+                ],
+            ));
+
+            let events_for_goal = profile.events_for_goal(goal_id);
+            assert!(matches!(
+                events_for_goal
+                    .iter()
+                    .map(|(event_id, _, relationship)| (*event_id, **relationship))
+                    .collect::<Vec<_>>()[..],
+                [
+                    (first_id, GoalRelationship::Starts(_)),
+                    (second_id, GoalRelationship::WorksOn(_)),
+                    (third_id, GoalRelationship::Ends(_)),
+                ] if first_id == start_event_id && second_id == end_event_id && third_id == end_event_id
+            ));
+            assert!(profile.events_for_goal(GoalId(100)).is_empty());
+        }
+
+        #[test]
+        fn find_goals_by_name() {
+            let mut profile = Profile::default();
+
+            let first_id = profile.add_goal(Goal::new("write report", 1)).unwrap();
+            let second_id = profile.add_goal(Goal::new("write", 1)).unwrap();
+            let third_id = profile.add_goal(Goal::new("write report", 1)).unwrap();
+            profile.add_goal(Goal::new("read", 1)).unwrap();
+
+            assert_eq!(
+                profile.find_goals_by_name("write report"),
+                vec![first_id, third_id]
+            );
+            assert_eq!(
+                profile.find_goals_by_name_prefix("write"),
+                vec![first_id, second_id, third_id]
+            );
+            assert!(profile.find_goals_by_name("writ").is_empty());
+        }
+
+        #[test]
+        fn statistics() {
+            let mut profile = Profile::default();
+            assert_eq!(profile.statistics(), ProfileStats::default());
+
+            let root_id = profile.add_goal(Goal::new("root", 10)).unwrap();
+            let child_id = profile
+                .refine_goal(Goal::new("child", 2), root_id, 2)
+                .unwrap();
+            profile
+                .refine_goal(Goal::new("grandchild", 1), child_id, 0)
+                .unwrap();
+            profile.add_goal(Goal::new("other root", 4)).unwrap();
+
+            profile.get_goal_mut(root_id).unwrap().add_effort(3);
+            profile.get_goal_mut(child_id).unwrap().add_effort(5);
+            profile.focus_goal(child_id);
+
+            assert_eq!(
+                profile.statistics(),
+                ProfileStats {
+                    goal_count: 4,
+                    finished_count: 1,
+                    unfinished_count: 3,
+                    total_effort_to_date: 8,
+                    total_effort_remaining: 5 + 1 + 4,
+                    max_tree_depth: 3,
+                    focused_count: 2,
+                }
+            );
+        }
+
+        #[test]
+        fn compact_ids() {
+            let mut profile = Profile::default();
+
+            let deleted_root_id = profile.add_goal(Goal::new("deleted root", 0)).unwrap();
+            let root_id = profile.add_goal(Goal::new("root", 0)).unwrap();
+            let deleted_child_id = profile
+                .refine_goal(Goal::new("deleted child", 0), root_id, 0)
+                .unwrap();
+            let child_id = profile
+                .refine_goal(Goal::new("child", 0), root_id, 0)
+                .unwrap();
+
+            let grandchild_id = profile
+                .refine_goal(Goal::new("grandchild", 3), child_id, 0)
+                .unwrap();
+            let other_root_id = profile.add_goal(Goal::new("other root", 2)).unwrap();
+            let event_id = profile.add_event(Event::instant_event(
+                Utc::now(),
+                vec![GoalRelationship::WorksOn(grandchild_id)],
+            ));
+
+            profile.remove_goal(deleted_root_id);
+            profile.remove_goal(deleted_child_id);
+            profile.focus_goal(root_id);
+
+            // The goal trees rendered without ids, in a stable order
+            fn tree_shapes(profile: &Profile) -> Vec<String> {
+                fn shape(goal: &PopulatedGoal) -> String {
+                    let children: Vec<String> = goal.children.iter().map(shape).collect();
+                    format!(
+                        "{} {}/{} {} [{}]",
+                        goal.name,
+                        goal.effort_to_date,
+                        goal.effort_to_complete,
+                        goal.focused,
+                        children.join(", ")
+                    )
+                }
+
+                let mut shapes: Vec<String> = profile.populate_goals().iter().map(shape).collect();
+                shapes.sort();
+                shapes
+            }
+            let shapes_before = tree_shapes(&profile);
+
+            let id_mapping = profile.compact_ids();
+            assert_eq!(tree_shapes(&profile), shapes_before);
+
+            let new_root_id = id_mapping[&root_id];
+            let new_child_id = id_mapping[&child_id];
+            let new_grandchild_id = id_mapping[&grandchild_id];
+
+            assert_eq!(id_mapping.len(), 4);
+            assert_eq!(
+                profile.goal_ids(),
+                (0..4).map(GoalId).collect::<HashSet<GoalId>>()
+            );
+            assert_eq!(
+                profile.get_goal(new_root_id).unwrap().children(),
+                &vec![new_child_id]
+            );
+            assert_eq!(
+                profile.focused_goals(),
+                &HashSet::from([new_root_id, new_child_id, new_grandchild_id])
+            );
+            assert!(matches!(
+                profile.get_event(event_id).unwrap().goal_relationships()[..],
+                [GoalRelationship::WorksOn(goal_id)] if goal_id == new_grandchild_id
+            ));
+            assert!(id_mapping.contains_key(&other_root_id));
+
+            let new_goal_id = profile.add_goal(Goal::new("new goal", 0)).unwrap();
+            assert!(!id_mapping.values().any(|id| *id == new_goal_id));
+        }
+
+        #[test]
+        fn merge_goal_into() {
+            let mut profile = Profile::default();
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let source_id = profile.add_goal(Goal::new("source", 10)).unwrap();
+            let source_child_id = profile
+                .refine_goal(Goal::new("source child", 0), source_id, 0)
+                .unwrap();
+            let target_id = profile.add_goal(Goal::new("target", 10)).unwrap();
+            profile.get_goal_mut(source_id).unwrap().add_effort(3);
+            profile.get_goal_mut(target_id).unwrap().add_effort(2);
+            let event_id = profile.add_event(Event::block_event(
+                start,
+                Duration::hours(1),
+                vec![GoalRelationship::WorksOn(source_id)],
+            ));
+
+            assert_eq!(
+                profile.merge_goal_into(source_id, source_child_id),
+                Err(MergeError::MergeIntoDescendant {
+                    source_goal_id: source_id,
+                    target_goal_id: source_child_id
+                })
+            );
+            assert_eq!(
+                profile.merge_goal_into(source_id, source_id),
+                Err(MergeError::MergeIntoSelf(source_id))
+            );
+
+            assert!(profile.merge_goal_into(source_id, target_id).is_ok());
+
+            let target = profile.get_goal(target_id).unwrap();
+            assert!(profile.get_goal(source_id).is_none());
+            assert_eq!(target.effort_to_date(), 5);
+            assert_eq!(target.children(), &vec![source_child_id]);
+            assert!(matches!(
+                profile.get_event(event_id).unwrap().goal_relationships()[..],
+                [GoalRelationship::WorksOn(goal_id)] if goal_id == target_id
+            ));
+        }
+
+        #[test]
+        fn merge_request_records_undo_state() {
+            let mut profile = Profile::default();
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+            let parent_id = profile.add_goal(Goal::new("parent", 2)).unwrap();
+            profile
+                .refine_goal(Goal::new("sibling", 1), parent_id, 1)
+                .unwrap();
+            let source_id = profile
+                .refine_goal(Goal::new("source", 1), parent_id, 1)
+                .unwrap();
+            let target_id = profile.add_goal(Goal::new("target", 1)).unwrap();
+            profile.collapse_goal(source_id);
+            let both_event_id = profile.add_event(Event::instant_event(
+                start,
+                vec![
+                    GoalRelationship::WorksOn(target_id),
+                    GoalRelationship::Ends(source_id),
+                    GoalRelationship::WorksOn(source_id),
+                ],
+            ));
+            let untouched_event_id = profile.add_event(Event::instant_event(
+                start,
+                vec![GoalRelationship::WorksOn(target_id)],
+            ));
+
+            let events = profile
+                .with_datetime(start)
+                .handle_request(GoalRequest::Merge {
+                    source_goal_id: source_id,
+                    target_goal_id: target_id,
+                });
+
+            let [GoalEvent::Merge {
+                source_parent_goal_id,
+                source_child_index,
+                source_was_collapsed,
+                moved_relationship_events,
+                moved_event_relationships,
+                ..
+            }] = &events[..]
+            else {
+                panic!("expected a single merge event, got {events:?}");
+            };
+            assert_eq!(*source_parent_goal_id, Some(parent_id));
+            assert_eq!(*source_child_index, Some(1));
+            assert!(*source_was_collapsed);
+            assert_eq!(moved_relationship_events, &vec![both_event_id]);
+            assert_eq!(
+                moved_event_relationships,
+                &vec![(
+                    both_event_id,
+                    vec![
+                        GoalRelationship::WorksOn(target_id),
+                        GoalRelationship::Ends(source_id),
+                        GoalRelationship::WorksOn(source_id),
+                    ]
+                )]
+            );
+
+            // The moved WorksOn duplicates the one the event already had
+            assert_eq!(
+                profile
+                    .get_event(both_event_id)
+                    .unwrap()
+                    .goal_relationships(),
+                &[
+                    GoalRelationship::WorksOn(target_id),
+                    GoalRelationship::Ends(target_id),
+                ]
+            );
+            assert_eq!(
+                profile
+                    .get_event(untouched_event_id)
+                    .unwrap()
+                    .goal_relationships(),
+                &[GoalRelationship::WorksOn(target_id)]
+            );
+            assert!(!profile.is_collapsed(source_id));
+        }
+
+        #[test]
+        fn replay_matches_live_profile() {
+            // Small linear congruential generator so that the sequence of requests is
+            // deterministic without pulling in a random number crate
+            let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+            let mut next = move |bound: u32| -> u32 {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                ((seed >> 33) % u64::from(bound)) as u32
+            };
+
+            let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+            let mut profile = Profile::default();
+            let mut history = vec![];
+
+            for step in 0..500 {
+                // Occasionally target goals that do not exist
+                let goal_id = GoalId(next(profile.goal_id_count + 2));
+                let other_goal_id = GoalId(next(profile.goal_id_count + 2));
+                let request = match next(17) {
+                    0 | 1 => GoalRequest::Create {
+                        name: format!("goal {step}"),
+                        effort_to_complete: next(20),
+                    },
+                    2 | 3 => GoalRequest::Refine {
+                        parent_goal_id: goal_id,
+                        parent_effort_removed: next(10),
+                        child_name: format!("child {step}"),
+                        child_effort_to_complete: next(10),
+                    },
+                    4 => GoalRequest::AddEffort {
+                        goal_id,
+                        effort: next(10),
+                    },
+                    5 => GoalRequest::RemoveEffort {
+                        goal_id,
+                        effort: next(10),
+                    },
+                    6 => GoalRequest::Focus(goal_id),
+                    7 => GoalRequest::Unfocus(goal_id),
+                    8 => GoalRequest::FocusSingle(goal_id),
+                    9 => GoalRequest::Rescope {
+                        goal_id,
+                        new_effort_to_complete: next(20),
+                        clamp_effort: next(2) == 0,
+                    },
+                    10 => GoalRequest::Delete(goal_id),
+                    11 => GoalRequest::Rename {
+                        goal_id,
+                        new_name: format!("renamed {step}"),
+                    },
+                    12 => GoalRequest::Merge {
+                        source_goal_id: goal_id,
+                        target_goal_id: other_goal_id,
+                    },
+                    13 => GoalRequest::FocusAll,
+                    14 => GoalRequest::UnfocusAll,
+                    15 => GoalRequest::Split {
+                        goal_id,
+                        split_effort: next(10),
+                        new_name: format!("split {step}"),
+                    },
+                    _ => GoalRequest::SetNotes {
+                        goal_id,
+                        notes: Some(format!("note {step}")),
+                    },
+                };
+
+                let datetime = start + Duration::minutes(step);
+                history.extend(profile.with_datetime(datetime).handle_request(request));
+            }
+
+            let replayed = Profile::replay(&history);
+
+            assert_eq!(replayed.goal_ids(), profile.goal_ids());
+            for goal_id in profile.goal_ids() {
+                assert_eq!(replayed.get_goal(goal_id), profile.get_goal(goal_id));
+            }
+            assert_eq!(replayed.focused_goals(), profile.focused_goals());
+        }
+    }
 }
@@ -0,0 +1,57 @@
+//! Predicates over single goals for use with
+//! [goals_matching](super::Profile::goals_matching), along with combinators to build
+//! larger predicates out of them. They only look at the goal itself, so anything that
+//! depends on events or the current time, such as whether a goal is blocked or
+//! overdue, is answered by [GoalQueryEngine](crate::query::GoalQueryEngine) on a
+//! [ProfileAndDateTime](super::ProfileAndDateTime) instead.
+
+use crate::goal::{Goal, Priority};
+
+pub fn finished() -> impl Fn(&Goal) -> bool {
+    Goal::finished
+}
+
+pub fn unfinished() -> impl Fn(&Goal) -> bool {
+    Goal::unfinished
+}
+
+pub fn tagged(tag: impl Into<String>) -> impl Fn(&Goal) -> bool {
+    let tag = tag.into();
+    move |goal| goal.has_tag(&tag)
+}
+
+/// Goals with more than `effort` effort remaining.
+pub fn effort_remaining_over(effort: u32) -> impl Fn(&Goal) -> bool {
+    move |goal| goal.effort_remaining() > effort
+}
+
+pub fn priority_at_least(priority: Priority) -> impl Fn(&Goal) -> bool {
+    move |goal| goal.priority() >= priority
+}
+
+pub fn has_deadline() -> impl Fn(&Goal) -> bool {
+    |goal| goal.deadline().is_some()
+}
+
+pub fn and<A, B>(a: A, b: B) -> impl Fn(&Goal) -> bool
+where
+    A: Fn(&Goal) -> bool,
+    B: Fn(&Goal) -> bool,
+{
+    move |goal| a(goal) && b(goal)
+}
+
+pub fn or<A, B>(a: A, b: B) -> impl Fn(&Goal) -> bool
+where
+    A: Fn(&Goal) -> bool,
+    B: Fn(&Goal) -> bool,
+{
+    move |goal| a(goal) || b(goal)
+}
+
+pub fn not<P>(predicate: P) -> impl Fn(&Goal) -> bool
+where
+    P: Fn(&Goal) -> bool,
+{
+    move |goal| !predicate(goal)
+}
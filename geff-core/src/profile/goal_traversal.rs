@@ -279,6 +279,12 @@ pub fn populated_goal_traversal_template(
         children: vec![],
         max_child_depth: 0,
         max_child_layer_width: 0,
+        selected: false,
+        collapsed: false,
+        notes: goal.notes().map(str::to_string),
+        deadline: goal.deadline(),
+        focused: false,
+        priority: goal.priority(),
     }
 }
 
@@ -290,111 +296,91 @@ pub fn populate_goal_tree(
     goals: &HashMap<GoalId, Goal>,
     goal_id: GoalId,
 ) -> Option<(PopulatedGoal, HashSet<GoalId>)> {
-    if let Some(goal) = goals.get(&goal_id) {
-        let parent_goal_id = get_goal_parent_id(goals, goal_id);
-
-        let mut root_populated_goal =
-            populated_goal_traversal_template(goal_id, goal, parent_goal_id);
-
-        let mut widths: Vec<usize> = vec![];
-        let mut add_node_to_width_vec = |node_depth: usize| {
-            let insert_index = node_depth
-                .checked_sub(1)
-                .expect("add_node_to_width_vec to only be called for non root nodes");
-            if let Some(width_counter) = widths.get_mut(insert_index) {
-                *width_counter += 1;
-            } else {
-                widths.extend((0..(insert_index - widths.len())).map(|_| 0));
-                widths.push(1);
-            }
-        };
-
-        let ids_visited = visit_goal_child_tree::<GoalChildIndexPath, _>(
-            goals,
-            goal_id,
-            &mut |parent_goal_id: GoalId,
-                  parent_index_path: &GoalChildIndexPath,
-                  child_id: GoalId,
-                  child_goal: &Goal|
-             -> GoalChildIndexPath {
-                let child_populated_goal_template =
-                    populated_goal_traversal_template(child_id, child_goal, Some(parent_goal_id));
-
-                let current_goal_populated_template = traverse_populated_goal_children_mut(
-                    &mut root_populated_goal,
-                    parent_index_path,
-                )
-                .expect("goal child index path to be valid");
-
-                let child_index_path = {
-                    let mut c = parent_index_path.clone();
-                    c.push(current_goal_populated_template.children.len());
-                    c
-                };
-
-                add_node_to_width_vec(child_index_path.len());
-
-                current_goal_populated_template
-                    .children
-                    .push(child_populated_goal_template);
+    populate_goals_with_focus(goals, goal_id, &HashSet::new())
+}
 
-                if child_goal.children().is_empty() {
-                    visit_goal_path_from(
-                        &mut root_populated_goal,
-                        parent_index_path,
-                        &mut |populated_goal, goal_path| {
-                            populated_goal.max_child_depth = usize::max(
-                                populated_goal.max_child_depth,
-                                child_index_path.len() - goal_path.len(),
-                            );
-                        },
-                    );
-                }
+/// Like [populate_goal_tree], but also marks the goals in `focused_goals` as focused.
+pub fn populate_goals_with_focus(
+    goals: &HashMap<GoalId, Goal>,
+    goal_id: GoalId,
+    focused_goals: &HashSet<GoalId>,
+) -> Option<(PopulatedGoal, HashSet<GoalId>)> {
+    if !goals.contains_key(&goal_id) {
+        return None;
+    }
+    let parent_goal_id = get_goal_parent_id(goals, goal_id);
 
-                child_index_path
-            },
-            vec![],
-        )
-        .expect("goal to be valid since it is checked before calling visit");
+    // Goals are visited with their parent and depth below the root, parents always
+    // before their children. Working with depths rather than child index paths keeps
+    // each visit constant time, even for very deep trees.
+    let mut visit_order: Vec<(GoalId, Option<GoalId>, usize)> = vec![(goal_id, parent_goal_id, 0)];
+    let ids_visited = visit_goal_child_tree(
+        goals,
+        goal_id,
+        &mut |parent_goal_id, parent_depth: &usize, child_id, _| {
+            visit_order.push((child_id, Some(parent_goal_id), parent_depth + 1));
+            parent_depth + 1
+        },
+        0,
+    )
+    .expect("goal to be valid since it is checked before calling visit");
+
+    // widths[n] is the number of goals at depth n + 1, then the maximum width of
+    // any layer at that depth or deeper
+    let mut widths: Vec<usize> = vec![];
+    for (_, _, depth) in visit_order.iter().skip(1) {
+        if widths.len() < *depth {
+            widths.resize(*depth, 0);
+        }
+        widths[depth - 1] += 1;
+    }
+    for index in (0..widths.len().saturating_sub(1)).rev() {
+        widths[index] = usize::max(widths[index], widths[index + 1]);
+    }
 
-        let propagate_max_width_back = &mut || {
-            let len = widths.len();
-            let mut max = None;
-            for width in widths[0..len].iter_mut().rev() {
-                if let Some(max) = max.as_mut() {
-                    if *width > *max {
-                        *max = *width;
-                    } else {
-                        *width = *max;
-                    }
-                } else {
-                    max = Some(*width);
-                }
-            }
-            // We need to set the width for the root since the visit does not touch the root
-            root_populated_goal.max_child_layer_width = widths
-                .first()
-                .copied()
-                .unwrap_or(root_populated_goal.children.len());
-        };
-
-        propagate_max_width_back();
-
-        visit_populated_goal_children_mut(
-            &mut root_populated_goal,
-            &mut |_, _, child_path, child_goal| {
-                child_goal.max_child_layer_width = widths
-                    .get(child_path.len())
-                    .copied()
-                    .unwrap_or(child_goal.children.len());
-            },
-            (),
+    // Build the tree from the bottom up so that every child is populated before
+    // its parent takes ownership of it
+    let mut populated_goals: HashMap<GoalId, PopulatedGoal> = HashMap::new();
+    for (current_goal_id, current_parent_goal_id, depth) in visit_order.into_iter().rev() {
+        let current_goal = goals
+            .get(&current_goal_id)
+            .expect("visited goal to be in profile");
+
+        let mut populated_goal = populated_goal_traversal_template(
+            current_goal_id,
+            current_goal,
+            current_parent_goal_id,
         );
-
-        Some((root_populated_goal, ids_visited))
-    } else {
-        None
+        populated_goal.focused = focused_goals.contains(&current_goal_id);
+
+        populated_goal.children = current_goal
+            .children()
+            .iter()
+            .map(|child_id| {
+                populated_goals
+                    .remove(child_id)
+                    .expect("children to be populated before their parent")
+            })
+            .collect();
+        populated_goal.max_child_depth = populated_goal
+            .children
+            .iter()
+            .map(|child| child.max_child_depth + 1)
+            .max()
+            .unwrap_or(0);
+        populated_goal.max_child_layer_width = widths
+            .get(depth)
+            .copied()
+            .unwrap_or(populated_goal.children.len());
+
+        populated_goals.insert(current_goal_id, populated_goal);
     }
+
+    let root_populated_goal = populated_goals
+        .remove(&goal_id)
+        .expect("root goal to be populated");
+
+    Some((root_populated_goal, ids_visited))
 }
 
 pub struct PartitionedPopulatedTree {
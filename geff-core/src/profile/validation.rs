@@ -0,0 +1,381 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{event::EventId, goal::GoalId};
+
+use super::{
+    goal_traversal::{get_goal_parent_id, get_root_goals},
+    Profile,
+};
+
+/// A structural problem with a [Profile](Profile) that the rest of the crate
+/// assumes cannot happen, typically caused by a hand-edited data file or a bug
+/// in an older version.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ProfileInconsistency {
+    #[error("goal {parent_goal_id} has nonexistent child {child_goal_id}")]
+    MissingChild {
+        parent_goal_id: GoalId,
+        child_goal_id: GoalId,
+    },
+    #[error(
+        "goal {goal_id} is a child of multiple goals {}",
+        display_goal_ids(parent_goal_ids)
+    )]
+    MultipleParents {
+        goal_id: GoalId,
+        parent_goal_ids: Vec<GoalId>,
+    },
+    #[error("goal {0} is not reachable from any root goal")]
+    UnreachableGoal(GoalId),
+    #[error("focused goal {0} does not exist")]
+    MissingFocusedGoal(GoalId),
+    #[error("event {event_id} has a relationship with nonexistent goal {goal_id}")]
+    DanglingEventRelationship { event_id: EventId, goal_id: GoalId },
+    #[error("goal id counter {goal_id_count} is not past existing goal {max_goal_id}")]
+    GoalIdCounterBehind {
+        goal_id_count: u32,
+        max_goal_id: GoalId,
+    },
+    #[error("event id counter {event_id_count} is not past existing event {max_event_id}")]
+    EventIdCounterBehind {
+        event_id_count: u32,
+        max_event_id: EventId,
+    },
+}
+
+fn display_goal_ids(goal_ids: &[GoalId]) -> String {
+    goal_ids
+        .iter()
+        .map(GoalId::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Profile {
+    fn goal_parent_ids(&self) -> HashMap<GoalId, Vec<GoalId>> {
+        let mut parent_ids: HashMap<GoalId, Vec<GoalId>> = HashMap::new();
+
+        for (&parent_goal_id, goal) in self.goals.iter() {
+            for child_goal_id in goal.children() {
+                parent_ids
+                    .entry(*child_goal_id)
+                    .or_default()
+                    .push(parent_goal_id);
+            }
+        }
+
+        for parents in parent_ids.values_mut() {
+            parents.sort();
+        }
+
+        parent_ids
+    }
+
+    /// Get the goals that cannot be reached by walking down from the root goals. Since
+    /// parents own the list of their children, a goal can only lose its place in the
+    /// tree by being part of a cycle of parents, or below one, which hides it from
+    /// [populate_goals](Profile::populate_goals). [repair](Profile::repair) promotes
+    /// such goals to roots.
+    pub fn unreachable_goals(&self) -> HashSet<GoalId> {
+        let mut reachable: HashSet<GoalId> = HashSet::new();
+        let mut needs_visiting: Vec<GoalId> = get_root_goals(&self.goals).collect();
+
+        while let Some(goal_id) = needs_visiting.pop() {
+            if !reachable.insert(goal_id) {
+                continue;
+            }
+
+            if let Some(goal) = self.goals.get(&goal_id) {
+                needs_visiting.extend(goal.children().iter().copied());
+            }
+        }
+
+        self.goal_ids().difference(&reachable).copied().collect()
+    }
+
+    /// Check the profile for structural inconsistencies, such as children or focused
+    /// goals that do not exist, goals with multiple parents, goal cycles and id counters
+    /// that could produce conflicting ids. Returns an empty list if the profile is
+    /// consistent.
+    pub fn validate(&self) -> Vec<ProfileInconsistency> {
+        let mut inconsistencies = vec![];
+
+        let mut goal_ids: Vec<GoalId> = self.goals.keys().copied().collect();
+        goal_ids.sort();
+
+        for &parent_goal_id in goal_ids.iter() {
+            for &child_goal_id in self.goals[&parent_goal_id].children() {
+                if !self.goals.contains_key(&child_goal_id) {
+                    inconsistencies.push(ProfileInconsistency::MissingChild {
+                        parent_goal_id,
+                        child_goal_id,
+                    });
+                }
+            }
+        }
+
+        let mut parent_ids: Vec<(GoalId, Vec<GoalId>)> = self
+            .goal_parent_ids()
+            .into_iter()
+            .filter(|(goal_id, parent_goal_ids)| {
+                self.goals.contains_key(goal_id) && parent_goal_ids.len() > 1
+            })
+            .collect();
+        parent_ids.sort();
+
+        inconsistencies.extend(parent_ids.into_iter().map(|(goal_id, parent_goal_ids)| {
+            ProfileInconsistency::MultipleParents {
+                goal_id,
+                parent_goal_ids,
+            }
+        }));
+
+        let mut unreachable_goals: Vec<GoalId> = self.unreachable_goals().into_iter().collect();
+        unreachable_goals.sort();
+
+        inconsistencies.extend(
+            unreachable_goals
+                .into_iter()
+                .map(ProfileInconsistency::UnreachableGoal),
+        );
+
+        let mut missing_focused_goals: Vec<GoalId> = self
+            .focused_goals
+            .iter()
+            .filter(|goal_id| !self.goals.contains_key(goal_id))
+            .copied()
+            .collect();
+        missing_focused_goals.sort();
+
+        inconsistencies.extend(
+            missing_focused_goals
+                .into_iter()
+                .map(ProfileInconsistency::MissingFocusedGoal),
+        );
+
+        let mut event_ids: Vec<EventId> = self.events.keys().copied().collect();
+        event_ids.sort();
+
+        for &event_id in event_ids.iter() {
+            for relationship in self.events[&event_id].goal_relationships() {
+                let goal_id = relationship.goal_id();
+                if !self.goals.contains_key(&goal_id) {
+                    inconsistencies.push(ProfileInconsistency::DanglingEventRelationship {
+                        event_id,
+                        goal_id,
+                    });
+                }
+            }
+        }
+
+        if let Some(&max_goal_id) = goal_ids.last() {
+            if self.goal_id_count <= max_goal_id.0 {
+                inconsistencies.push(ProfileInconsistency::GoalIdCounterBehind {
+                    goal_id_count: self.goal_id_count,
+                    max_goal_id,
+                });
+            }
+        }
+
+        if let Some(&max_event_id) = event_ids.last() {
+            if self.event_id_count <= max_event_id.0 {
+                inconsistencies.push(ProfileInconsistency::EventIdCounterBehind {
+                    event_id_count: self.event_id_count,
+                    max_event_id,
+                });
+            }
+        }
+
+        inconsistencies
+    }
+
+    /// Fix every inconsistency reported by [validate](Profile::validate) and return the
+    /// inconsistencies that were found. Nonexistent children, focused goals and event
+    /// relationships are removed, goals with multiple parents are kept only under the
+    /// parent with the lowest id, goal cycles are broken by promoting a goal in the cycle
+    /// to a root and id counters are moved past the largest existing id.
+    pub fn repair(&mut self) -> Vec<ProfileInconsistency> {
+        let inconsistencies = self.validate();
+        self.invalidate_active_goals_cache();
+
+        let existing_goal_ids = self.goal_ids();
+        for goal in self.goals.values_mut() {
+            goal.retain_children(|child_goal_id| existing_goal_ids.contains(child_goal_id));
+        }
+
+        for (goal_id, parent_goal_ids) in self.goal_parent_ids() {
+            for parent_goal_id in parent_goal_ids.iter().skip(1) {
+                if let Some(parent_goal) = self.goals.get_mut(parent_goal_id) {
+                    parent_goal.remove_child(goal_id);
+                }
+            }
+        }
+
+        // Every goal now has at most one parent, so walking up the parents of an
+        // unreachable goal must eventually revisit a goal that is part of a cycle,
+        // which promoting to a root will break.
+        while let Some(unreachable_goal_id) = self.unreachable_goals().into_iter().min() {
+            let mut visited = HashSet::new();
+            let mut goal_id = unreachable_goal_id;
+            while visited.insert(goal_id) {
+                goal_id = get_goal_parent_id(&self.goals, goal_id)
+                    .expect("unreachable goal to have a parent");
+            }
+
+            if let Some(parent_goal_id) = get_goal_parent_id(&self.goals, goal_id) {
+                if let Some(parent_goal) = self.goals.get_mut(&parent_goal_id) {
+                    parent_goal.remove_child(goal_id);
+                }
+            }
+        }
+
+        self.focused_goals
+            .retain(|goal_id| existing_goal_ids.contains(goal_id));
+
+        for event in self.events.values_mut() {
+            event
+                .goal_relationships_mut()
+                .retain(|relationship| existing_goal_ids.contains(&relationship.goal_id()));
+        }
+
+        if let Some(max_goal_id) = self.goals.keys().max() {
+            self.goal_id_count = u32::max(self.goal_id_count, max_goal_id.0 + 1);
+        }
+
+        if let Some(max_event_id) = self.events.keys().max() {
+            self.event_id_count = u32::max(self.event_id_count, max_event_id.0 + 1);
+        }
+
+        inconsistencies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        event::{Event, EventId},
+        goal::{Goal, GoalId, GoalRelationship},
+        profile::Profile,
+    };
+
+    use super::ProfileInconsistency;
+
+    #[test]
+    fn validate_and_repair() {
+        let mut profile = Profile::default();
+
+        let root_id = profile.add_goal(Goal::new("root", 0)).unwrap();
+        let first_cycle_id = profile.add_goal(Goal::new("first cycle", 0)).unwrap();
+        let second_cycle_id = profile.add_goal(Goal::new("second cycle", 0)).unwrap();
+        let missing_id = GoalId(100);
+
+        assert!(profile.validate().is_empty());
+
+        let event_id = profile.add_event(Event::instant_event(
+            chrono::Utc::now(),
+            vec![GoalRelationship::Starts(missing_id)],
+        ));
+
+        profile
+            .get_goal_mut(root_id)
+            .unwrap()
+            .refine(missing_id, 0)
+            .unwrap();
+        profile
+            .get_goal_mut(first_cycle_id)
+            .unwrap()
+            .refine(second_cycle_id, 0)
+            .unwrap();
+        profile
+            .get_goal_mut(second_cycle_id)
+            .unwrap()
+            .refine(first_cycle_id, 0)
+            .unwrap();
+        profile.focused_goals.insert(missing_id);
+        profile.goal_id_count = 0;
+        profile.event_id_count = 0;
+
+        let inconsistencies = vec![
+            ProfileInconsistency::MissingChild {
+                parent_goal_id: root_id,
+                child_goal_id: missing_id,
+            },
+            ProfileInconsistency::UnreachableGoal(first_cycle_id),
+            ProfileInconsistency::UnreachableGoal(second_cycle_id),
+            ProfileInconsistency::MissingFocusedGoal(missing_id),
+            ProfileInconsistency::DanglingEventRelationship {
+                event_id,
+                goal_id: missing_id,
+            },
+            ProfileInconsistency::GoalIdCounterBehind {
+                goal_id_count: 0,
+                max_goal_id: second_cycle_id,
+            },
+            ProfileInconsistency::EventIdCounterBehind {
+                event_id_count: 0,
+                max_event_id: EventId(0),
+            },
+        ];
+
+        assert_eq!(profile.validate(), inconsistencies);
+        assert_eq!(profile.repair(), inconsistencies);
+        assert!(profile.validate().is_empty());
+        assert_eq!(profile.populate_goals().len(), 2);
+    }
+
+    #[test]
+    fn inconsistency_messages() {
+        assert_eq!(
+            ProfileInconsistency::MultipleParents {
+                goal_id: GoalId(3),
+                parent_goal_ids: vec![GoalId(1), GoalId(2)],
+            }
+            .to_string(),
+            "goal #3 is a child of multiple goals #1, #2"
+        );
+        assert_eq!(
+            ProfileInconsistency::DanglingEventRelationship {
+                event_id: EventId(0),
+                goal_id: GoalId(4),
+            }
+            .to_string(),
+            "event #0 has a relationship with nonexistent goal #4"
+        );
+    }
+
+    #[test]
+    fn unreachable_goals() {
+        let mut profile = Profile::default();
+
+        let root_id = profile.add_goal(Goal::new("root", 0)).unwrap();
+        let parent_id = profile
+            .refine_goal(Goal::new("parent", 0), root_id, 0)
+            .unwrap();
+        let child_id = profile
+            .refine_goal(Goal::new("child", 0), parent_id, 0)
+            .unwrap();
+        assert!(profile.unreachable_goals().is_empty());
+
+        // Strand the parent and its child below a cycle, as a bug moving goals might
+        profile
+            .get_goal_mut(root_id)
+            .unwrap()
+            .remove_child(parent_id);
+        profile
+            .get_goal_mut(child_id)
+            .unwrap()
+            .refine(parent_id, 0)
+            .unwrap();
+        assert_eq!(
+            profile.unreachable_goals(),
+            HashSet::from([parent_id, child_id])
+        );
+        assert_eq!(profile.populate_goals().len(), 1);
+
+        profile.repair();
+        assert!(profile.unreachable_goals().is_empty());
+        assert_eq!(profile.goal_count(), 3);
+    }
+}
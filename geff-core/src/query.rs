@@ -1,21 +1,25 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
-use chrono::NaiveTime;
+use chrono::{DateTime, DurationRound, FixedOffset, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
     event::{EventId, TimeOfDay},
-    goal::GoalId,
+    goal::{GoalId, GoalRelationship, Priority},
     profile::ProfileAndDateTime,
 };
 
 use self::event_query_helpers::{event_ended, event_not_started, event_occuring};
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
 pub struct TimeOfDayConfiguration {
     midday_start: NaiveTime,
     evening_start: NaiveTime,
+    /// The offset from UTC, in seconds, that dates and times of day are taken to be
+    /// in. The system's local timezone is used when this is not set.
+    #[serde(default)]
+    utc_offset_seconds: Option<i32>,
 }
 
 #[derive(Error, Debug, Clone, Copy)]
@@ -38,6 +42,7 @@ impl TimeOfDayConfiguration {
             Ok(TimeOfDayConfiguration {
                 midday_start,
                 evening_start,
+                utc_offset_seconds: None,
             })
         } else {
             Err(TimeOfDayCreationError::SuppliedMiddayIsAfterEvening {
@@ -47,6 +52,30 @@ impl TimeOfDayConfiguration {
         }
     }
 
+    /// Pin dates and times of day to a fixed offset from UTC rather than the system's
+    /// local timezone, or go back to the local timezone with `None`.
+    pub fn with_utc_offset(self, utc_offset: Option<FixedOffset>) -> TimeOfDayConfiguration {
+        TimeOfDayConfiguration {
+            utc_offset_seconds: utc_offset.map(|offset| offset.local_minus_utc()),
+            ..self
+        }
+    }
+
+    /// The fixed offset from UTC that dates and times of day are taken to be in, or
+    /// `None` if the system's local timezone is used.
+    pub fn utc_offset(&self) -> Option<FixedOffset> {
+        self.utc_offset_seconds.and_then(FixedOffset::east_opt)
+    }
+
+    /// The time a time of day starts at, mornings start at midnight.
+    pub fn start_of(&self, time_of_day: TimeOfDay) -> NaiveTime {
+        match time_of_day {
+            TimeOfDay::Morning => NaiveTime::default(),
+            TimeOfDay::Midday => self.midday_start,
+            TimeOfDay::Evening => self.evening_start,
+        }
+    }
+
     pub fn map_time(&self, time: NaiveTime) -> TimeOfDay {
         if time < self.midday_start {
             TimeOfDay::Morning
@@ -63,76 +92,177 @@ impl Default for TimeOfDayConfiguration {
         Self {
             midday_start: NaiveTime::from_hms_opt(12, 0, 0).expect("12h to be less than 24h"),
             evening_start: NaiveTime::from_hms_opt(18, 0, 0).expect("18h to be less than 24h"),
+            utc_offset_seconds: None,
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub(crate) struct ActiveGoalsCache {
+    minute: DateTime<Utc>,
+    active_goals: HashSet<GoalId>,
+}
+
 pub trait EventQueryEngine {
     fn currently_occuring_events(&self) -> HashSet<EventId>;
 
     fn past_events(&self) -> HashSet<EventId>;
     fn future_events(&self) -> HashSet<EventId>;
     fn event_ids(&self) -> HashSet<EventId>;
+
+    /// When an event starts. Floating events start at the start of their time of day.
+    fn event_start(&self, event_id: EventId) -> Option<DateTime<Utc>>;
+
+    /// The future event that starts first, ties are broken by the smallest event id.
+    fn next_event(&self) -> Option<EventId> {
+        self.future_events()
+            .into_iter()
+            .filter_map(|event_id| Some((self.event_start(event_id)?, event_id)))
+            .min()
+            .map(|(_, event_id)| event_id)
+    }
 }
 
+/// Block and instant events happen at fixed instants, but floating events happen on a
+/// date and time of day which are taken to be in the configured timezone, falling back
+/// to the local timezone. Each helper has a `_in` variant taking the timezone
+/// explicitly so that the day boundary can be tested independently of the machine
+/// running the tests.
 pub mod event_query_helpers {
 
-    use chrono::{DateTime, Local, Utc};
+    use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
 
-    use crate::event::Event;
+    use crate::event::{Event, FloatingEvent, TimeOfDay};
 
     use super::TimeOfDayConfiguration;
 
-    pub fn event_not_started(
+    /// The date and time of day of `reference` in the timezone `tz`.
+    fn date_and_time_of_day_in<Tz: TimeZone>(
+        time_of_day_config: &TimeOfDayConfiguration,
+        reference: DateTime<Utc>,
+        tz: &Tz,
+    ) -> (NaiveDate, TimeOfDay) {
+        let local_reference = reference.with_timezone(tz).naive_local();
+
+        (
+            local_reference.date(),
+            time_of_day_config.map_time(local_reference.time()),
+        )
+    }
+
+    /// When a floating event starts in the timezone `tz`, or `None` if its start does
+    /// not exist in that timezone.
+    pub fn floating_event_start_in<Tz: TimeZone>(
+        time_of_day_config: &TimeOfDayConfiguration,
+        event: &FloatingEvent,
+        tz: &Tz,
+    ) -> Option<DateTime<Utc>> {
+        tz.from_local_datetime(
+            &event
+                .date
+                .and_time(time_of_day_config.start_of(event.time_of_day)),
+        )
+        .earliest()
+        .map(|start| start.with_timezone(&Utc))
+    }
+
+    pub fn floating_event_start(
+        time_of_day_config: &TimeOfDayConfiguration,
+        event: &FloatingEvent,
+    ) -> Option<DateTime<Utc>> {
+        match time_of_day_config.utc_offset() {
+            Some(offset) => floating_event_start_in(time_of_day_config, event, &offset),
+            None => floating_event_start_in(time_of_day_config, event, &Local),
+        }
+    }
+
+    pub fn event_not_started_in<Tz: TimeZone>(
         time_of_day_config: &TimeOfDayConfiguration,
         reference: DateTime<Utc>,
         event: &Event,
+        tz: &Tz,
     ) -> bool {
         match event {
-            Event::BlockEvent(event) => {
-                let local_event_start = event.start.with_timezone(&Local);
-
-                reference < local_event_start
-            }
-            Event::InstantEvent(event) => {
-                let local_event_time = event.time.with_timezone(&Local);
-
-                reference < local_event_time
+            Event::BlockEvent(event) => reference < event.start,
+            Event::InstantEvent(event) => reference < event.time,
+            Event::FloatingEvent(event) => {
+                let (reference_date, reference_time_of_day) =
+                    date_and_time_of_day_in(time_of_day_config, reference, tz);
+
+                match reference_date.cmp(&event.date) {
+                    std::cmp::Ordering::Equal => {
+                        !reference_time_of_day.during_or_after(event.time_of_day)
+                    }
+                    std::cmp::Ordering::Less => true,
+                    std::cmp::Ordering::Greater => false,
+                }
             }
-            Event::FloatingEvent(event) => match reference.date_naive().cmp(&event.date) {
-                std::cmp::Ordering::Equal => {
-                    let reference_time_of_day = time_of_day_config.map_time(reference.time());
+        }
+    }
 
-                    !reference_time_of_day.during_or_after(event.time_of_day)
-                }
-                std::cmp::Ordering::Less => true,
-                std::cmp::Ordering::Greater => false,
-            },
+    pub fn event_not_started(
+        time_of_day_config: &TimeOfDayConfiguration,
+        reference: DateTime<Utc>,
+        event: &Event,
+    ) -> bool {
+        match time_of_day_config.utc_offset() {
+            Some(offset) => event_not_started_in(time_of_day_config, reference, event, &offset),
+            None => event_not_started_in(time_of_day_config, reference, event, &Local),
         }
     }
 
-    pub fn event_occuring(
+    pub fn event_occuring_in<Tz: TimeZone>(
         time_of_day_config: &TimeOfDayConfiguration,
         reference: DateTime<Utc>,
         event: &Event,
+        tz: &Tz,
     ) -> bool {
         match event {
             Event::BlockEvent(event) => {
-                let event_end = event.start + event.duration;
-                let local_event_start = event.start.with_timezone(&Local);
-                let local_event_end = event_end.with_timezone(&Local);
-
-                reference <= local_event_end && reference >= local_event_start
+                reference <= event.start + event.duration && reference >= event.start
             }
             Event::InstantEvent(_) => false,
-            Event::FloatingEvent(event) => match reference.date_naive().cmp(&event.date) {
-                std::cmp::Ordering::Equal => {
-                    let reference_time_of_day = time_of_day_config.map_time(reference.time());
+            Event::FloatingEvent(event) => {
+                let (reference_date, reference_time_of_day) =
+                    date_and_time_of_day_in(time_of_day_config, reference, tz);
+
+                reference_date == event.date && reference_time_of_day == event.time_of_day
+            }
+        }
+    }
+
+    pub fn event_occuring(
+        time_of_day_config: &TimeOfDayConfiguration,
+        reference: DateTime<Utc>,
+        event: &Event,
+    ) -> bool {
+        match time_of_day_config.utc_offset() {
+            Some(offset) => event_occuring_in(time_of_day_config, reference, event, &offset),
+            None => event_occuring_in(time_of_day_config, reference, event, &Local),
+        }
+    }
 
-                    reference_time_of_day == event.time_of_day
+    pub fn event_ended_in<Tz: TimeZone>(
+        time_of_day_config: &TimeOfDayConfiguration,
+        reference: DateTime<Utc>,
+        event: &Event,
+        tz: &Tz,
+    ) -> bool {
+        match event {
+            Event::BlockEvent(event) => reference > event.start + event.duration,
+            Event::InstantEvent(event) => reference >= event.time,
+            Event::FloatingEvent(event) => {
+                let (reference_date, reference_time_of_day) =
+                    date_and_time_of_day_in(time_of_day_config, reference, tz);
+
+                match reference_date.cmp(&event.date) {
+                    std::cmp::Ordering::Less => false,
+                    std::cmp::Ordering::Equal => {
+                        reference_time_of_day.during_or_after(event.time_of_day)
+                    }
+                    std::cmp::Ordering::Greater => true,
                 }
-                _ => false,
-            },
+            }
         }
     }
 
@@ -141,66 +271,158 @@ pub mod event_query_helpers {
         reference: DateTime<Utc>,
         event: &Event,
     ) -> bool {
-        match event {
-            Event::BlockEvent(event) => {
-                let event_end = event.start + event.duration;
-                let local_event_end = event_end.with_timezone(&Local);
+        match time_of_day_config.utc_offset() {
+            Some(offset) => event_ended_in(time_of_day_config, reference, event, &offset),
+            None => event_ended_in(time_of_day_config, reference, event, &Local),
+        }
+    }
 
-                reference > local_event_end
-            }
-            Event::InstantEvent(event) => {
-                let local_event_time = event.time.with_timezone(&Local);
+    #[cfg(test)]
+    mod tests {
+        use chrono::{FixedOffset, NaiveDate, TimeZone, Utc};
+
+        use crate::{
+            event::{Event, FloatingEvent, TimeOfDay},
+            query::TimeOfDayConfiguration,
+        };
+
+        use super::{
+            event_ended_in, event_not_started_in, event_occuring, event_occuring_in,
+            floating_event_start_in,
+        };
+
+        #[test]
+        fn floating_events_use_local_day() {
+            let config = TimeOfDayConfiguration::default();
+            // 23:00 on the 1st in UTC is already 08:00 on the 2nd at UTC+9
+            let reference = Utc.with_ymd_and_hms(2023, 1, 1, 23, 0, 0).unwrap();
+            let utc = FixedOffset::east_opt(0).unwrap();
+            let utc_plus_nine = FixedOffset::east_opt(9 * 3600).unwrap();
+
+            let second_morning = Event::floating_event(
+                NaiveDate::from_ymd_opt(2023, 1, 2).unwrap(),
+                TimeOfDay::Morning,
+                vec![],
+            );
+            assert!(event_not_started_in(
+                &config,
+                reference,
+                &second_morning,
+                &utc
+            ));
+            assert!(!event_occuring_in(
+                &config,
+                reference,
+                &second_morning,
+                &utc
+            ));
+            assert!(!event_not_started_in(
+                &config,
+                reference,
+                &second_morning,
+                &utc_plus_nine
+            ));
+            assert!(event_occuring_in(
+                &config,
+                reference,
+                &second_morning,
+                &utc_plus_nine
+            ));
+
+            let first_evening = Event::floating_event(
+                NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(),
+                TimeOfDay::Evening,
+                vec![],
+            );
+            assert!(event_occuring_in(&config, reference, &first_evening, &utc));
+            assert!(event_ended_in(
+                &config,
+                reference,
+                &first_evening,
+                &utc_plus_nine
+            ));
+        }
 
-                reference >= local_event_time
-            }
-            Event::FloatingEvent(event) => match reference.date_naive().cmp(&event.date) {
-                std::cmp::Ordering::Less => false,
-                std::cmp::Ordering::Equal => {
-                    let reference_time_of_day = time_of_day_config.map_time(reference.time());
+        #[test]
+        fn floating_event_start() {
+            let config = TimeOfDayConfiguration::default();
+            let utc_plus_nine = FixedOffset::east_opt(9 * 3600).unwrap();
+
+            let second_midday = FloatingEvent {
+                date: NaiveDate::from_ymd_opt(2023, 1, 2).unwrap(),
+                time_of_day: TimeOfDay::Midday,
+                goal_relationships: vec![],
+            };
+
+            assert_eq!(
+                floating_event_start_in(&config, &second_midday, &utc_plus_nine),
+                Some(Utc.with_ymd_and_hms(2023, 1, 2, 3, 0, 0).unwrap())
+            );
+        }
 
-                    reference_time_of_day.during_or_after(event.time_of_day)
-                }
-                std::cmp::Ordering::Greater => true,
-            },
+        #[test]
+        fn configured_utc_offset() {
+            let reference = Utc.with_ymd_and_hms(2023, 1, 1, 23, 0, 0).unwrap();
+            let second_morning = Event::floating_event(
+                NaiveDate::from_ymd_opt(2023, 1, 2).unwrap(),
+                TimeOfDay::Morning,
+                vec![],
+            );
+
+            let utc_config = TimeOfDayConfiguration::default()
+                .with_utc_offset(Some(FixedOffset::east_opt(0).unwrap()));
+            let utc_plus_nine_config = TimeOfDayConfiguration::default()
+                .with_utc_offset(Some(FixedOffset::east_opt(9 * 3600).unwrap()));
+
+            assert!(!event_occuring(&utc_config, reference, &second_morning));
+            assert!(event_occuring(
+                &utc_plus_nine_config,
+                reference,
+                &second_morning
+            ));
+            assert_eq!(
+                utc_plus_nine_config.with_utc_offset(None),
+                TimeOfDayConfiguration::default()
+            );
         }
     }
 }
 
 pub mod goal_query_helpers {
+    use std::collections::HashSet;
+
     use crate::{
         event::Event,
         goal::{GoalId, GoalRelationship},
     };
 
+    fn first_event_with_relationship<'a, E: Iterator<Item = &'a Event>>(
+        mut events: E,
+        mut predicate: impl FnMut(&GoalRelationship) -> bool,
+    ) -> Option<Event> {
+        events
+            .find(|event| event.goal_relationships().iter().any(&mut predicate))
+            .cloned()
+    }
+
     pub fn goal_start_event<'a, E: Iterator<Item = &'a Event>>(
         goal_id: GoalId,
         events: E,
     ) -> Option<Event> {
-        for event in events {
-            for relationship in event.goal_relationships() {
-                match relationship {
-                    GoalRelationship::Starts(id) if *id == goal_id => return Some(event.clone()),
-                    _ => {}
-                }
-            }
-        }
-
-        None
+        first_event_with_relationship(
+            events,
+            |relationship| matches!(relationship, GoalRelationship::Starts(id) if *id == goal_id),
+        )
     }
+
     pub fn goal_end_event<'a, E: Iterator<Item = &'a Event>>(
         goal_id: GoalId,
         events: E,
     ) -> Option<Event> {
-        for event in events {
-            for relationship in event.goal_relationships() {
-                match relationship {
-                    GoalRelationship::Ends(id) if *id == goal_id => return Some(event.clone()),
-                    _ => {}
-                }
-            }
-        }
-
-        None
+        first_event_with_relationship(
+            events,
+            |relationship| matches!(relationship, GoalRelationship::Ends(id) if *id == goal_id),
+        )
     }
 
     pub fn goal_has_end<'a, E: Iterator<Item = &'a Event>>(goal_id: GoalId, events: E) -> bool {
@@ -210,6 +432,35 @@ pub mod goal_query_helpers {
     pub fn goal_has_start<'a, E: Iterator<Item = &'a Event>>(goal_id: GoalId, events: E) -> bool {
         goal_start_event(goal_id, events).is_some()
     }
+
+    /// Collect the goals required by a goal. A goal requires every goal that is the
+    /// subject of a [Requires](GoalRelationship::Requires) relationship on an event the
+    /// goal is otherwise related to.
+    pub fn goal_requirements<'a, E: Iterator<Item = &'a Event>>(
+        goal_id: GoalId,
+        events: E,
+    ) -> HashSet<GoalId> {
+        let mut requirements = HashSet::new();
+
+        for event in events {
+            let relationships = event.goal_relationships();
+            let relates_to_goal = relationships.iter().any(|relationship| {
+                !matches!(relationship, GoalRelationship::Requires(_))
+                    && relationship.goal_id() == goal_id
+            });
+
+            if relates_to_goal {
+                requirements.extend(relationships.iter().filter_map(|relationship| {
+                    match relationship {
+                        GoalRelationship::Requires(id) if *id != goal_id => Some(*id),
+                        _ => None,
+                    }
+                }));
+            }
+        }
+
+        requirements
+    }
 }
 
 pub trait GoalQueryEngine {
@@ -228,6 +479,16 @@ pub trait GoalQueryEngine {
             .collect()
     }
 
+    /// Overdue goals are unfinished goals whose deadline has passed.
+    fn overdue_goals(&self) -> HashSet<GoalId> {
+        let unfinished_goals = self.unfinished_goals();
+
+        self.past_deadline_goals()
+            .into_iter()
+            .filter(|g| unfinished_goals.contains(g))
+            .collect()
+    }
+
     fn inactive_goals(&self) -> HashSet<GoalId> {
         let active_goals = self.active_goals();
         self.goal_ids()
@@ -240,6 +501,13 @@ pub trait GoalQueryEngine {
     fn finished_goals(&self) -> HashSet<GoalId>;
     fn ended_goals(&self) -> HashSet<GoalId>;
     fn started_goals(&self) -> HashSet<GoalId>;
+    /// Blocked goals are goals that require at least one unfinished goal, see
+    /// [goal_requirements](goal_query_helpers::goal_requirements).
+    fn blocked_goals(&self) -> HashSet<GoalId>;
+    /// Goals with a [deadline](crate::goal::Goal::deadline) before the reference datetime.
+    fn past_deadline_goals(&self) -> HashSet<GoalId>;
+    /// Goals grouped by [priority](crate::goal::Goal::priority), each group sorted by id.
+    fn goals_by_priority(&self) -> BTreeMap<Priority, Vec<GoalId>>;
 
     fn goal_ids(&self) -> HashSet<GoalId>;
 }
@@ -268,12 +536,13 @@ impl GoalQueryEngine for ProfileAndDateTime<'_> {
             .goals
             .iter()
             .filter(|(&id, _)| {
-                match goal_query_helpers::goal_end_event(id, self.0.events.values()) {
-                    Some(goal_end_event) => {
-                        event_ended(&self.0.time_of_day_config, self.1, &goal_end_event)
-                    }
-                    None => false,
-                }
+                self.0
+                    .events_for_goal(id)
+                    .into_iter()
+                    .find(|(_, _, relationship)| matches!(relationship, GoalRelationship::Ends(_)))
+                    .is_some_and(|(_, goal_end_event, _)| {
+                        event_ended(&self.0.time_of_day_config, self.1, goal_end_event)
+                    })
             })
             .map(|(&id, _)| id)
             .collect()
@@ -284,17 +553,63 @@ impl GoalQueryEngine for ProfileAndDateTime<'_> {
             .goals
             .iter()
             .filter(|(&id, _)| {
-                match goal_query_helpers::goal_start_event(id, self.0.events.values()) {
-                    Some(goal_end_event) => {
-                        !event_not_started(&self.0.time_of_day_config, self.1, &goal_end_event)
-                    }
-                    None => false,
-                }
+                self.0
+                    .events_for_goal(id)
+                    .into_iter()
+                    .find(|(_, _, relationship)| {
+                        matches!(relationship, GoalRelationship::Starts(_))
+                    })
+                    .is_some_and(|(_, goal_start_event, _)| {
+                        !event_not_started(&self.0.time_of_day_config, self.1, goal_start_event)
+                    })
             })
             .map(|(&id, _)| id)
             .collect()
     }
 
+    fn blocked_goals(&self) -> HashSet<GoalId> {
+        self.0
+            .goals
+            .keys()
+            .filter(|&&id| {
+                goal_query_helpers::goal_requirements(id, self.0.events.values())
+                    .into_iter()
+                    .any(|required_id| {
+                        self.0
+                            .goals
+                            .get(&required_id)
+                            .is_some_and(|goal| goal.unfinished())
+                    })
+            })
+            .copied()
+            .collect()
+    }
+
+    fn past_deadline_goals(&self) -> HashSet<GoalId> {
+        self.0
+            .goals
+            .iter()
+            .filter(|(_, goal)| goal.deadline().is_some_and(|deadline| deadline < self.1))
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    fn goals_by_priority(&self) -> BTreeMap<Priority, Vec<GoalId>> {
+        let mut goals_by_priority: BTreeMap<Priority, Vec<GoalId>> = BTreeMap::new();
+        for (&id, goal) in self.0.goals.iter() {
+            goals_by_priority
+                .entry(goal.priority())
+                .or_default()
+                .push(id);
+        }
+
+        for goal_ids in goals_by_priority.values_mut() {
+            goal_ids.sort();
+        }
+
+        goals_by_priority
+    }
+
     fn goal_ids(&self) -> HashSet<GoalId> {
         self.0.goal_ids()
     }
@@ -319,6 +634,34 @@ impl GoalQueryEngine for ProfileAndDateTime<'_> {
     }
 }
 
+impl ProfileAndDateTime<'_> {
+    /// Same as [active_goals](GoalQueryEngine::active_goals) but memoized per minute,
+    /// so that frontends polling on a short interval only recompute active goals when
+    /// the minute rolls over or the profile changes. Goals starting or ending part way
+    /// through a minute will be reflected at the start of the next minute.
+    pub fn active_goals_cached(&self) -> HashSet<GoalId> {
+        let minute = self
+            .1
+            .duration_trunc(chrono::Duration::minutes(1))
+            .unwrap_or(self.1);
+
+        if let Some(cache) = self.0.active_goals_cache.borrow().as_ref() {
+            if cache.minute == minute {
+                return cache.active_goals.clone();
+            }
+        }
+
+        let active_goals = self.active_goals();
+
+        *self.0.active_goals_cache.borrow_mut() = Some(ActiveGoalsCache {
+            minute,
+            active_goals: active_goals.clone(),
+        });
+
+        active_goals
+    }
+}
+
 impl<'a> EventQueryEngine for ProfileAndDateTime<'a> {
     fn currently_occuring_events(&self) -> HashSet<EventId> {
         self.0
@@ -350,4 +693,8 @@ impl<'a> EventQueryEngine for ProfileAndDateTime<'a> {
     fn event_ids(&self) -> HashSet<EventId> {
         self.0.events.iter().map(|(&id, _)| id).collect()
     }
+
+    fn event_start(&self, event_id: EventId) -> Option<DateTime<Utc>> {
+        self.0.event_start(event_id)
+    }
 }
@@ -1,11 +1,21 @@
-use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    goal::{Goal, GoalEvent, GoalId},
-    profile::ProfileAndDateTime,
+    event::EventId,
+    goal::{
+        check_goal_name, Goal, GoalEvent, GoalId, GoalOperationError, GoalRelationship,
+        InvalidNameError, Priority,
+    },
+    profile::{
+        goal_traversal::get_goal_parent_id, MergeError, ProfileAndDateTime, RefineError, SplitError,
+    },
+    query::TimeOfDayConfiguration,
 };
 
+#[serde_with::serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum GoalRequest {
     AddEffort {
@@ -23,6 +33,9 @@ pub enum GoalRequest {
     Rescope {
         goal_id: GoalId,
         new_effort_to_complete: u32,
+        /// Also clamp the effort to date of the goal down to the new effort to complete.
+        #[serde(default)]
+        clamp_effort: bool,
     },
     ProcessDateTime {
         datetime: DateTime<Utc>,
@@ -42,27 +55,148 @@ pub enum GoalRequest {
         goal_id: GoalId,
         new_name: String,
     },
+    Tag {
+        goal_id: GoalId,
+        tag: String,
+    },
+    Untag {
+        goal_id: GoalId,
+        tag: String,
+    },
+    SetTimeOfDayConfig(TimeOfDayConfiguration),
+    Merge {
+        source_goal_id: GoalId,
+        target_goal_id: GoalId,
+    },
+    SetNotes {
+        goal_id: GoalId,
+        notes: Option<String>,
+    },
+    SetDeadline {
+        goal_id: GoalId,
+        deadline: Option<DateTime<Utc>>,
+    },
+    FocusAll,
+    UnfocusAll,
+    /// Swap the positions of two goals with the same parent.
+    SwapWithSibling {
+        goal_id: GoalId,
+        other_goal_id: GoalId,
+    },
+    SetPriority {
+        goal_id: GoalId,
+        priority: Priority,
+    },
+    /// Set the effort to date of a goal directly.
+    SetEffort {
+        goal_id: GoalId,
+        effort: u32,
+    },
+    /// Split effort across goals in proportion to their remaining effort, see
+    /// [distribute_effort](crate::profile::Profile::distribute_effort).
+    DistributeEffort {
+        effort: u32,
+        goal_ids: HashSet<GoalId>,
+    },
+    /// Set the label effort is displayed in, such as "hrs".
+    SetEffortUnit(String),
+    /// Move part of a goal's remaining effort into a new sibling goal.
+    Split {
+        goal_id: GoalId,
+        split_effort: u32,
+        new_name: String,
+    },
+    /// Move the deadlines of a goal and its descendants, see
+    /// [shift_deadlines](crate::profile::Profile::shift_deadlines).
+    ShiftDeadlines {
+        goal_id: GoalId,
+        #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+        by: Duration,
+    },
+    /// Apply each request in order, returning the events of all of them so that the
+    /// batch is recorded to history as a unit.
+    Batch(Vec<GoalRequest>),
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum RequestError {
+    #[error("no goal with id {0}")]
+    NoSuchGoal(GoalId),
+    #[error(transparent)]
+    Refine(#[from] RefineError),
+    #[error(transparent)]
+    Merge(#[from] MergeError),
+    #[error(transparent)]
+    Split(#[from] SplitError),
+    #[error("goal {0} has no parent")]
+    NoParent(GoalId),
+    #[error(transparent)]
+    InvalidName(#[from] InvalidNameError),
+    #[error(transparent)]
+    GoalOperation(#[from] GoalOperationError),
+    #[error("request at index {index} of the batch failed: {error}")]
+    Batch {
+        index: usize,
+        #[source]
+        error: Box<RequestError>,
+    },
 }
 
 pub trait GoalRequestHandler {
     fn handle_request(&mut self, request: GoalRequest) -> Vec<GoalEvent>;
+
+    /// Check whether a request would succeed without applying it. The requests of a
+    /// [Batch](GoalRequest::Batch) are checked in order against a copy of the profile
+    /// that each earlier request has been applied to, and the first request that
+    /// fails is reported.
+    fn preview_request(&self, request: &GoalRequest) -> Result<(), RequestError>;
 }
 
 impl GoalRequestHandler for ProfileAndDateTime<'_> {
     fn handle_request(&mut self, request: GoalRequest) -> Vec<GoalEvent> {
-        match request {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("handle_request", ?request).entered();
+
+        self.0.invalidate_active_goals_cache();
+
+        // Batches are checked as a whole so that either all of their requests are
+        // applied or none are
+        if let Err(_e) = self.preview_request(&request) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(error = %_e, "rejected request");
+
+            return vec![];
+        }
+
+        let events = match request {
             GoalRequest::AddEffort { goal_id, effort } => {
                 self.0.goals.get_mut(&goal_id).map_or(vec![], |goal| {
+                    let was_finished = goal.finished();
                     goal.add_effort(effort);
+                    goal.set_last_effort_at(self.1);
+
+                    let mut events = vec![GoalEvent::AddEffort {
+                        goal_id,
+                        effort,
+                        datetime: self.1,
+                    }];
+                    if !was_finished && goal.finished() {
+                        events.push(GoalEvent::Finished { goal_id });
+                    }
 
-                    vec![GoalEvent::AddEffort { goal_id, effort }]
+                    events
                 })
             }
             GoalRequest::RemoveEffort { goal_id, effort } => {
                 self.0.goals.get_mut(&goal_id).map_or(vec![], |goal| {
                     goal.remove_effort(effort);
+                    goal.set_last_effort_at(self.1);
 
-                    vec![GoalEvent::RemoveEffort { goal_id, effort }]
+                    vec![GoalEvent::RemoveEffort {
+                        goal_id,
+                        effort,
+                        datetime: self.1,
+                    }]
                 })
             }
             GoalRequest::Focus(goal_id) => {
@@ -100,22 +234,45 @@ impl GoalRequestHandler for ProfileAndDateTime<'_> {
             GoalRequest::Rescope {
                 goal_id,
                 new_effort_to_complete,
-            } => self.0.rescope_goal(goal_id, new_effort_to_complete).map_or(
-                vec![],
-                |original_effort_to_complete| {
-                    vec![GoalEvent::Rescope {
+                clamp_effort,
+            } => {
+                let was_finished = self.0.get_goal(goal_id).is_some_and(Goal::finished);
+
+                let rescope_result = if clamp_effort {
+                    self.0.rescope_goal_clamped(goal_id, new_effort_to_complete)
+                } else {
+                    self.0
+                        .rescope_goal(goal_id, new_effort_to_complete)
+                        .map(|original_effort_to_complete| (original_effort_to_complete, 0))
+                };
+
+                rescope_result.map_or(vec![], |(original_effort_to_complete, clamped_effort)| {
+                    let mut events = vec![GoalEvent::Rescope {
                         goal_id,
                         new_effort_to_complete,
                         original_effort_to_complete,
-                    }]
-                },
-            ),
+                        clamped_effort,
+                    }];
+                    if !was_finished && self.0.get_goal(goal_id).is_some_and(Goal::finished) {
+                        events.push(GoalEvent::Finished { goal_id });
+                    }
+
+                    events
+                })
+            }
             GoalRequest::Create {
                 name,
                 effort_to_complete,
-            } => vec![GoalEvent::Add {
-                goal_id: self.0.add_goal(Goal::new(name, effort_to_complete)),
-            }],
+            } => self
+                .0
+                .add_goal(Goal::new(name.clone(), effort_to_complete))
+                .map_or(vec![], |goal_id| {
+                    vec![GoalEvent::Add {
+                        goal_id,
+                        name,
+                        effort_to_complete,
+                    }]
+                }),
             GoalRequest::Refine {
                 parent_goal_id,
                 parent_effort_removed,
@@ -124,15 +281,18 @@ impl GoalRequestHandler for ProfileAndDateTime<'_> {
             } => self
                 .0
                 .refine_goal(
-                    Goal::new(child_name, child_effort_to_complete),
+                    Goal::new(child_name.clone(), child_effort_to_complete),
                     parent_goal_id,
                     parent_effort_removed,
                 )
+                .ok()
                 .map_or(vec![], |child_goal_id| {
                     vec![GoalEvent::Refine {
                         parent_goal_id,
                         parent_effort_removed,
                         new_child_goal_id: child_goal_id,
+                        child_name,
+                        child_effort_to_complete,
                     }]
                 }),
             GoalRequest::Delete(goal_id) => self
@@ -144,10 +304,450 @@ impl GoalRequestHandler for ProfileAndDateTime<'_> {
             GoalRequest::Rename { goal_id, new_name } => self
                 .0
                 .rename_goal(goal_id, &new_name)
+                .ok()
+                .flatten()
                 .map_or(vec![], |old_name| {
-                    vec![GoalEvent::Rename { goal_id, old_name }]
+                    vec![GoalEvent::Rename {
+                        goal_id,
+                        old_name,
+                        new_name,
+                    }]
                 }),
-            GoalRequest::ProcessDateTime { datetime: _ } => todo!(),
+            GoalRequest::Tag { goal_id, tag } => self
+                .0
+                .tag_goal(goal_id, &tag)
+                .then_some(tag)
+                .map_or(vec![], |tag| vec![GoalEvent::Tag { goal_id, tag }]),
+            GoalRequest::Untag { goal_id, tag } => self
+                .0
+                .untag_goal(goal_id, &tag)
+                .then_some(tag)
+                .map_or(vec![], |tag| vec![GoalEvent::Untag { goal_id, tag }]),
+            GoalRequest::SetTimeOfDayConfig(new_config) => {
+                let old_config = self.0.set_time_of_day_config(new_config);
+
+                if old_config != new_config {
+                    vec![GoalEvent::TimeOfDayConfigChanged {
+                        old_config,
+                        new_config,
+                    }]
+                } else {
+                    vec![]
+                }
+            }
+            GoalRequest::DistributeEffort { effort, goal_ids } => {
+                let finished_goal_ids: HashSet<GoalId> = goal_ids
+                    .iter()
+                    .copied()
+                    .filter(|goal_id| self.0.get_goal(*goal_id).is_some_and(Goal::finished))
+                    .collect();
+
+                let mut amounts: Vec<(GoalId, u32)> = self
+                    .0
+                    .distribute_effort(effort, &goal_ids)
+                    .into_iter()
+                    .collect();
+                amounts.sort();
+
+                let mut events = vec![];
+                for (goal_id, effort) in amounts {
+                    let goal = self
+                        .0
+                        .get_goal_mut(goal_id)
+                        .expect("goal to exist since effort was distributed to it");
+                    goal.set_last_effort_at(self.1);
+
+                    events.push(GoalEvent::AddEffort {
+                        goal_id,
+                        effort,
+                        datetime: self.1,
+                    });
+                    if !finished_goal_ids.contains(&goal_id) && goal.finished() {
+                        events.push(GoalEvent::Finished { goal_id });
+                    }
+                }
+
+                events
+            }
+            GoalRequest::SetEffortUnit(new_unit) => {
+                let old_unit = self.0.set_effort_unit(new_unit.clone());
+
+                if old_unit != new_unit {
+                    vec![GoalEvent::EffortUnitChanged { old_unit, new_unit }]
+                } else {
+                    vec![]
+                }
+            }
+            GoalRequest::Merge {
+                source_goal_id,
+                target_goal_id,
+            } => {
+                let Some(source_goal) = self.0.goals.get(&source_goal_id).cloned() else {
+                    return vec![];
+                };
+                let source_parent_goal_id = get_goal_parent_id(&self.0.goals, source_goal_id);
+                let source_child_index = source_parent_goal_id.and_then(|parent_goal_id| {
+                    self.0.goals[&parent_goal_id]
+                        .children()
+                        .iter()
+                        .position(|&child_id| child_id == source_goal_id)
+                });
+                let source_was_focused = self.0.focused_goals().contains(&source_goal_id);
+                let source_was_collapsed = self.0.is_collapsed(source_goal_id);
+                let mut moved_event_relationships: Vec<(EventId, Vec<GoalRelationship>)> = self
+                    .0
+                    .events
+                    .iter()
+                    .filter(|(_, event)| {
+                        event
+                            .goal_relationships()
+                            .iter()
+                            .any(|relationship| relationship.goal_id() == source_goal_id)
+                    })
+                    .map(|(&id, event)| (id, event.goal_relationships().to_vec()))
+                    .collect();
+                moved_event_relationships.sort_by_key(|(event_id, _)| *event_id);
+                let moved_relationship_events = moved_event_relationships
+                    .iter()
+                    .map(|(event_id, _)| *event_id)
+                    .collect();
+
+                self.0
+                    .merge_goal_into(source_goal_id, target_goal_id)
+                    .map_or(vec![], |_| {
+                        vec![GoalEvent::Merge {
+                            source_goal_id,
+                            target_goal_id,
+                            source_goal,
+                            source_parent_goal_id,
+                            source_was_focused,
+                            moved_relationship_events,
+                            source_child_index,
+                            source_was_collapsed,
+                            moved_event_relationships,
+                        }]
+                    })
+            }
+            GoalRequest::SetNotes { goal_id, notes } => {
+                match self.0.set_goal_notes(goal_id, notes.clone()) {
+                    Some(old_notes) if old_notes != notes => {
+                        vec![GoalEvent::SetNotes {
+                            goal_id,
+                            old_notes,
+                            notes,
+                        }]
+                    }
+                    _ => vec![],
+                }
+            }
+            GoalRequest::SetDeadline { goal_id, deadline } => {
+                match self.0.set_goal_deadline(goal_id, deadline) {
+                    Some(old_deadline) if old_deadline != deadline => {
+                        vec![GoalEvent::SetDeadline {
+                            goal_id,
+                            old_deadline,
+                            deadline,
+                        }]
+                    }
+                    _ => vec![],
+                }
+            }
+            GoalRequest::ShiftDeadlines { goal_id, by } => self
+                .0
+                .shift_deadlines(goal_id, by)
+                .into_iter()
+                .map(|goal_id| {
+                    let deadline = self.0.goals[&goal_id].deadline();
+
+                    GoalEvent::SetDeadline {
+                        goal_id,
+                        old_deadline: deadline.map(|deadline| deadline - by),
+                        deadline,
+                    }
+                })
+                .collect(),
+            GoalRequest::SetPriority { goal_id, priority } => {
+                match self.0.set_goal_priority(goal_id, priority) {
+                    Some(old_priority) if old_priority != priority => {
+                        vec![GoalEvent::SetPriority {
+                            goal_id,
+                            old_priority,
+                            priority,
+                        }]
+                    }
+                    _ => vec![],
+                }
+            }
+            GoalRequest::SetEffort { goal_id, effort } => {
+                let was_finished = self.0.get_goal(goal_id).map(Goal::finished);
+
+                match self.0.set_effort(goal_id, effort) {
+                    Some(old_effort) if old_effort != effort => {
+                        let goal = self
+                            .0
+                            .get_goal_mut(goal_id)
+                            .expect("goal to exist since its effort was just set");
+                        goal.set_last_effort_at(self.1);
+
+                        let mut events = vec![GoalEvent::SetEffort {
+                            goal_id,
+                            old_effort,
+                            new_effort: effort,
+                            datetime: self.1,
+                        }];
+                        if was_finished == Some(false) && goal.finished() {
+                            events.push(GoalEvent::Finished { goal_id });
+                        }
+
+                        events
+                    }
+                    _ => vec![],
+                }
+            }
+            GoalRequest::FocusAll => {
+                let focused_goals = self.0.focus_all();
+
+                if focused_goals.is_empty() {
+                    vec![]
+                } else {
+                    vec![GoalEvent::FocusAll { focused_goals }]
+                }
+            }
+            GoalRequest::UnfocusAll => {
+                let unfocused_goals = self.0.unfocus_all();
+
+                if unfocused_goals.is_empty() {
+                    vec![]
+                } else {
+                    vec![GoalEvent::UnfocusAll { unfocused_goals }]
+                }
+            }
+            GoalRequest::SwapWithSibling {
+                goal_id,
+                other_goal_id,
+            } => {
+                let Some(parent_goal_id) = get_goal_parent_id(&self.0.goals, goal_id) else {
+                    return vec![];
+                };
+
+                self.0
+                    .goals
+                    .get_mut(&parent_goal_id)
+                    .and_then(|parent_goal| parent_goal.swap_children(goal_id, other_goal_id).ok())
+                    .map_or(vec![], |_| {
+                        vec![GoalEvent::SwapSiblings {
+                            parent_goal_id,
+                            goal_id,
+                            other_goal_id,
+                        }]
+                    })
+            }
+            GoalRequest::Split {
+                goal_id,
+                split_effort,
+                new_name,
+            } => {
+                let Some(goal) = self.0.get_goal(goal_id) else {
+                    return vec![];
+                };
+                let was_finished = goal.finished();
+                let original_effort_to_complete = goal.effort_to_complete();
+                let parent_goal_id = get_goal_parent_id(&self.0.goals, goal_id);
+
+                let Ok(new_goal_id) = self.0.split_goal(goal_id, split_effort, &new_name) else {
+                    return vec![];
+                };
+
+                let mut events = vec![GoalEvent::Rescope {
+                    goal_id,
+                    new_effort_to_complete: original_effort_to_complete - split_effort,
+                    original_effort_to_complete,
+                    clamped_effort: 0,
+                }];
+                if !was_finished && self.0.get_goal(goal_id).is_some_and(Goal::finished) {
+                    events.push(GoalEvent::Finished { goal_id });
+                }
+                // Refining without removing effort from the parent adds the new goal as
+                // a sibling when the history is replayed
+                events.push(match parent_goal_id {
+                    Some(parent_goal_id) => GoalEvent::Refine {
+                        parent_goal_id,
+                        parent_effort_removed: 0,
+                        new_child_goal_id: new_goal_id,
+                        child_name: new_name,
+                        child_effort_to_complete: split_effort,
+                    },
+                    None => GoalEvent::Add {
+                        goal_id: new_goal_id,
+                        name: new_name,
+                        effort_to_complete: split_effort,
+                    },
+                });
+
+                events
+            }
+            GoalRequest::Batch(requests) => {
+                let mut events = vec![];
+                for request in requests {
+                    events.extend(self.handle_request(request));
+                }
+
+                events
+            }
+            GoalRequest::ProcessDateTime { datetime } => {
+                let mut ended_event_ids: Vec<EventId> = self
+                    .0
+                    .events
+                    .iter()
+                    .filter(|(id, event)| {
+                        !self.0.effort_applied_events.contains(id)
+                            && event.end().is_some_and(|end| end <= datetime)
+                    })
+                    .map(|(&id, _)| id)
+                    .collect();
+                ended_event_ids.sort();
+
+                let mut events = vec![];
+                for event_id in ended_event_ids {
+                    self.0.effort_applied_events.insert(event_id);
+
+                    let event = &self.0.events[&event_id];
+                    let end = event.end().expect("ended events to have an end");
+                    let efforts: Vec<(GoalId, u32)> = event
+                        .goal_relationships()
+                        .iter()
+                        .filter_map(|relationship| {
+                            relationship
+                                .expected_effort()
+                                .map(|effort| (relationship.goal_id(), effort))
+                        })
+                        .collect();
+
+                    for (goal_id, effort) in efforts {
+                        if let Some(goal) = self.0.goals.get_mut(&goal_id) {
+                            let was_finished = goal.finished();
+                            goal.add_effort(effort);
+                            goal.set_last_effort_at(end);
+
+                            events.push(GoalEvent::AddEffort {
+                                goal_id,
+                                effort,
+                                datetime: end,
+                            });
+                            if !was_finished && goal.finished() {
+                                events.push(GoalEvent::Finished { goal_id });
+                            }
+                        }
+                    }
+                }
+
+                events
+            }
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(event_count = events.len(), "handled request");
+
+        events
+    }
+
+    fn preview_request(&self, request: &GoalRequest) -> Result<(), RequestError> {
+        let goal_exists = |goal_id: &GoalId| {
+            self.get_goal(*goal_id)
+                .map(|_| ())
+                .ok_or(RequestError::NoSuchGoal(*goal_id))
+        };
+        let valid_name = |name: &str| Ok(check_goal_name(name)?);
+
+        match request {
+            GoalRequest::AddEffort { goal_id, .. }
+            | GoalRequest::RemoveEffort { goal_id, .. }
+            | GoalRequest::Focus(goal_id)
+            | GoalRequest::Unfocus(goal_id)
+            | GoalRequest::FocusSingle(goal_id)
+            | GoalRequest::UnfocusSingle(goal_id)
+            | GoalRequest::Rescope { goal_id, .. }
+            | GoalRequest::Delete(goal_id)
+            | GoalRequest::Tag { goal_id, .. }
+            | GoalRequest::Untag { goal_id, .. }
+            | GoalRequest::SetNotes { goal_id, .. }
+            | GoalRequest::SetDeadline { goal_id, .. }
+            | GoalRequest::ShiftDeadlines { goal_id, .. }
+            | GoalRequest::SetPriority { goal_id, .. }
+            | GoalRequest::SetEffort { goal_id, .. } => goal_exists(goal_id),
+            GoalRequest::Create { name, .. } => valid_name(name),
+            GoalRequest::Rename { goal_id, new_name } => {
+                goal_exists(goal_id)?;
+                valid_name(new_name)
+            }
+            GoalRequest::Refine {
+                parent_goal_id,
+                parent_effort_removed,
+                child_name,
+                child_effort_to_complete,
+            } => {
+                self.0.check_refine(
+                    *parent_goal_id,
+                    *parent_effort_removed,
+                    *child_effort_to_complete,
+                )?;
+                valid_name(child_name)
+            }
+            GoalRequest::Merge {
+                source_goal_id,
+                target_goal_id,
+            } => Ok(self.0.check_merge(*source_goal_id, *target_goal_id)?),
+            GoalRequest::Split {
+                goal_id,
+                split_effort,
+                new_name,
+            } => {
+                self.0.check_split(*goal_id, *split_effort)?;
+                valid_name(new_name)
+            }
+            GoalRequest::DistributeEffort { goal_ids, .. } => {
+                goal_ids.iter().try_for_each(goal_exists)
+            }
+            GoalRequest::SwapWithSibling {
+                goal_id,
+                other_goal_id,
+            } => {
+                goal_exists(goal_id)?;
+                goal_exists(other_goal_id)?;
+
+                let parent_goal_id = get_goal_parent_id(&self.0.goals, *goal_id)
+                    .ok_or(RequestError::NoParent(*goal_id))?;
+                let parent_goal = &self.0.goals[&parent_goal_id];
+                if !parent_goal.children().contains(other_goal_id) {
+                    return Err(GoalOperationError::NoSuchChild(
+                        parent_goal.name().to_string(),
+                        *other_goal_id,
+                    )
+                    .into());
+                }
+
+                Ok(())
+            }
+            GoalRequest::FocusAll
+            | GoalRequest::UnfocusAll
+            | GoalRequest::SetTimeOfDayConfig(_)
+            | GoalRequest::SetEffortUnit(_)
+            | GoalRequest::ProcessDateTime { .. } => Ok(()),
+            GoalRequest::Batch(requests) => {
+                let mut profile = self.0.clone();
+                let mut profile = ProfileAndDateTime(&mut profile, self.1);
+                for (index, request) in requests.iter().enumerate() {
+                    profile
+                        .preview_request(request)
+                        .map_err(|error| RequestError::Batch {
+                            index,
+                            error: Box::new(error),
+                        })?;
+                    profile.handle_request(request.clone());
+                }
+
+                Ok(())
+            }
         }
     }
 }
@@ -1,11 +1,13 @@
-use geff_core::goal::{GoalId, PopulatedGoal};
+use geff_core::event::EventId;
+use geff_core::goal::{GoalEvent, GoalId, GoalRelationship, PopulatedGoal, TimedGoalEvent};
+use geff_core::profile::{Profile, ProfileStats};
 use geff_core::request::{GoalRequest, GoalRequestHandler};
 use geff_core::{DateTime, Utc};
 use geff_util::{
     get_selected_goal_id, Cursor, CursorAction, PersistentState as PersistentGoalState,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct CommandlineDisplayConfig {
@@ -50,9 +52,61 @@ pub struct DisplayConfig {
     commandline: CommandlineDisplayConfig,
 }
 
+/// An action that a single key press can be bound to.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyAction {
+    #[serde(rename = "cursor")]
+    Cursor(CursorAction),
+    /// Start typing a command into the commandline.
+    #[serde(rename = "commandLeader")]
+    CommandLeader,
+    /// Open or close the inspector for the selected goal.
+    #[serde(rename = "inspect")]
+    Inspect,
+    /// Add one unit of effort to the selected goal.
+    #[serde(rename = "addEffort")]
+    AddEffort,
+    /// Remove one unit of effort from the selected goal.
+    #[serde(rename = "removeEffort")]
+    RemoveEffort,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct KeyBindings {
+    bindings: HashMap<char, KeyAction>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            bindings: HashMap::from([
+                (':', KeyAction::CommandLeader),
+                ('h', KeyAction::Cursor(CursorAction::Out)),
+                ('j', KeyAction::Cursor(CursorAction::Down)),
+                ('k', KeyAction::Cursor(CursorAction::Up)),
+                ('l', KeyAction::Cursor(CursorAction::In)),
+                ('g', KeyAction::Cursor(CursorAction::Top)),
+                ('G', KeyAction::Cursor(CursorAction::Bottom)),
+                ('0', KeyAction::Cursor(CursorAction::Root)),
+                ('z', KeyAction::Cursor(CursorAction::Collapse)),
+                ('o', KeyAction::Cursor(CursorAction::Expand)),
+                ('i', KeyAction::Inspect),
+                ('+', KeyAction::AddEffort),
+                ('-', KeyAction::RemoveEffort),
+            ]),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Default, Debug)]
 pub struct Config {
     display: DisplayConfig,
+    #[serde(default, rename = "keyBindings")]
+    key_bindings: KeyBindings,
+    /// How often the frontend should request an autosave, autosave is disabled if
+    /// this is not set.
+    #[serde(default, rename = "autosaveIntervalSeconds")]
+    autosave_interval_seconds: Option<u32>,
 }
 
 #[derive(Clone, Debug)]
@@ -62,6 +116,8 @@ pub enum AppCommand {
     DisplayCommand(DisplayCommand),
     LoadRequest,
     SaveRequest,
+    /// Save only if there are unsaved changes.
+    AutosaveRequest,
 }
 
 impl From<GoalRequest> for AppCommand {
@@ -76,6 +132,8 @@ pub struct GoalState {
     pub(crate) cursor: Cursor,
     pub(crate) populated_goals: Vec<PopulatedGoal>,
     pub(crate) current_datetime: DateTime<Utc>,
+    /// Whether there are changes that have not been saved yet.
+    pub(crate) dirty: bool,
 }
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -86,9 +144,82 @@ pub struct FrontendGoalState {
     pub selected_goal_id: Option<GoalId>,
     #[serde(rename = "focusedGoals")]
     pub focused_goals: HashSet<GoalId>,
+    #[serde(rename = "effortUnit")]
+    pub effort_unit: String,
     pub config: Config,
 }
 
+/// An event related to an inspected goal.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct InspectedEvent {
+    #[serde(rename = "eventId")]
+    pub event_id: EventId,
+    /// How the event relates to the goal, such as "starts" or "works on".
+    pub relationship: String,
+    pub start: Option<DateTime<Utc>>,
+}
+
+/// Everything about a single goal shown by the inspector.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct GoalInspection {
+    pub id: GoalId,
+    pub name: String,
+    pub notes: Option<String>,
+    #[serde(rename = "effortToDate")]
+    pub effort_to_date: u32,
+    #[serde(rename = "effortToComplete")]
+    pub effort_to_complete: u32,
+    #[serde(rename = "subtreeEffortRemaining")]
+    pub subtree_effort_remaining: u32,
+    /// The names of the goals above the goal, starting with the root of its tree.
+    pub ancestors: Vec<String>,
+    pub events: Vec<InspectedEvent>,
+}
+
+impl GoalInspection {
+    /// Inspect a goal, returning None if no goal exists with the provided `goal_id`.
+    pub fn new(profile: &Profile, goal_id: GoalId) -> Option<GoalInspection> {
+        let goal = profile.get_goal(goal_id)?;
+
+        let ancestors = profile
+            .ancestors(goal_id)
+            .into_iter()
+            .rev()
+            .filter_map(|ancestor_id| profile.get_goal(ancestor_id))
+            .map(|ancestor| ancestor.name().to_string())
+            .collect();
+
+        let events = profile
+            .events_for_goal(goal_id)
+            .into_iter()
+            .map(|(event_id, _, relationship)| InspectedEvent {
+                event_id,
+                relationship: match relationship {
+                    GoalRelationship::Requires(_) => "requires".to_string(),
+                    GoalRelationship::Ends(_) => "ends".to_string(),
+                    GoalRelationship::WorksOn(_) => "works on".to_string(),
+                    GoalRelationship::Starts(_) => "starts".to_string(),
+                    GoalRelationship::WorksOnWithEffort { effort, .. } => {
+                        format!("works on ({effort} effort)")
+                    }
+                },
+                start: profile.event_start(event_id),
+            })
+            .collect();
+
+        Some(GoalInspection {
+            id: goal_id,
+            name: goal.name().to_string(),
+            notes: goal.notes().map(str::to_string),
+            effort_to_date: goal.effort_to_date(),
+            effort_to_complete: goal.effort_to_complete(),
+            subtree_effort_remaining: profile.subtree_effort_remaining(goal_id)?,
+            ancestors,
+            events,
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Copy, Clone)]
 pub enum ActiveActivity {
     Goals,
@@ -132,6 +263,7 @@ impl AppState {
                     cursor: Cursor::SelectedGoal(Some(selected_goal)),
                     populated_goals,
                     current_datetime: _,
+                    dirty: _,
                 },
             active_activity: _,
         } = self
@@ -146,18 +278,23 @@ impl AppState {
                 goal_state:
                     GoalState {
                         persistent_state,
-                        cursor: _,
+                        cursor,
                         populated_goals,
                         current_datetime: _,
+                        dirty: _,
                     },
                 active_activity,
             } = self
             {
+                let mut populated_goals = populated_goals.clone();
+                cursor.mark_selection(&mut populated_goals);
+
                 Some(FrontendAppState {
                     goal_state: FrontendGoalState {
-                        populated_goals: populated_goals.clone(),
+                        populated_goals,
                         selected_goal_id,
                         focused_goals: persistent_state.profile.focused_goals().clone(),
+                        effort_unit: persistent_state.profile.effort_unit().to_string(),
                         config: persistent_state.config.clone(),
                     },
                     active_activity: *active_activity,
@@ -170,6 +307,46 @@ impl AppState {
         )
     }
 
+    /// Inspect the selected goal, returning None if no goal is selected.
+    pub fn inspect_selected_goal(&self) -> Result<Option<GoalInspection>, String> {
+        match self {
+            AppState::Loaded {
+                goal_state:
+                    GoalState {
+                        persistent_state,
+                        cursor: Cursor::SelectedGoal(Some(selected_goal)),
+                        populated_goals,
+                        ..
+                    },
+                active_activity: _,
+            } => {
+                let selected_goal_id = get_selected_goal_id(selected_goal, populated_goals)
+                    .map_err(|e| e.to_string())?;
+
+                Ok(GoalInspection::new(
+                    &persistent_state.profile,
+                    selected_goal_id,
+                ))
+            }
+            AppState::Loaded { .. } => Ok(None),
+            AppState::Error(e) => Err(e.to_string()),
+            AppState::Unloaded => Err("app state is not loaded".to_string()),
+        }
+    }
+
+    pub fn statistics(&self) -> Result<ProfileStats, String> {
+        match self {
+            AppState::Loaded {
+                goal_state: GoalState {
+                    persistent_state, ..
+                },
+                active_activity: _,
+            } => Ok(persistent_state.profile.statistics()),
+            AppState::Error(e) => Err(e.to_string()),
+            AppState::Unloaded => Err("app state is not loaded".to_string()),
+        }
+    }
+
     async fn load_app_state(&mut self) -> anyhow::Result<()> {
         let config_data_path = match PersistentGoalState::<Config>::data_path("geff-tauri") {
             Ok(config_data_path) => config_data_path,
@@ -178,21 +355,24 @@ impl AppState {
                 return Ok(());
             }
         };
-        let persistent_state = match PersistentGoalState::<Config>::load(config_data_path).await {
-            Ok(persistent_state) => persistent_state,
-            Err(e) => {
-                *self = AppState::Error(e.to_string());
-                return Ok(());
-            }
-        };
+        let persistent_state =
+            match PersistentGoalState::<Config>::try_load_with_recovery(config_data_path).await {
+                Ok(persistent_state) => persistent_state,
+                Err(e) => {
+                    *self = AppState::Error(e.to_string());
+                    return Ok(());
+                }
+            };
         let populated_goals = persistent_state.profile.populate_goals();
+        let cursor = Cursor::restore(persistent_state.last_cursor.as_ref(), &populated_goals);
 
         *self = AppState::Loaded {
             goal_state: GoalState {
                 persistent_state,
-                cursor: Default::default(),
+                cursor,
                 populated_goals,
                 current_datetime: Utc::now(),
+                dirty: false,
             },
             active_activity: ActiveActivity::Help,
         };
@@ -200,7 +380,9 @@ impl AppState {
         Ok(())
     }
 
-    pub async fn handle_command(&mut self, command: AppCommand) -> anyhow::Result<()> {
+    /// Handle a command, returning the goal events emitted by it. Emitted events are
+    /// also appended to the goal event history.
+    pub async fn handle_command(&mut self, command: AppCommand) -> anyhow::Result<Vec<GoalEvent>> {
         if let AppState::Loaded {
             goal_state:
                 GoalState {
@@ -208,25 +390,45 @@ impl AppState {
                     cursor,
                     populated_goals,
                     current_datetime,
+                    dirty,
                 },
             active_activity: _,
         } = self
         {
+            let mut events = vec![];
+
             match command {
                 AppCommand::LoadRequest => {
                     self.load_app_state().await?;
                 }
                 AppCommand::GoalRequest(goal_request) => {
+                    // Requests are stamped with the time they are made, not when the
+                    // app was loaded
+                    *current_datetime = Utc::now();
+                    let mut profile = persistent_state.profile.with_datetime(*current_datetime);
+                    profile.preview_request(&goal_request)?;
+                    events = profile.handle_request(goal_request);
+                    *dirty |= !events.is_empty();
                     persistent_state
-                        .profile
-                        .with_datetime(*current_datetime)
-                        .handle_request(goal_request);
+                        .goal_event_history
+                        .extend(events.iter().map(|event| TimedGoalEvent {
+                            at: *current_datetime,
+                            event: event.clone(),
+                        }));
                     *populated_goals = persistent_state.profile.populate_goals();
-
-                    if let Cursor::SelectedGoal(Some(selected_goal)) = cursor {
-                        if get_selected_goal_id(selected_goal, populated_goals).is_err() {
-                            *cursor = Cursor::SelectedGoal(None);
-                        }
+                    cursor.clamp_to(populated_goals);
+                }
+                AppCommand::CursorAction(
+                    cursor_action @ (CursorAction::Collapse | CursorAction::Expand),
+                ) => {
+                    if cursor.collapse_selected_goal(
+                        cursor_action,
+                        &mut persistent_state.profile,
+                        populated_goals,
+                    )? {
+                        *dirty = true;
+                        *populated_goals = persistent_state.profile.populate_goals();
+                        cursor.clamp_to(populated_goals);
                     }
                 }
                 AppCommand::CursorAction(cursor_action) => {
@@ -238,6 +440,7 @@ impl AppState {
                         background_color,
                         font_color,
                     } = &mut persistent_state.config.display.commandline;
+                    *dirty = true;
 
                     match command {
                         CommandlineDisplayCommand::ChangeFontSize(fs) => *font_size_pixels = fs,
@@ -247,32 +450,206 @@ impl AppState {
                         CommandlineDisplayCommand::ChangeFontColor(color) => *font_color = color,
                     }
                 }
-                AppCommand::SaveRequest => {
-                    let config_data_path =
-                        match PersistentGoalState::<Config>::data_path("geff-tauri") {
-                            Ok(config_data_path) => config_data_path,
-                            Err(e) => {
-                                *self = AppState::Error(e.to_string());
-                                return Ok(());
-                            }
-                        };
-                    match persistent_state.save_to_file(config_data_path).await {
-                        Ok(config_data_path) => config_data_path,
-                        Err(e) => {
-                            *self = AppState::Error(e.to_string());
-                            return Ok(());
-                        }
-                    };
+                AppCommand::AutosaveRequest if !*dirty => {}
+                AppCommand::SaveRequest | AppCommand::AutosaveRequest => {
+                    if let Err(e) = save_goal_state(persistent_state, cursor, populated_goals).await
+                    {
+                        *self = AppState::Error(e);
+                        return Ok(vec![]);
+                    }
+                    *dirty = false;
                 }
             };
 
-            Ok(())
+            Ok(events)
         } else {
             if let AppCommand::LoadRequest = command {
                 self.load_app_state().await?;
             }
 
-            Ok(())
+            Ok(vec![])
         }
     }
+
+    /// Save unsaved changes before the app exits. Unlike a save request, a failure
+    /// leaves the app state as it is so that exiting can be cancelled without losing
+    /// the changes.
+    pub async fn save_before_exit(&mut self) -> Result<(), String> {
+        if let AppState::Loaded {
+            goal_state:
+                GoalState {
+                    persistent_state,
+                    cursor,
+                    populated_goals,
+                    dirty: dirty @ true,
+                    ..
+                },
+            ..
+        } = self
+        {
+            save_goal_state(persistent_state, cursor, populated_goals).await?;
+            *dirty = false;
+        }
+
+        Ok(())
+    }
+}
+
+/// Save goal state to the app's data file, remembering the cursor so that it can be
+/// restored on the next load.
+async fn save_goal_state(
+    persistent_state: &mut PersistentGoalState<Config>,
+    cursor: &Cursor,
+    populated_goals: &[PopulatedGoal],
+) -> Result<(), String> {
+    persistent_state.last_cursor = cursor.save(populated_goals);
+
+    let config_data_path =
+        PersistentGoalState::<Config>::data_path("geff-tauri").map_err(|e| e.to_string())?;
+    persistent_state
+        .save_to_file(config_data_path)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use geff_core::event::{Event, EventId};
+    use geff_core::goal::{Goal, GoalEvent, GoalRelationship};
+    use geff_core::profile::Profile;
+    use geff_core::request::GoalRequest;
+    use geff_core::{TimeZone, Utc};
+    use geff_util::{Cursor, PersistentState};
+
+    use super::{ActiveActivity, AppState, GoalInspection, GoalState, InspectedEvent};
+
+    #[test]
+    fn inspect_goal() {
+        let mut profile = Profile::default();
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+
+        let root_id = profile.add_goal(Goal::new("root", 3)).unwrap();
+        let parent_id = profile
+            .refine_goal(Goal::new("parent", 2), root_id, 2)
+            .unwrap();
+        let goal_id = profile
+            .refine_goal(Goal::new("goal", 1), parent_id, 1)
+            .unwrap();
+        profile.add_event(Event::instant_event(
+            start,
+            vec![GoalRelationship::Ends(goal_id)],
+        ));
+
+        let inspection = GoalInspection::new(&profile, parent_id).unwrap();
+        assert_eq!(inspection.ancestors, vec!["root".to_string()]);
+        assert_eq!(inspection.subtree_effort_remaining, 1 + 1);
+        assert!(inspection.events.is_empty());
+
+        let inspection = GoalInspection::new(&profile, goal_id).unwrap();
+        assert_eq!(
+            inspection.ancestors,
+            vec!["root".to_string(), "parent".to_string()]
+        );
+        assert_eq!(
+            inspection.events,
+            vec![InspectedEvent {
+                event_id: EventId(0),
+                relationship: "ends".to_string(),
+                start: Some(start),
+            }]
+        );
+    }
+
+    #[test]
+    fn goal_requests_are_recorded_to_history() {
+        let loaded_at = Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap();
+        let mut app_state = AppState::Loaded {
+            goal_state: GoalState {
+                persistent_state: PersistentState::in_memory(),
+                cursor: Cursor::default(),
+                populated_goals: vec![],
+                current_datetime: loaded_at,
+                dirty: false,
+            },
+            active_activity: ActiveActivity::Goals,
+        };
+
+        let requested_at = Utc::now();
+        let events = tauri::async_runtime::block_on(
+            app_state.handle_command(
+                GoalRequest::Create {
+                    name: "goal".to_string(),
+                    effort_to_complete: 1,
+                }
+                .into(),
+            ),
+        )
+        .unwrap();
+
+        let AppState::Loaded { goal_state, .. } = &app_state else {
+            panic!("app state to stay loaded");
+        };
+        assert_eq!(events.len(), 1);
+        let recorded_events: Vec<_> = goal_state
+            .persistent_state
+            .goal_event_history
+            .iter()
+            .map(|timed_event| timed_event.event.clone())
+            .collect();
+        assert_eq!(recorded_events, events);
+        assert!(goal_state
+            .persistent_state
+            .goal_event_history
+            .iter()
+            .all(|timed_event| timed_event.at >= requested_at && timed_event.at > loaded_at));
+        assert!(goal_state.dirty);
+    }
+
+    #[test]
+    fn effort_is_logged_at_request_time() {
+        let mut persistent_state = PersistentState::in_memory();
+        let goal_id = persistent_state
+            .profile
+            .add_goal(Goal::new("goal", 5))
+            .unwrap();
+        let mut app_state = AppState::Loaded {
+            goal_state: GoalState {
+                persistent_state,
+                cursor: Cursor::default(),
+                populated_goals: vec![],
+                current_datetime: Utc.with_ymd_and_hms(2023, 1, 1, 9, 0, 0).unwrap(),
+                dirty: false,
+            },
+            active_activity: ActiveActivity::Goals,
+        };
+
+        let mut add_effort = || {
+            let events = tauri::async_runtime::block_on(
+                app_state.handle_command(GoalRequest::AddEffort { goal_id, effort: 1 }.into()),
+            )
+            .unwrap();
+            match events[..] {
+                [GoalEvent::AddEffort { datetime, .. }] => datetime,
+                _ => panic!("expected a single AddEffort event, got {events:?}"),
+            }
+        };
+
+        let first_logged_at = add_effort();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let second_logged_at = add_effort();
+        assert!(second_logged_at > first_logged_at);
+
+        let AppState::Loaded { goal_state, .. } = &app_state else {
+            panic!("app state to stay loaded");
+        };
+        assert_eq!(
+            goal_state
+                .persistent_state
+                .profile
+                .get_goal(goal_id)
+                .unwrap()
+                .last_effort_at(),
+            Some(second_logged_at)
+        );
+    }
 }
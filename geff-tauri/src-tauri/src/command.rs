@@ -1,6 +1,11 @@
-use crate::app::{ActiveActivity, AppCommand, AppState, FrontendAppState, GoalState};
+use crate::app::{
+    ActiveActivity, AppCommand, AppState, FrontendAppState, GoalInspection, GoalState,
+};
 use crate::parser::{self, GoalCommand};
 use crate::parser::{command as parse_command, ControlCommand};
+use geff_core::goal::GoalEvent;
+use geff_core::profile::ProfileStats;
+use geff_core::query::TimeOfDayConfiguration;
 use geff_core::request::GoalRequest;
 use geff_util::{get_selected_goal_id, Cursor, CursorAction};
 use nom::Finish;
@@ -15,7 +20,9 @@ pub async fn load(state: tauri::State<'_, Mutex<AppState>>) -> Result<(), String
     state
         .handle_command(AppCommand::LoadRequest)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
 }
 
 #[tauri::command]
@@ -27,6 +34,35 @@ pub async fn fetch(
     app_state.try_into_frontend()
 }
 
+#[tauri::command]
+pub async fn statistics(state: tauri::State<'_, Mutex<AppState>>) -> Result<ProfileStats, String> {
+    let app_state = state.lock().await;
+
+    app_state.statistics()
+}
+
+#[tauri::command]
+pub async fn inspect(
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<Option<GoalInspection>, String> {
+    let app_state = state.lock().await;
+
+    app_state.inspect_selected_goal()
+}
+
+/// Save the app state if it has changed since it was last saved.
+#[tauri::command]
+pub async fn autosave(state: tauri::State<'_, Mutex<AppState>>) -> Result<(), String> {
+    let mut state = state.lock().await;
+
+    state
+        .handle_command(AppCommand::AutosaveRequest)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn cursor_action(
     state: tauri::State<'_, Mutex<AppState>>,
@@ -59,18 +95,43 @@ pub async fn set_active_activity(
 }
 
 pub fn invoke_handler() -> impl Fn(Invoke) {
-    tauri::generate_handler![app_command, load, fetch, cursor_action, set_active_activity]
+    tauri::generate_handler![
+        app_command,
+        load,
+        fetch,
+        statistics,
+        inspect,
+        autosave,
+        cursor_action,
+        set_active_activity
+    ]
 }
 
+fn current_time_of_day_config(app_state: &AppState) -> Option<TimeOfDayConfiguration> {
+    if let AppState::Loaded {
+        goal_state: GoalState {
+            persistent_state, ..
+        },
+        ..
+    } = app_state
+    {
+        Some(*persistent_state.profile.time_of_day_config())
+    } else {
+        None
+    }
+}
+
+/// Handle goal commands that do not need a selected goal, returning `None` if the
+/// command was not handled.
 async fn handle_untargeted_goal_command(
     app_state: &mut AppState,
     command: GoalCommand,
-) -> anyhow::Result<bool> {
+) -> anyhow::Result<Option<Vec<GoalEvent>>> {
     Ok(match command {
         parser::GoalCommand::Create {
             name,
             effort_to_complete,
-        } => {
+        } => Some(
             app_state
                 .handle_command(
                     GoalRequest::Create {
@@ -79,47 +140,166 @@ async fn handle_untargeted_goal_command(
                     }
                     .into(),
                 )
-                .await?;
+                .await?,
+        ),
+        parser::GoalCommand::SetTimeOfDayConfig {
+            midday_start,
+            evening_start,
+        } => {
+            let Some(current_config) = current_time_of_day_config(app_state) else {
+                return Ok(None);
+            };
+            let config = TimeOfDayConfiguration::from_start_of_midday_and_evening(
+                midday_start,
+                evening_start,
+            )?
+            .with_utc_offset(current_config.utc_offset());
+
+            Some(
+                app_state
+                    .handle_command(GoalRequest::SetTimeOfDayConfig(config).into())
+                    .await?,
+            )
+        }
+        parser::GoalCommand::SetUtcOffset { utc_offset } => {
+            let Some(current_config) = current_time_of_day_config(app_state) else {
+                return Ok(None);
+            };
+            let config = current_config.with_utc_offset(utc_offset);
+
+            Some(
+                app_state
+                    .handle_command(GoalRequest::SetTimeOfDayConfig(config).into())
+                    .await?,
+            )
+        }
+        parser::GoalCommand::DistributeEffort { effort } => {
+            let AppState::Loaded {
+                goal_state: GoalState {
+                    persistent_state, ..
+                },
+                ..
+            } = &*app_state
+            else {
+                return Ok(None);
+            };
+            let goal_ids = persistent_state.profile.focused_goals().clone();
+
+            Some(
+                app_state
+                    .handle_command(GoalRequest::DistributeEffort { effort, goal_ids }.into())
+                    .await?,
+            )
+        }
+        parser::GoalCommand::SetEffortUnit { effort_unit } => Some(
+            app_state
+                .handle_command(GoalRequest::SetEffortUnit(effort_unit).into())
+                .await?,
+        ),
+        parser::GoalCommand::FocusAll => Some(
+            app_state
+                .handle_command(GoalRequest::FocusAll.into())
+                .await?,
+        ),
+        parser::GoalCommand::UnfocusAll => Some(
+            app_state
+                .handle_command(GoalRequest::UnfocusAll.into())
+                .await?,
+        ),
+        parser::GoalCommand::FocusTagged { tags } => {
+            let AppState::Loaded {
+                goal_state: GoalState {
+                    persistent_state, ..
+                },
+                ..
+            } = &*app_state
+            else {
+                return Ok(None);
+            };
 
-            true
+            let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+            let mut tagged_goal_ids: Vec<_> = persistent_state
+                .profile
+                .goals_with_all_tags(&tags)
+                .into_iter()
+                .collect();
+            tagged_goal_ids.sort();
+
+            Some(
+                app_state
+                    .handle_command(
+                        GoalRequest::Batch(
+                            tagged_goal_ids
+                                .into_iter()
+                                .map(GoalRequest::FocusSingle)
+                                .collect(),
+                        )
+                        .into(),
+                    )
+                    .await?,
+            )
         }
-        _ => false,
+        _ => None,
     })
 }
 
+/// Handle goal commands that act on the selected goal, returning `None` if there is
+/// no selected goal or the command was not handled.
 async fn handle_targeted_goal_command(
     app_state: &mut AppState,
     command: GoalCommand,
-) -> anyhow::Result<bool> {
-    let selected_goal_id = if let AppState::Loaded {
+) -> anyhow::Result<Option<Vec<GoalEvent>>> {
+    let (selected_goal_id, profile) = if let AppState::Loaded {
         goal_state:
             GoalState {
-                persistent_state: _,
+                persistent_state,
                 cursor: Cursor::SelectedGoal(Some(selected_goal)),
                 populated_goals,
                 current_datetime: _,
+                dirty: _,
             },
         active_activity: _,
-    } = &mut *app_state
+    } = &*app_state
     {
-        get_selected_goal_id(selected_goal, populated_goals)?
+        (
+            get_selected_goal_id(selected_goal, populated_goals)?,
+            &persistent_state.profile,
+        )
     } else {
-        return Ok(false);
+        return Ok(None);
     };
 
     let command = match command {
-        GoalCommand::Create { .. } => return Ok(false),
+        GoalCommand::Create { .. }
+        | GoalCommand::SetTimeOfDayConfig { .. }
+        | GoalCommand::SetUtcOffset { .. }
+        | GoalCommand::SetEffortUnit { .. }
+        | GoalCommand::DistributeEffort { .. }
+        | GoalCommand::FocusTagged { .. }
+        | GoalCommand::FocusAll
+        | GoalCommand::UnfocusAll => return Ok(None),
         GoalCommand::Delete => GoalRequest::Delete(selected_goal_id),
         GoalCommand::Refine {
             child_name,
             child_effort_to_complete,
             parent_effort_removed,
-        } => GoalRequest::Refine {
-            parent_goal_id: selected_goal_id,
-            parent_effort_removed,
-            child_name,
-            child_effort_to_complete,
-        },
+            force,
+        } => {
+            if !force {
+                if let Some(warning) =
+                    profile.refine_warning(selected_goal_id, parent_effort_removed)
+                {
+                    anyhow::bail!("{warning}, use :r! to refine anyway");
+                }
+            }
+
+            GoalRequest::Refine {
+                parent_goal_id: selected_goal_id,
+                parent_effort_removed,
+                child_name,
+                child_effort_to_complete,
+            }
+        }
         GoalCommand::AddEffort { effort } => GoalRequest::AddEffort {
             goal_id: selected_goal_id,
             effort,
@@ -128,34 +308,88 @@ async fn handle_targeted_goal_command(
             goal_id: selected_goal_id,
             effort,
         },
+        GoalCommand::SetEffort { effort } => GoalRequest::SetEffort {
+            goal_id: selected_goal_id,
+            effort,
+        },
         GoalCommand::Focus => GoalRequest::Focus(selected_goal_id),
         GoalCommand::Unfocus => GoalRequest::Unfocus(selected_goal_id),
         GoalCommand::FocusSingle => GoalRequest::FocusSingle(selected_goal_id),
         GoalCommand::UnfocusSingle => GoalRequest::UnfocusSingle(selected_goal_id),
         GoalCommand::Rescope {
             new_effort_to_complete,
+            clamp_effort,
         } => GoalRequest::Rescope {
             goal_id: selected_goal_id,
             new_effort_to_complete,
+            clamp_effort,
         },
         GoalCommand::Rename { new_name } => GoalRequest::Rename {
             goal_id: selected_goal_id,
             new_name,
         },
-    };
+        GoalCommand::Tag { tag } => GoalRequest::Tag {
+            goal_id: selected_goal_id,
+            tag,
+        },
+        GoalCommand::Untag { tag } => GoalRequest::Untag {
+            goal_id: selected_goal_id,
+            tag,
+        },
+        GoalCommand::SetNotes { notes } => GoalRequest::SetNotes {
+            goal_id: selected_goal_id,
+            notes: Some(notes),
+        },
+        GoalCommand::SetPriority { priority } => GoalRequest::SetPriority {
+            goal_id: selected_goal_id,
+            priority,
+        },
+        GoalCommand::Split {
+            new_name,
+            split_effort,
+        } => GoalRequest::Split {
+            goal_id: selected_goal_id,
+            split_effort,
+            new_name,
+        },
+        GoalCommand::SwapWithSibling { sibling_name } => {
+            let parent_goal_id = profile
+                .get_goal_parent_id(selected_goal_id)
+                .ok_or_else(|| anyhow::anyhow!("Selected goal has no siblings"))?;
+            let other_goal_id = profile
+                .get_goal(parent_goal_id)
+                .and_then(|parent_goal| {
+                    parent_goal.children().iter().copied().find(|child_id| {
+                        *child_id != selected_goal_id
+                            && profile
+                                .get_goal(*child_id)
+                                .is_some_and(|child| child.name() == sibling_name)
+                    })
+                })
+                .ok_or_else(|| anyhow::anyhow!("No sibling named `{sibling_name}`"))?;
 
-    app_state.handle_command(command.into()).await?;
+            GoalRequest::SwapWithSibling {
+                goal_id: selected_goal_id,
+                other_goal_id,
+            }
+        }
+    };
 
-    Ok(true)
+    Ok(Some(app_state.handle_command(command.into()).await?))
 }
 
-async fn handle_goal_command(app_state: &mut AppState, command: GoalCommand) -> anyhow::Result<()> {
-    if !handle_untargeted_goal_command(app_state, command.clone()).await? {
-        // false return means action us unhandled and nothing was triggered
-        handle_targeted_goal_command(app_state, command).await?;
+async fn handle_goal_command(
+    app_state: &mut AppState,
+    command: GoalCommand,
+) -> anyhow::Result<Vec<GoalEvent>> {
+    if let Some(events) = handle_untargeted_goal_command(app_state, command.clone()).await? {
+        return Ok(events);
     }
 
-    Ok(())
+    // None means the command is unhandled and nothing was triggered
+    Ok(handle_targeted_goal_command(app_state, command)
+        .await?
+        .unwrap_or_default())
 }
 
 #[tauri::command]
@@ -163,7 +397,7 @@ pub async fn app_command(
     state: tauri::State<'_, Mutex<AppState>>,
     handle: tauri::AppHandle,
     command: String,
-) -> Result<(), String> {
+) -> Result<Vec<GoalEvent>, String> {
     let mut app_state = state.lock().await;
 
     let (_, command) = parse_command(&command)
@@ -175,18 +409,27 @@ pub async fn app_command(
             .handle_command(AppCommand::DisplayCommand(command))
             .await
             .map_err(|e| e.to_string()),
+        parser::Command::Cursor(cursor_action) => app_state
+            .handle_command(AppCommand::CursorAction(cursor_action))
+            .await
+            .map_err(|e| e.to_string()),
         parser::Command::Goal(command) => handle_goal_command(app_state.deref_mut(), command)
             .await
             .map_err(|e| e.to_string()),
         parser::Command::Control(control_command) => match control_command {
             ControlCommand::SwitchActivity(active_activity) => {
                 app_state.handle_switch_active_state(active_activity);
-                Ok(())
+                Ok(vec![])
             }
             ControlCommand::Quit => {
+                app_state
+                    .save_before_exit()
+                    .await
+                    .map_err(|e| format!("Failed to save before quitting, not quitting: {e}"))?;
+
                 handle.exit(0);
 
-                Ok(())
+                Ok(vec![])
             }
             ControlCommand::Save => app_state
                 .handle_command(AppCommand::SaveRequest)
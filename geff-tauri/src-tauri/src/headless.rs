@@ -0,0 +1,196 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use geff_core::profile::Profile;
+use geff_util::PersistentState as PersistentGoalState;
+
+use crate::app::Config;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeadlessMode {
+    Validate,
+    Repair,
+    /// Write the profile to a file as JSON.
+    Export(PathBuf),
+    /// Replace the profile with one read from a JSON file.
+    Import(PathBuf),
+}
+
+impl HeadlessMode {
+    /// Find the headless mode requested by the command line arguments, if any. Modes
+    /// that take a path are an error without one, so that scripts fail instead of
+    /// starting the UI.
+    pub fn from_args<I: Iterator<Item = String>>(
+        mut args: I,
+    ) -> Result<Option<HeadlessMode>, String> {
+        while let Some(arg) = args.next() {
+            let mut path = || {
+                args.next()
+                    .map(PathBuf::from)
+                    .ok_or_else(|| format!("{arg} requires a path"))
+            };
+
+            match arg.as_str() {
+                "--validate" => return Ok(Some(HeadlessMode::Validate)),
+                "--repair" => return Ok(Some(HeadlessMode::Repair)),
+                "--export" => return Ok(Some(HeadlessMode::Export(path()?))),
+                "--import" => return Ok(Some(HeadlessMode::Import(path()?))),
+                _ => {}
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Load the profile, run a one-shot operation on it and exit without starting the UI.
+/// Validation exits with failure if the profile is inconsistent, while repair fixes
+/// the profile and saves it back to the data file. Export writes the profile to a JSON
+/// file and import replaces the profile with one from a JSON file.
+pub fn run(mode: HeadlessMode) -> ExitCode {
+    let data_path = match PersistentGoalState::<Config>::data_path("geff-tauri") {
+        Ok(data_path) => data_path,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Validation and repair need to see dangling relationships that loading normally
+    // prunes
+    let load_result = match mode {
+        HeadlessMode::Validate | HeadlessMode::Repair => {
+            PersistentGoalState::<Config>::blocking_load_unrepaired(&data_path)
+        }
+        HeadlessMode::Export(_) | HeadlessMode::Import(_) => {
+            PersistentGoalState::<Config>::blocking_load(&data_path)
+        }
+    };
+    let persistent_state = match load_result {
+        Ok(persistent_state) => persistent_state,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match mode {
+        HeadlessMode::Validate => check(persistent_state, &data_path, false),
+        HeadlessMode::Repair => check(persistent_state, &data_path, true),
+        HeadlessMode::Export(export_path) => export(&persistent_state.profile, &export_path),
+        HeadlessMode::Import(import_path) => import(persistent_state, &data_path, &import_path),
+    }
+}
+
+fn check(
+    mut persistent_state: PersistentGoalState<Config>,
+    data_path: &Path,
+    repair: bool,
+) -> ExitCode {
+    let inconsistencies = if repair {
+        persistent_state.profile.repair()
+    } else {
+        persistent_state.profile.validate()
+    };
+
+    for inconsistency in inconsistencies.iter() {
+        println!("{inconsistency}");
+    }
+
+    if inconsistencies.is_empty() {
+        println!("Profile at {} is consistent", data_path.display());
+        return ExitCode::SUCCESS;
+    } else if !repair {
+        return ExitCode::FAILURE;
+    }
+
+    match tauri::async_runtime::block_on(persistent_state.save_to_file(data_path)) {
+        Ok(()) => {
+            println!(
+                "Repaired {} inconsistencies in profile at {}",
+                inconsistencies.len(),
+                data_path.display()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn export(profile: &Profile, export_path: &Path) -> ExitCode {
+    match std::fs::write(export_path, profile.export_json()) {
+        Ok(()) => {
+            println!("Exported profile to {}", export_path.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Failed to write {}: {e}", export_path.display());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn import(
+    mut persistent_state: PersistentGoalState<Config>,
+    data_path: &Path,
+    import_path: &Path,
+) -> ExitCode {
+    let json = match std::fs::read_to_string(import_path) {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("Failed to read {}: {e}", import_path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    persistent_state.profile = match Profile::import_json(&json) {
+        Ok(profile) => profile,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    // The history describes how the replaced profile came to be
+    persistent_state.goal_event_history.clear();
+
+    match tauri::async_runtime::block_on(persistent_state.save_to_file(data_path)) {
+        Ok(()) => {
+            println!(
+                "Imported profile from {} to {}",
+                import_path.display(),
+                data_path.display()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::HeadlessMode;
+
+    fn from_args(args: &[&str]) -> Result<Option<HeadlessMode>, String> {
+        HeadlessMode::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn headless_mode_from_args() {
+        assert_eq!(from_args(&[]), Ok(None));
+        assert_eq!(from_args(&["--validate"]), Ok(Some(HeadlessMode::Validate)));
+        assert_eq!(
+            from_args(&["--export", "goals.json"]),
+            Ok(Some(HeadlessMode::Export(PathBuf::from("goals.json"))))
+        );
+        assert!(from_args(&["--export"]).is_err());
+        assert!(from_args(&["--import"]).is_err());
+    }
+}
@@ -5,16 +5,51 @@
 
 mod app;
 mod command;
+mod headless;
 mod parser;
 
+use std::process::ExitCode;
+
 use app::AppState;
 use command::invoke_handler;
+use headless::HeadlessMode;
 use tauri::async_runtime::Mutex;
+use tauri::{Manager, WindowEvent};
+
+fn main() -> ExitCode {
+    match HeadlessMode::from_args(std::env::args().skip(1)) {
+        Ok(Some(mode)) => return headless::run(mode),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    }
 
-fn main() {
     tauri::Builder::default()
         .manage(Mutex::new(AppState::Unloaded))
         .invoke_handler(invoke_handler())
+        .on_window_event(|event| {
+            // Closing the window quits like :q, so unsaved changes are saved first and
+            // the window stays open if they can't be
+            if let WindowEvent::CloseRequested { api, .. } = event.event() {
+                let state = event.window().state::<Mutex<AppState>>();
+                let save_result = tauri::async_runtime::block_on(async {
+                    state.lock().await.save_before_exit().await
+                });
+
+                if let Err(e) = save_result {
+                    api.prevent_close();
+                    tauri::api::dialog::message(
+                        Some(event.window()),
+                        "Failed to save",
+                        format!("Failed to save before quitting, not quitting: {e}"),
+                    );
+                }
+            }
+        })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
+
+    ExitCode::SUCCESS
 }
@@ -1,13 +1,17 @@
 use nom::{
     branch::alt,
-    bytes::complete::tag,
+    bytes::complete::{escaped_transform, tag},
     character::complete::{alphanumeric1, char, multispace1, none_of, one_of, u32},
-    combinator::{eof, map},
-    multi::{count, many1},
+    combinator::{eof, map, map_opt, opt, value, verify},
+    multi::{count, separated_list1},
     sequence::{delimited, tuple},
     IResult,
 };
 
+use geff_core::goal::Priority;
+use geff_core::{FixedOffset, NaiveTime};
+use geff_util::CursorAction;
+
 use crate::app::{ActiveActivity, CommandlineDisplayCommand, DisplayCommand};
 
 #[derive(Debug, Clone)]
@@ -17,10 +21,13 @@ pub enum GoalCommand {
         effort_to_complete: u32,
     },
     Delete,
+    /// Refine the selected goal, `force` skips confirming refines that may be
+    /// surprising.
     Refine {
         child_name: String,
         child_effort_to_complete: u32,
         parent_effort_removed: u32,
+        force: bool,
     },
     AddEffort {
         effort: u32,
@@ -28,22 +35,80 @@ pub enum GoalCommand {
     RemoveEffort {
         effort: u32,
     },
+    SetEffort {
+        effort: u32,
+    },
+    /// Split effort across the focused goals in proportion to their remaining effort.
+    DistributeEffort {
+        effort: u32,
+    },
     Focus,
     Unfocus,
     FocusSingle,
     UnfocusSingle,
     Rescope {
         new_effort_to_complete: u32,
+        clamp_effort: bool,
     },
     Rename {
         new_name: String,
     },
+    Tag {
+        tag: String,
+    },
+    Untag {
+        tag: String,
+    },
+    SetNotes {
+        notes: String,
+    },
+    /// Move `split_effort` of the selected goal's remaining effort into a new sibling.
+    Split {
+        new_name: String,
+        split_effort: u32,
+    },
+    /// Swap the selected goal with the sibling that has this name.
+    SwapWithSibling {
+        sibling_name: String,
+    },
+    SetPriority {
+        priority: Priority,
+    },
+    /// Focus every goal that has all of the tags.
+    FocusTagged {
+        tags: Vec<String>,
+    },
+    FocusAll,
+    UnfocusAll,
+    SetTimeOfDayConfig {
+        midday_start: NaiveTime,
+        evening_start: NaiveTime,
+    },
+    /// Pin dates and times of day to an offset from UTC, or use the local timezone
+    /// when `None`.
+    SetUtcOffset {
+        utc_offset: Option<FixedOffset>,
+    },
+    /// Display effort in this unit, or without a unit when empty.
+    SetEffortUnit {
+        effort_unit: String,
+    },
 }
 
+/// A non-empty string delimited by double quotes, where `\"` and `\\` are unescaped
+/// to `"` and `\` respectively.
 fn quoted_string(input: &str) -> IResult<&str, String> {
-    map(
-        delimited(char('\"'), many1(none_of("\"")), char('\"')),
-        |r| r.into_iter().collect(),
+    delimited(
+        char('"'),
+        verify(
+            escaped_transform(
+                none_of("\\\""),
+                '\\',
+                alt((value("\\", char('\\')), value("\"", char('"')))),
+            ),
+            |s: &String| !s.is_empty(),
+        ),
+        char('"'),
     )(input)
 }
 
@@ -65,10 +130,22 @@ fn delete_command(input: &str) -> IResult<&str, GoalCommand> {
     map(char('d'), |_| GoalCommand::Delete)(input)
 }
 
+/// Effort with an optional sign, where `-` removes effort and `+` or no sign adds it.
+fn signed_effort(input: &str) -> IResult<&str, GoalCommand> {
+    alt((
+        map(tuple((char('-'), u32)), |(_, effort)| {
+            GoalCommand::RemoveEffort { effort }
+        }),
+        map(tuple((opt(char('+')), u32)), |(_, effort)| {
+            GoalCommand::AddEffort { effort }
+        }),
+    ))(input)
+}
+
 fn add_effort_command(input: &str) -> IResult<&str, GoalCommand> {
     map(
-        tuple((char('e'), multispace1, u32, eof)),
-        |(_, _, effort, _)| GoalCommand::AddEffort { effort },
+        tuple((char('e'), multispace1, signed_effort, eof)),
+        |(_, _, command, _)| command,
     )(input)
 }
 
@@ -79,10 +156,34 @@ fn remove_effort_command(input: &str) -> IResult<&str, GoalCommand> {
     )(input)
 }
 
+fn set_effort_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(
+        tuple((tag("se"), multispace1, u32, eof)),
+        |(_, _, effort, _)| GoalCommand::SetEffort { effort },
+    )(input)
+}
+
+fn distribute_effort_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(
+        tuple((tag("de"), multispace1, u32, eof)),
+        |(_, _, effort, _)| GoalCommand::DistributeEffort { effort },
+    )(input)
+}
+
+fn effort_command(input: &str) -> IResult<&str, GoalCommand> {
+    alt((
+        add_effort_command,
+        remove_effort_command,
+        set_effort_command,
+        distribute_effort_command,
+    ))(input)
+}
+
 fn refine_command(input: &str) -> IResult<&str, GoalCommand> {
     map(
         tuple((
             char('r'),
+            opt(char('!')),
             multispace1,
             name,
             multispace1,
@@ -90,16 +191,39 @@ fn refine_command(input: &str) -> IResult<&str, GoalCommand> {
             multispace1,
             u32,
         )),
-        |(_, _, child_name, _, child_effort_to_complete, _, parent_effort_removed)| {
+        |(_, force, _, child_name, _, child_effort_to_complete, _, parent_effort_removed)| {
             GoalCommand::Refine {
                 child_name,
                 child_effort_to_complete,
                 parent_effort_removed,
+                force: force.is_some(),
             }
         },
     )(input)
 }
 
+fn tag_query(input: &str) -> IResult<&str, Vec<String>> {
+    map(
+        tuple((tag("tag:"), separated_list1(char(','), name))),
+        |(_, tags)| tags,
+    )(input)
+}
+
+fn focus_tagged_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(
+        tuple((char('f'), multispace1, tag_query, eof)),
+        |(_, _, tags, _)| GoalCommand::FocusTagged { tags },
+    )(input)
+}
+
+fn focus_all_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(tuple((tag("fa"), eof)), |_| GoalCommand::FocusAll)(input)
+}
+
+fn unfocus_all_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(tuple((tag("ufa"), eof)), |_| GoalCommand::UnfocusAll)(input)
+}
+
 fn focus_command(input: &str) -> IResult<&str, GoalCommand> {
     map(tag("f"), |_| GoalCommand::Focus)(input)
 }
@@ -116,11 +240,14 @@ fn unfocus_single_command(input: &str) -> IResult<&str, GoalCommand> {
     map(tag("ufs"), |_| GoalCommand::UnfocusSingle)(input)
 }
 
+/// `rs <effort>` rescopes the selected goal, and `rsc <effort>` also clamps its effort
+/// to date to the new effort to complete.
 fn rescope_command(input: &str) -> IResult<&str, GoalCommand> {
     map(
-        tuple((tag("rs"), multispace1, u32)),
-        |(_, _, new_effort_to_complete)| GoalCommand::Rescope {
+        tuple((tag("rs"), opt(char('c')), multispace1, u32)),
+        |(_, clamp, _, new_effort_to_complete)| GoalCommand::Rescope {
             new_effort_to_complete,
+            clamp_effort: clamp.is_some(),
         },
     )(input)
 }
@@ -131,19 +258,150 @@ fn rename_command(input: &str) -> IResult<&str, GoalCommand> {
     })(input)
 }
 
+fn tag_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(
+        tuple((tag("tag"), multispace1, name, eof)),
+        |(_, _, tag, _)| GoalCommand::Tag { tag },
+    )(input)
+}
+
+fn untag_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(
+        tuple((tag("untag"), multispace1, name, eof)),
+        |(_, _, tag, _)| GoalCommand::Untag { tag },
+    )(input)
+}
+
+fn notes_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(
+        tuple((tag("note"), multispace1, quoted_string, eof)),
+        |(_, _, notes, _)| GoalCommand::SetNotes { notes },
+    )(input)
+}
+
+fn split_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(
+        tuple((tag("split"), multispace1, name, multispace1, u32, eof)),
+        |(_, _, new_name, _, split_effort, _)| GoalCommand::Split {
+            new_name,
+            split_effort,
+        },
+    )(input)
+}
+
+fn swap_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(
+        tuple((tag("swap"), multispace1, name, eof)),
+        |(_, _, sibling_name, _)| GoalCommand::SwapWithSibling { sibling_name },
+    )(input)
+}
+
+fn priority_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(
+        tuple((
+            tag("pri"),
+            multispace1,
+            alt((
+                value(Priority::High, tag("high")),
+                value(Priority::Normal, tag("normal")),
+                value(Priority::Low, tag("low")),
+            )),
+            eof,
+        )),
+        |(_, _, priority, _)| GoalCommand::SetPriority { priority },
+    )(input)
+}
+
+fn time(input: &str) -> IResult<&str, NaiveTime> {
+    map_opt(tuple((u32, char(':'), u32)), |(hour, _, minute)| {
+        NaiveTime::from_hms_opt(hour, minute, 0)
+    })(input)
+}
+
+fn time_of_day_config_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(
+        tuple((tag("tod"), multispace1, time, multispace1, time, eof)),
+        |(_, _, midday_start, _, evening_start, _)| GoalCommand::SetTimeOfDayConfig {
+            midday_start,
+            evening_start,
+        },
+    )(input)
+}
+
+/// A UTC offset such as `+09:00` or `-05:30`.
+fn utc_offset(input: &str) -> IResult<&str, FixedOffset> {
+    map_opt(
+        tuple((one_of("+-"), u32, char(':'), u32)),
+        |(sign, hours, _, minutes)| {
+            if minutes >= 60 {
+                return None;
+            }
+            let seconds =
+                i32::try_from(hours.checked_mul(3600)?.checked_add(minutes * 60)?).ok()?;
+
+            if sign == '-' {
+                FixedOffset::west_opt(seconds)
+            } else {
+                FixedOffset::east_opt(seconds)
+            }
+        },
+    )(input)
+}
+
+/// `tz <offset>` pins the timezone to a UTC offset, and `tz local` goes back to the
+/// local timezone.
+fn utc_offset_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(
+        tuple((
+            tag("tz"),
+            multispace1,
+            alt((value(None, tag("local")), map(utc_offset, Some))),
+            eof,
+        )),
+        |(_, _, utc_offset, _)| GoalCommand::SetUtcOffset { utc_offset },
+    )(input)
+}
+
+/// `unit <name>` displays effort in a unit, and `unit` alone removes the unit.
+fn effort_unit_command(input: &str) -> IResult<&str, GoalCommand> {
+    map(
+        tuple((tag("unit"), opt(tuple((multispace1, name))), eof)),
+        |(_, effort_unit, _)| GoalCommand::SetEffortUnit {
+            effort_unit: effort_unit.map_or_else(String::new, |(_, name)| name),
+        },
+    )(input)
+}
+
+fn config_command(input: &str) -> IResult<&str, GoalCommand> {
+    alt((
+        time_of_day_config_command,
+        utc_offset_command,
+        effort_unit_command,
+    ))(input)
+}
+
 fn goal_command(input: &str) -> IResult<&str, GoalCommand> {
     alt((
         create_command,
-        add_effort_command,
-        remove_effort_command,
+        effort_command,
         delete_command,
         refine_command,
+        focus_tagged_command,
+        focus_all_command,
+        unfocus_all_command,
         focus_command,
         unfocus_command,
         focus_single_command,
         unfocus_single_command,
         rescope_command,
         rename_command,
+        tag_command,
+        untag_command,
+        notes_command,
+        split_command,
+        swap_command,
+        priority_command,
+        config_command,
     ))(input)
 }
 
@@ -240,11 +498,24 @@ fn control_command(input: &str) -> IResult<&str, ControlCommand> {
     ))(input)
 }
 
+fn collapse_command(input: &str) -> IResult<&str, CursorAction> {
+    map(tuple((tag("collapse"), eof)), |_| CursorAction::Collapse)(input)
+}
+
+fn expand_command(input: &str) -> IResult<&str, CursorAction> {
+    map(tuple((tag("expand"), eof)), |_| CursorAction::Expand)(input)
+}
+
+fn cursor_command(input: &str) -> IResult<&str, CursorAction> {
+    alt((collapse_command, expand_command))(input)
+}
+
 #[derive(Debug, Clone)]
 pub enum Command {
     Display(DisplayCommand),
     Goal(GoalCommand),
     Control(ControlCommand),
+    Cursor(CursorAction),
 }
 
 pub fn command(input: &str) -> IResult<&str, Command> {
@@ -255,6 +526,7 @@ pub fn command(input: &str) -> IResult<&str, Command> {
                 map(display_command, |display_command| {
                     Command::Display(display_command)
                 }),
+                map(cursor_command, Command::Cursor),
                 map(goal_command, Command::Goal),
                 map(control_command, |control_command| {
                     Command::Control(control_command)
@@ -264,3 +536,175 @@ pub fn command(input: &str) -> IResult<&str, Command> {
         |(_, command)| command,
     )(input)
 }
+
+#[cfg(test)]
+mod tests {
+    use geff_core::goal::Priority;
+
+    use super::{command, Command, GoalCommand};
+
+    fn parse_rename(input: &str) -> Option<String> {
+        match command(input) {
+            Ok(("", Command::Goal(GoalCommand::Rename { new_name }))) => Some(new_name),
+            _ => None,
+        }
+    }
+
+    fn parse_effort(input: &str) -> Option<(bool, u32)> {
+        match command(input) {
+            Ok(("", Command::Goal(GoalCommand::AddEffort { effort }))) => Some((true, effort)),
+            Ok(("", Command::Goal(GoalCommand::RemoveEffort { effort }))) => Some((false, effort)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn forced_refine() {
+        assert!(matches!(
+            command(":r child 1 1"),
+            Ok(("", Command::Goal(GoalCommand::Refine { force: false, .. })))
+        ));
+        assert!(matches!(
+            command(":r! child 1 1"),
+            Ok(("", Command::Goal(GoalCommand::Refine { force: true, .. })))
+        ));
+    }
+
+    #[test]
+    fn effort_unit() {
+        assert!(matches!(
+            command(":unit hrs"),
+            Ok(("", Command::Goal(GoalCommand::SetEffortUnit { effort_unit })))
+                if effort_unit == "hrs"
+        ));
+        assert!(matches!(
+            command(":unit"),
+            Ok(("", Command::Goal(GoalCommand::SetEffortUnit { effort_unit })))
+                if effort_unit.is_empty()
+        ));
+    }
+
+    #[test]
+    fn split() {
+        assert!(matches!(
+            command(":split \"write tests\" 3"),
+            Ok(("", Command::Goal(GoalCommand::Split { new_name, split_effort: 3 })))
+                if new_name == "write tests"
+        ));
+        assert!(command(":split tests").is_err());
+    }
+
+    #[test]
+    fn set_effort() {
+        assert!(matches!(
+            command(":se 4"),
+            Ok(("", Command::Goal(GoalCommand::SetEffort { effort: 4 })))
+        ));
+        assert!(command(":se -4").is_err());
+        assert!(matches!(
+            command(":de 3"),
+            Ok((
+                "",
+                Command::Goal(GoalCommand::DistributeEffort { effort: 3 })
+            ))
+        ));
+        assert!(matches!(
+            command(":d"),
+            Ok(("", Command::Goal(GoalCommand::Delete)))
+        ));
+    }
+
+    #[test]
+    fn focus_all() {
+        assert!(matches!(
+            command(":fa"),
+            Ok(("", Command::Goal(GoalCommand::FocusAll)))
+        ));
+        assert!(matches!(
+            command(":ufa"),
+            Ok(("", Command::Goal(GoalCommand::UnfocusAll)))
+        ));
+    }
+
+    #[test]
+    fn swap_with_sibling() {
+        assert!(matches!(
+            command(r#":swap "Read Dune""#),
+            Ok(("", Command::Goal(GoalCommand::SwapWithSibling { sibling_name })))
+                if sibling_name == "Read Dune"
+        ));
+        assert!(command(":swap").is_err());
+    }
+
+    #[test]
+    fn tag() {
+        assert!(matches!(
+            command(":tag work"),
+            Ok(("", Command::Goal(GoalCommand::Tag { tag }))) if tag == "work"
+        ));
+        assert!(matches!(
+            command(r#":untag "deep work""#),
+            Ok(("", Command::Goal(GoalCommand::Untag { tag }))) if tag == "deep work"
+        ));
+        assert!(command(":tag").is_err());
+        assert!(command(":untag").is_err());
+    }
+
+    #[test]
+    fn priority() {
+        assert!(matches!(
+            command(":pri high"),
+            Ok((
+                "",
+                Command::Goal(GoalCommand::SetPriority {
+                    priority: Priority::High
+                })
+            ))
+        ));
+        assert!(command(":pri urgent").is_err());
+    }
+
+    #[test]
+    fn utc_offset() {
+        let parse_utc_offset = |input| match command(input) {
+            Ok(("", Command::Goal(GoalCommand::SetUtcOffset { utc_offset }))) => {
+                Some(utc_offset.map(|offset| offset.local_minus_utc()))
+            }
+            _ => None,
+        };
+
+        assert_eq!(parse_utc_offset(":tz +09:00"), Some(Some(9 * 3600)));
+        assert_eq!(
+            parse_utc_offset(":tz -05:30"),
+            Some(Some(-(5 * 3600 + 30 * 60)))
+        );
+        assert_eq!(parse_utc_offset(":tz local"), Some(None));
+        assert_eq!(parse_utc_offset(":tz 09:00"), None);
+        assert_eq!(parse_utc_offset(":tz +09:60"), None);
+        assert_eq!(parse_utc_offset(":tz +24:00"), None);
+        assert_eq!(parse_utc_offset(":tz +4000000:00"), None);
+    }
+
+    #[test]
+    fn signed_effort() {
+        assert_eq!(parse_effort(":e 5"), Some((true, 5)));
+        assert_eq!(parse_effort(":e +5"), Some((true, 5)));
+        assert_eq!(parse_effort(":e -3"), Some((false, 3)));
+        assert_eq!(parse_effort(":re 3"), Some((false, 3)));
+        assert_eq!(parse_effort(":e +-3"), None);
+    }
+
+    #[test]
+    fn quoted_names_with_escapes() {
+        assert_eq!(
+            parse_rename(r#":rn "Read \"Dune\"""#).as_deref(),
+            Some(r#"Read "Dune""#)
+        );
+        assert_eq!(
+            parse_rename(r#":rn "trailing \\""#).as_deref(),
+            Some(r"trailing \")
+        );
+        assert_eq!(parse_rename(r#":rn "unterminated \""#), None);
+        assert_eq!(parse_rename(r#":rn """#), None);
+    }
+}
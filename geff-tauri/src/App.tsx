@@ -5,10 +5,17 @@ import {
   useAppDispatch,
   useCommandline,
   useCommandlineDisplayState,
+  useGoalState,
 } from "./Store";
 
 import "./App.scss";
-import { keyboardEvent, loadCommand } from "./Event";
+import {
+  autosaveCommand,
+  keyboardEvent,
+  keydownEvent,
+  loadCommand,
+} from "./Event";
+import { Inspector } from "./Inspector";
 import { RootGoals } from "./RootGoals";
 
 function StatusBar(): JSX.Element {
@@ -62,6 +69,9 @@ function ActiveActivity(): JSX.Element | null {
 
 function App() {
   const dispatch = useAppDispatch();
+  const goalState = useGoalState();
+  const autosaveIntervalSeconds =
+    goalState.type === "loaded" ? goalState.autosaveIntervalSeconds : null;
 
   function dispatchKeyPress(event: KeyboardEvent) {
     dispatch(keyboardEvent(event));
@@ -71,6 +81,20 @@ function App() {
     dispatch(loadCommand());
   }, []);
 
+  useEffect(() => {
+    if (autosaveIntervalSeconds === null) {
+      return;
+    }
+
+    const intervalId = setInterval(() => {
+      dispatch(autosaveCommand());
+    }, autosaveIntervalSeconds * 1000);
+
+    return () => {
+      window.clearInterval(intervalId);
+    };
+  }, [autosaveIntervalSeconds, dispatch]);
+
   useEffect(() => {
     window.addEventListener("keypress", dispatchKeyPress);
 
@@ -79,12 +103,25 @@ function App() {
     };
   }, [dispatchKeyPress, dispatch]);
 
+  useEffect(() => {
+    function dispatchKeyDown(event: KeyboardEvent) {
+      dispatch(keydownEvent(event));
+    }
+
+    window.addEventListener("keydown", dispatchKeyDown);
+
+    return () => {
+      window.removeEventListener("keydown", dispatchKeyDown);
+    };
+  }, [dispatch]);
+
   return (
     <div className="app">
       <div className="main">
         <StatusBar />
         <ActiveActivity />
       </div>
+      <Inspector />
       <Commandline />
     </div>
   );
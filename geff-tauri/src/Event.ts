@@ -3,11 +3,21 @@ import { invoke } from "@tauri-apps/api/tauri";
 import { AnyAction } from "redux";
 import {
   ActiveActivity,
+  closeInspector,
+  CursorAction,
   displayError,
   DisplayState,
+  GoalInspection,
   handleKeyPressEvent,
+  keyActionFor,
+  keyBindingsFromState,
+  KeyBindings,
   load,
+  openInspector,
   PopulatedGoal,
+  ProfileStats,
+  recallHistory,
+  recordCommand,
   RootGetState,
   RootState,
   setActiveActivity,
@@ -48,6 +58,9 @@ function fetchState() {
           populatedGoals: goalState.populatedGoals,
           selectedGoalId: goalState.selectedGoalId,
           focusedGoals: goalState.focusedGoals,
+          effortUnit: goalState.effortUnit,
+          keyBindings: goalState.config.keyBindings,
+          autosaveIntervalSeconds: goalState.config.autosaveIntervalSeconds,
         })
       );
 
@@ -63,8 +76,6 @@ function fetchState() {
   return wrapErrorHandler(fetchStateThunk, { fetchStateAfter: false });
 }
 
-export type CursorAction = "up" | "down" | "in" | "out";
-
 function cursorAction(action: CursorAction) {
   async function cursorActionThunk(dispatch: RootThunkDispatch) {
     await invoke("cursor_action", {
@@ -77,6 +88,18 @@ function cursorAction(action: CursorAction) {
   return wrapErrorHandler(cursorActionThunk);
 }
 
+function openInspectorForSelectedGoal() {
+  async function openInspectorThunk(dispatch: RootThunkDispatch) {
+    const inspection: GoalInspection | null = await invoke("inspect");
+
+    if (inspection !== null) {
+      dispatch(openInspector(inspection));
+    }
+  }
+
+  return wrapErrorHandler(openInspectorThunk, { fetchStateAfter: false });
+}
+
 function invokeSetActiveActivity(activeActivity: ActiveActivity) {
   async function invokeSetActiveActivityThunk() {
     await invoke("set_active_activity", {
@@ -88,11 +111,12 @@ function invokeSetActiveActivity(activeActivity: ActiveActivity) {
 }
 
 function invokeAppCommand(command: string) {
-  async function invokeAppCommandThunk() {
+  async function invokeAppCommandThunk(dispatch: RootThunkDispatch) {
     const result = await invoke("app_command", {
       command,
     });
     console.debug(`Invoke '${command}' returned ${JSON.stringify(result)}`);
+    dispatch(recordCommand({ command }));
 
     return result;
   }
@@ -102,6 +126,8 @@ function invokeAppCommand(command: string) {
 
 type FrontendConfig = {
   display: DisplayState;
+  keyBindings: KeyBindings;
+  autosaveIntervalSeconds: number | null;
 };
 
 type FrontendState = {
@@ -109,11 +135,24 @@ type FrontendState = {
     populatedGoals: Array<PopulatedGoal>;
     selectedGoalId?: number;
     focusedGoals: Array<number>;
+    effortUnit: string;
     config: FrontendConfig;
   };
   activeActivity: ActiveActivity;
 };
 
+export function autosaveCommand() {
+  async function autosaveThunk() {
+    await invoke("autosave");
+  }
+
+  return wrapErrorHandler(autosaveThunk, { fetchStateAfter: false });
+}
+
+export async function fetchStatistics(): Promise<ProfileStats> {
+  return await invoke("statistics");
+}
+
 async function loadCommandThunk(dispatch: RootThunkDispatch) {
   const error = await invoke("load")
     .then(() => null)
@@ -136,41 +175,91 @@ export function keyboardEvent(event: KeyboardEvent) {
     getState: RootGetState
   ) {
     const commandlineState = getState().commandline;
+    const keyAction = keyActionFor(
+      keyBindingsFromState(getState().goal.state),
+      event.key
+    );
+
+    // The cursor and commandline are disabled while inspecting
+    if (getState().inspector.inspection !== null) {
+      if (keyAction === "inspect" || event.key === "q") {
+        dispatch(closeInspector());
+      }
+      return;
+    }
+
     if (event.key === "Enter") {
       if (commandlineState.state.type === "typing") {
         dispatch(invokeAppCommand(commandlineState.state.content));
       }
     }
 
-    dispatch(handleKeyPressEvent(event.key));
+    dispatch(
+      handleKeyPressEvent({
+        key: event.key,
+        isCommandLeader: keyAction === "commandLeader",
+      })
+    );
 
     if (commandlineState.state.type !== "typing") {
-      switch (event.key) {
-        case "q": {
-          const activeActivity = getState().activity.activeActivity;
-          if (activeActivity === "Help") {
-            dispatch(invokeSetActiveActivity("Goals"));
-          }
-        }
-        case "h": {
-          dispatch(cursorAction("out"));
-          break;
-        }
-        case "j": {
-          dispatch(cursorAction("down"));
-          break;
-        }
-        case "k": {
-          dispatch(cursorAction("up"));
-          break;
-        }
-        case "l": {
-          dispatch(cursorAction("in"));
-          break;
+      if (event.key === "q") {
+        const activeActivity = getState().activity.activeActivity;
+        if (activeActivity === "Help") {
+          dispatch(invokeSetActiveActivity("Goals"));
         }
       }
+
+      if (keyAction === "inspect") {
+        dispatch(openInspectorForSelectedGoal());
+      } else if (keyAction === "addEffort") {
+        dispatch(invokeShortcut(":e 1"));
+      } else if (keyAction === "removeEffort") {
+        dispatch(invokeShortcut(":re 1"));
+      } else if (keyAction !== null && keyAction !== "commandLeader") {
+        dispatch(cursorAction(keyAction.cursor));
+      }
     }
   }
 
   return keyboardEventThunk;
 }
+
+/**
+ * Invoke a command from a shortcut without recording it to the commandline
+ * history.
+ */
+function invokeShortcut(command: ":w" | ":q" | ":e 1" | ":re 1") {
+  async function invokeShortcutThunk() {
+    await invoke("app_command", { command });
+  }
+
+  return wrapErrorHandler(invokeShortcutThunk);
+}
+
+/**
+ * Handle keys that do not produce `keypress` events, such as the arrow keys and
+ * shortcuts using Ctrl. Ctrl-S saves and Ctrl-Q saves unsaved changes then quits,
+ * and Escape closes the inspector.
+ */
+export function keydownEvent(event: KeyboardEvent) {
+  function keydownEventThunk(
+    dispatch: RootThunkDispatch,
+    getState: RootGetState
+  ) {
+    if (event.ctrlKey && (event.key === "s" || event.key === "q")) {
+      event.preventDefault();
+      dispatch(invokeShortcut(event.key === "s" ? ":w" : ":q"));
+    } else if (
+      event.key === "Escape" &&
+      getState().inspector.inspection !== null
+    ) {
+      dispatch(closeInspector());
+    } else if (event.key === "ArrowUp") {
+      dispatch(recallHistory({ direction: "older" }));
+    } else if (event.key === "ArrowDown") {
+      dispatch(recallHistory({ direction: "newer" }));
+    }
+  }
+
+  return keydownEventThunk;
+}
@@ -0,0 +1,65 @@
+import {
+  effortUnitFromState,
+  formatEffortProgress,
+  GoalInspection,
+  InspectedEvent,
+  useGoalState,
+  useInspection,
+} from "./Store";
+
+function formatEvent(event: InspectedEvent): string {
+  const start =
+    event.start === null ? "" : " at " + new Date(event.start).toString();
+
+  return "Event " + event.eventId + " " + event.relationship + start;
+}
+
+function InspectorDetails({
+  inspection,
+  effortUnit,
+}: {
+  inspection: GoalInspection;
+  effortUnit: string;
+}): JSX.Element {
+  const path = [...inspection.ancestors, inspection.name].join(" > ");
+  const progressText = formatEffortProgress(
+    inspection.effortToDate,
+    inspection.effortToComplete,
+    effortUnit
+  );
+
+  return (
+    <div className="inspector">
+      <div className="inspector-path">{path}</div>
+      <div>Effort {progressText}</div>
+      <div>
+        Effort remaining in subtree {inspection.subtreeEffortRemaining}
+        {effortUnit === "" ? "" : " " + effortUnit}
+      </div>
+      {inspection.notes !== null ? (
+        <div className="inspector-notes">{inspection.notes}</div>
+      ) : null}
+      {inspection.events.length > 0 ? (
+        <ul className="inspector-events">
+          {inspection.events.map((event) => (
+            <li key={event.eventId + event.relationship}>
+              {formatEvent(event)}
+            </li>
+          ))}
+        </ul>
+      ) : (
+        <div>No related events</div>
+      )}
+    </div>
+  );
+}
+
+/** A read-only panel describing the inspected goal, if any. */
+export function Inspector(): JSX.Element | null {
+  const inspection = useInspection();
+  const effortUnit = effortUnitFromState(useGoalState());
+
+  return inspection === null ? null : (
+    <InspectorDetails inspection={inspection} effortUnit={effortUnit} />
+  );
+}
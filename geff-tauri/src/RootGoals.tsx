@@ -1,7 +1,9 @@
+import { useEffect, useRef } from "react";
 import {
   useGoalState,
   PopulatedGoal,
   useCommandlineDisplayState,
+  formatEffortProgress,
 } from "./Store";
 import StarIcon from "@mui/icons-material/Star";
 
@@ -26,15 +28,11 @@ function colorFromGoalDepth(depth: number, finished: boolean): string {
 
 function GoalStatusIndicator({
   goal,
-  selectedGoalId,
-  focusedGoals,
 }: {
   goal: PopulatedGoal;
-  selectedGoalId?: number;
-  focusedGoals: Array<number>;
 }): JSX.Element | null {
-  const isSelected = selectedGoalId !== null && selectedGoalId === goal.id;
-  const isFocused = focusedGoals.includes(goal.id);
+  const isSelected = goal.selected;
+  const isFocused = goal.focused;
 
   const focusedToken = String.fromCodePoint(0x2605);
 
@@ -55,22 +53,23 @@ function GoalStatusIndicator({
 
 function Goal({
   goal,
-  selectedGoalId,
-  focusedGoals,
   key,
   depth,
+  effortUnit,
 }: {
   goal: PopulatedGoal;
-  selectedGoalId?: number;
-  focusedGoals: Array<number>;
   key: number;
   depth: number;
+  effortUnit: string;
 }): JSX.Element {
-  const isSelected = selectedGoalId !== null && selectedGoalId === goal.id;
-  const hasChildren = goal.children.length > 0;
+  const isSelected = goal.selected;
+  const hasChildren = goal.children.length > 0 && !goal.collapsed;
 
-  const progressText =
-    "(" + goal.effortToDate + "/" + goal.effortToComplete + ")";
+  const progressText = formatEffortProgress(
+    goal.effortToDate,
+    goal.effortToComplete,
+    effortUnit
+  );
 
   return (
     <div
@@ -86,43 +85,41 @@ function Goal({
       key={key}
     >
       <div
-        className="goal-info"
+        className={isSelected ? "goal-info selected-goal-info" : "goal-info"}
         style={{ marginRight: hasChildren ? "4px" : undefined }}
       >
-        <GoalStatusIndicator
-          goal={goal}
-          selectedGoalId={selectedGoalId}
-          focusedGoals={focusedGoals}
-        />
-        <div className="goal-name">{goal.name}</div>
+        <GoalStatusIndicator goal={goal} />
+        <div className="goal-name">
+          {goal.priority === "High" ? "! " + goal.name : goal.name}
+        </div>
         <div className="goal-progress">{progressText}</div>
+        {isSelected && goal.notes !== null ? (
+          <div className="goal-notes">{goal.notes}</div>
+        ) : null}
       </div>
-      <Goals
-        goals={goal.children}
-        selectedGoalId={selectedGoalId}
-        focusedGoals={focusedGoals}
-        depth={depth + 1}
-      />
+      {goal.collapsed ? null : (
+        <Goals
+          goals={goal.children}
+          depth={depth + 1}
+          effortUnit={effortUnit}
+        />
+      )}
     </div>
   );
 }
 
 function Goals({
   goals,
-  selectedGoalId,
-  focusedGoals,
   depth,
+  effortUnit,
 }: {
   goals: Array<PopulatedGoal>;
-  selectedGoalId?: number;
-  focusedGoals: Array<number>;
   depth: number;
+  effortUnit: string;
 }): JSX.Element {
   return (
     <div className="goals">
-      {goals.map((goal) =>
-        Goal({ goal, focusedGoals, selectedGoalId, key: goal.id, depth })
-      )}
+      {goals.map((goal) => Goal({ goal, key: goal.id, depth, effortUnit }))}
     </div>
   );
 }
@@ -132,16 +129,25 @@ export function RootGoals(): JSX.Element {
 
   const goals = useGoalState();
   const { fontSizePixels } = commandlineDisplay;
+  const rootGoalsRef = useRef<HTMLDivElement>(null);
+
+  // Deep descendants are laid out far to the right, so follow the selection
+  useEffect(() => {
+    rootGoalsRef.current
+      ?.querySelector(".selected-goal-info")
+      ?.scrollIntoView({ block: "nearest", inline: "nearest" });
+  });
 
   if (goals.type === "loaded") {
-    const { populatedGoals, focusedGoals, selectedGoalId } = goals;
+    const { populatedGoals, effortUnit } = goals;
     return (
       <div
         className="root-goals"
+        ref={rootGoalsRef}
         style={{ paddingBottom: fontSizePixels + "px" }}
       >
         {populatedGoals.map((goal) =>
-          Goal({ goal, focusedGoals, selectedGoalId, key: goal.id, depth: 0 })
+          Goal({ goal, key: goal.id, depth: 0, effortUnit })
         )}
       </div>
     );
@@ -11,21 +11,37 @@ type CommandlineTyping = { type: "typing"; content: string };
 type CommandlineError = { type: "error"; error: string };
 type CommandlineEmpty = { type: "empty" };
 
+/** The most commands kept in the commandline history. */
+const MAX_COMMAND_HISTORY = 100;
+
 type CommandlineStore = {
   state: CommandlineTyping | CommandlineError | CommandlineEmpty;
+  /** Previously entered commands, oldest first. */
+  history: Array<string>;
+  /** The index into `history` being recalled, if any. */
+  historyPosition: number | null;
+  /** What was typed before recalling history, restored past the newest command. */
+  stashedContent: string | null;
 };
 
+export type HistoryDirection = "older" | "newer";
+
 const commandlineSlice = createSlice({
   name: "commandline",
-  initialState: { state: { type: "empty" } } as CommandlineStore,
+  initialState: {
+    state: { type: "empty" },
+    history: [],
+    historyPosition: null,
+    stashedContent: null,
+  } as CommandlineStore,
   reducers: {
     handleKeyPressEvent: (
       store: CommandlineStore,
-      action: PayloadAction<string>
+      action: PayloadAction<{ key: string; isCommandLeader: boolean }>
     ) => {
-      const key = action.payload;
+      const { key, isCommandLeader } = action.payload;
       if (store.state.type === "empty" || store.state.type === "error") {
-        if (key === ":") {
+        if (isCommandLeader) {
           store.state = { type: "typing", content: ":" };
         } else if (key === "Escape") {
           store.state = { type: "empty" };
@@ -50,6 +66,11 @@ const commandlineSlice = createSlice({
           }
         }
       }
+
+      if (store.state.type !== "typing") {
+        store.historyPosition = null;
+        store.stashedContent = null;
+      }
     },
     displayError: (
       store: CommandlineStore,
@@ -58,6 +79,44 @@ const commandlineSlice = createSlice({
       const { error } = action.payload;
       store.state = { type: "error", error };
     },
+    recordCommand: (
+      store: CommandlineStore,
+      action: PayloadAction<{ command: string }>
+    ) => {
+      store.history.push(action.payload.command);
+      if (store.history.length > MAX_COMMAND_HISTORY) {
+        store.history.splice(0, store.history.length - MAX_COMMAND_HISTORY);
+      }
+      store.historyPosition = null;
+      store.stashedContent = null;
+    },
+    recallHistory: (
+      store: CommandlineStore,
+      action: PayloadAction<{ direction: HistoryDirection }>
+    ) => {
+      if (store.state.type !== "typing" || store.history.length === 0) {
+        return;
+      }
+
+      if (action.payload.direction === "older") {
+        if (store.historyPosition === null) {
+          store.stashedContent = store.state.content;
+          store.historyPosition = store.history.length - 1;
+        } else if (store.historyPosition > 0) {
+          store.historyPosition -= 1;
+        }
+        store.state.content = store.history[store.historyPosition];
+      } else if (store.historyPosition !== null) {
+        if (store.historyPosition < store.history.length - 1) {
+          store.historyPosition += 1;
+          store.state.content = store.history[store.historyPosition];
+        } else {
+          store.state.content = store.stashedContent ?? ":";
+          store.historyPosition = null;
+          store.stashedContent = null;
+        }
+      }
+    },
   },
 });
 
@@ -75,7 +134,12 @@ export function formatCommandline(state: CommandlineStore) {
   }
 }
 
-export const { handleKeyPressEvent, displayError } = commandlineSlice.actions;
+export const {
+  handleKeyPressEvent,
+  displayError,
+  recordCommand,
+  recallHistory,
+} = commandlineSlice.actions;
 
 export type PopulatedGoal = {
   id: number;
@@ -86,6 +150,71 @@ export type PopulatedGoal = {
   maxChildLayerWidth: number;
   maxChildDepth: number;
   children: Array<PopulatedGoal>;
+  selected: boolean;
+  collapsed: boolean;
+  notes: string | null;
+  deadline: string | null;
+  focused: boolean;
+  priority: Priority;
+};
+
+export type Priority = "Low" | "Normal" | "High";
+
+export type CursorAction =
+  | "up"
+  | "down"
+  | "in"
+  | "out"
+  | "top"
+  | "bottom"
+  | "root"
+  | "collapse"
+  | "expand";
+
+export type KeyAction =
+  | { cursor: CursorAction }
+  | "commandLeader"
+  | "inspect"
+  | "addEffort"
+  | "removeEffort";
+
+export type KeyBindings = {
+  bindings: Record<string, KeyAction>;
+};
+
+export const DEFAULT_KEY_BINDINGS: KeyBindings = {
+  bindings: {
+    ":": "commandLeader",
+    h: { cursor: "out" },
+    j: { cursor: "down" },
+    k: { cursor: "up" },
+    l: { cursor: "in" },
+    g: { cursor: "top" },
+    G: { cursor: "bottom" },
+    "0": { cursor: "root" },
+    z: { cursor: "collapse" },
+    o: { cursor: "expand" },
+    i: "inspect",
+    "+": "addEffort",
+    "-": "removeEffort",
+  },
+};
+
+export function keyActionFor(
+  keyBindings: KeyBindings,
+  key: string
+): KeyAction | null {
+  return keyBindings.bindings[key] ?? null;
+}
+
+export type ProfileStats = {
+  goalCount: number;
+  finishedCount: number;
+  unfinishedCount: number;
+  totalEffortToDate: number;
+  totalEffortRemaining: number;
+  maxTreeDepth: number;
+  focusedCount: number;
 };
 
 export type GoalStateLoaded = {
@@ -93,6 +222,10 @@ export type GoalStateLoaded = {
   populatedGoals: Array<PopulatedGoal>;
   selectedGoalId?: number;
   focusedGoals: Array<number>;
+  /** The label effort is displayed in, empty if effort has no unit. */
+  effortUnit: string;
+  keyBindings: KeyBindings;
+  autosaveIntervalSeconds: number | null;
 };
 
 type GoalStateUnloaded = {
@@ -115,8 +248,72 @@ export function useGoalState(): GoalStateLoaded | GoalStateUnloaded {
   return useSelector((root: RootState) => root.goal.state);
 }
 
+export function effortUnitFromState(
+  state: GoalStateLoaded | GoalStateUnloaded
+): string {
+  return state.type === "loaded" ? state.effortUnit : "";
+}
+
+/** Progress such as `(3/8 hrs)`, or `(3/8)` without a unit. */
+export function formatEffortProgress(
+  effortToDate: number,
+  effortToComplete: number,
+  effortUnit: string
+): string {
+  const unit = effortUnit === "" ? "" : " " + effortUnit;
+
+  return "(" + effortToDate + "/" + effortToComplete + unit + ")";
+}
+
+export function keyBindingsFromState(
+  state: GoalStateLoaded | GoalStateUnloaded
+): KeyBindings {
+  return state.type === "loaded" ? state.keyBindings : DEFAULT_KEY_BINDINGS;
+}
+
 export const { load } = goalSlice.actions;
 
+export type InspectedEvent = {
+  eventId: number;
+  relationship: string;
+  start: string | null;
+};
+
+export type GoalInspection = {
+  id: number;
+  name: string;
+  notes: string | null;
+  effortToDate: number;
+  effortToComplete: number;
+  subtreeEffortRemaining: number;
+  ancestors: Array<string>;
+  events: Array<InspectedEvent>;
+};
+
+type InspectorState = { inspection: GoalInspection | null };
+
+const inspectorSlice = createSlice({
+  name: "inspector",
+  initialState: { inspection: null } as InspectorState,
+  reducers: {
+    openInspector: (
+      state: InspectorState,
+      action: PayloadAction<GoalInspection>
+    ) => {
+      state.inspection = action.payload;
+    },
+    closeInspector: (state: InspectorState) => {
+      state.inspection = null;
+    },
+  },
+});
+
+export function useInspection(): GoalInspection | null {
+  return useSelector((root: RootState) => root.inspector.inspection);
+}
+
+export const { openInspector, closeInspector } = inspectorSlice.actions;
+
 export type ActiveActivity = "Goals" | "Help";
 
 export type ActivityState = { activeActivity: ActiveActivity };
@@ -183,6 +380,7 @@ const rootReducer = combineReducers({
   goal: goalSlice.reducer,
   display: displaySlice.reducer,
   activity: activitySlice.reducer,
+  inspector: inspectorSlice.reducer,
 });
 
 const store = configureStore({ reducer: rootReducer });
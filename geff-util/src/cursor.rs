@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize};
 
 use geff_core::goal::{GoalId, PopulatedGoal};
-use geff_core::profile::goal_traversal::{traverse_populated_goal_children, GoalChildIndexPath};
+use geff_core::profile::goal_traversal::{
+    traverse_populated_goal_children, traverse_populated_goal_children_mut, GoalChildIndexPath,
+};
+use geff_core::profile::Profile;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct SelectedGoal {
     root_goal_index: usize,
     child_index_path: GoalChildIndexPath,
@@ -26,13 +29,22 @@ impl SelectedGoal {
     }
 }
 
+/// A cursor position that can be persisted between sessions. The id of the selected
+/// goal is kept alongside its path so that the selection can be found again even if
+/// goals were reordered or deleted in the meantime.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SavedCursor {
+    selected_goal: SelectedGoal,
+    goal_id: GoalId,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum CursorError {
     #[error("root index of selected goal does not exist: {0:?}")]
     InvalidRootIndex(SelectedGoal),
-    #[error("attempted to visit nonexistent child index {child_index} in goal {goal:?}")]
+    #[error("attempted to visit nonexistent child index {child_index} in goal {}", goal.id)]
     InvalidGoalChild {
-        goal: PopulatedGoal,
+        goal: Box<PopulatedGoal>,
         child_index: usize,
     },
     #[error("error attempting to traverse to selected goal at {0:?}")]
@@ -60,6 +72,19 @@ pub enum CursorAction {
     In,
     #[serde(rename = "out")]
     Out,
+    #[serde(rename = "top")]
+    Top,
+    #[serde(rename = "bottom")]
+    Bottom,
+    /// Select the root goal of the selected goal's tree.
+    #[serde(rename = "root")]
+    Root,
+    /// Collapse the selected goal with [collapse_selected_goal](Cursor::collapse_selected_goal).
+    #[serde(rename = "collapse")]
+    Collapse,
+    /// Expand the selected goal with [collapse_selected_goal](Cursor::collapse_selected_goal).
+    #[serde(rename = "expand")]
+    Expand,
 }
 
 pub fn selected_goal_siblings<'a>(
@@ -76,7 +101,7 @@ pub fn selected_goal_siblings<'a>(
                 .children
                 .get(*index)
                 .ok_or(CursorError::InvalidGoalChild {
-                    goal: current.clone(),
+                    goal: Box::new(current.clone()),
                     child_index: *index,
                 })?;
         }
@@ -100,7 +125,7 @@ pub fn get_selected_goal<'a>(
             .children
             .get(*index)
             .ok_or(CursorError::InvalidGoalChild {
-                goal: current.clone(),
+                goal: Box::new(current.clone()),
                 child_index: *index,
             })?;
     }
@@ -116,7 +141,129 @@ pub fn get_selected_goal_id(
     Ok(selected_goal_data.id)
 }
 
+fn find_selected_goal(goal_id: GoalId, goals: &[PopulatedGoal]) -> Option<SelectedGoal> {
+    goals
+        .iter()
+        .enumerate()
+        .find_map(|(root_goal_index, root_goal)| {
+            root_goal
+                .find(goal_id)
+                .map(|(_, child_index_path)| SelectedGoal {
+                    root_goal_index,
+                    child_index_path,
+                })
+        })
+}
+
+/// Populate the goals of a profile with the goal selected by the cursor marked as
+/// [selected](PopulatedGoal::selected), for frontends that want the selection as part
+/// of the goal tree rather than tracking it separately.
+pub fn populate_goals_with_selection(profile: &Profile, cursor: &Cursor) -> Vec<PopulatedGoal> {
+    let mut goals = profile.populate_goals();
+    cursor.mark_selection(&mut goals);
+    goals
+}
+
 impl Cursor {
+    /// Mark the goal selected by the cursor as [selected](PopulatedGoal::selected).
+    /// Does nothing if no goal is selected or the selection is not valid for `goals`.
+    pub fn mark_selection(&self, goals: &mut [PopulatedGoal]) {
+        if let Cursor::SelectedGoal(Some(selected_goal)) = self {
+            if let Some(selected_goal_data) =
+                goals
+                    .get_mut(selected_goal.root_goal_index)
+                    .and_then(|root_goal| {
+                        traverse_populated_goal_children_mut(
+                            root_goal,
+                            &selected_goal.child_index_path,
+                        )
+                    })
+            {
+                selected_goal_data.selected = true;
+            }
+        }
+    }
+
+    pub fn save(&self, goals: &[PopulatedGoal]) -> Option<SavedCursor> {
+        match self {
+            Cursor::SelectedGoal(Some(selected_goal)) => get_selected_goal_id(selected_goal, goals)
+                .ok()
+                .map(|goal_id| SavedCursor {
+                    selected_goal: selected_goal.clone(),
+                    goal_id,
+                }),
+            Cursor::SelectedGoal(None) => None,
+        }
+    }
+
+    /// Restore a cursor saved with [save](Cursor::save) against freshly populated goals.
+    /// The saved path is used if it still points at the saved goal, otherwise the goal
+    /// is searched for by id. If the goal no longer exists the cursor is cleared.
+    pub fn restore(saved_cursor: Option<&SavedCursor>, goals: &[PopulatedGoal]) -> Cursor {
+        let Some(SavedCursor {
+            selected_goal,
+            goal_id,
+        }) = saved_cursor
+        else {
+            return Cursor::default();
+        };
+
+        if get_selected_goal_id(selected_goal, goals).ok() == Some(*goal_id) {
+            Cursor::SelectedGoal(Some(selected_goal.clone()))
+        } else {
+            Cursor::SelectedGoal(find_selected_goal(*goal_id, goals))
+        }
+    }
+
+    /// Make sure the cursor points at a goal after the goal tree changes structurally.
+    /// Indices past the end of their siblings are moved to the last sibling and path
+    /// components that no longer exist are dropped, so the cursor ends up on the
+    /// nearest remaining sibling or ancestor of where it was.
+    pub fn clamp_to(&mut self, goals: &[PopulatedGoal]) {
+        let Cursor::SelectedGoal(Some(selected_goal)) = self else {
+            return;
+        };
+
+        let Some(last_root_goal_index) = goals.len().checked_sub(1) else {
+            *self = Cursor::SelectedGoal(None);
+            return;
+        };
+        selected_goal.root_goal_index = selected_goal.root_goal_index.min(last_root_goal_index);
+
+        let mut current = &goals[selected_goal.root_goal_index];
+        let mut valid_path_len = 0;
+        for index in selected_goal.child_index_path.iter_mut() {
+            let Some(last_child_index) = current.children.len().checked_sub(1) else {
+                break;
+            };
+            *index = (*index).min(last_child_index);
+            current = &current.children[*index];
+            valid_path_len += 1;
+        }
+        selected_goal.child_index_path.truncate(valid_path_len);
+    }
+
+    /// Collapse or expand the selected goal in `profile` for a
+    /// [Collapse](CursorAction::Collapse) or [Expand](CursorAction::Expand) action.
+    /// Returns whether the profile changed, in which case goals should be repopulated.
+    pub fn collapse_selected_goal(
+        &self,
+        action: CursorAction,
+        profile: &mut Profile,
+        goals: &[PopulatedGoal],
+    ) -> Result<bool, CursorError> {
+        let Cursor::SelectedGoal(Some(selected_goal)) = self else {
+            return Ok(false);
+        };
+        let goal_id = get_selected_goal_id(selected_goal, goals)?;
+
+        Ok(match action {
+            CursorAction::Collapse => profile.collapse_goal(goal_id),
+            CursorAction::Expand => profile.expand_goal(goal_id),
+            _ => false,
+        })
+    }
+
     pub fn handle_action(
         &mut self,
         action: CursorAction,
@@ -156,7 +303,9 @@ impl Cursor {
                             )
                             .ok_or(CursorError::TraversalError(selected_goal.clone()))?;
 
-                            if !selected_goal_data.children.is_empty() {
+                            if !selected_goal_data.children.is_empty()
+                                && !selected_goal_data.collapsed
+                            {
                                 selected_goal.push_child(0);
                             }
 
@@ -168,6 +317,23 @@ impl Cursor {
                             }
                             Ok(())
                         }
+                        Top => {
+                            *selected_goal.selected_index() = 0;
+                            Ok(())
+                        }
+                        Bottom => {
+                            let sibling_goals = selected_goal_siblings(selected_goal, goals)?;
+
+                            *selected_goal.selected_index() = sibling_goals.len().saturating_sub(1);
+                            Ok(())
+                        }
+                        Root => {
+                            selected_goal.child_index_path.clear();
+                            Ok(())
+                        }
+                        // The selection stays on the collapsed or expanded goal, so it
+                        // remains visible either way.
+                        Collapse | Expand => Ok(()),
                     },
                     None => {
                         if !goals.is_empty() {
@@ -184,3 +350,135 @@ impl Cursor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use geff_core::{goal::Goal, profile::Profile};
+
+    use super::{find_selected_goal, Cursor, CursorAction};
+
+    #[test]
+    fn restore_saved_cursor() {
+        let mut profile = Profile::default();
+
+        let root_id = profile.add_goal(Goal::new("root", 0)).unwrap();
+        let first_child_id = profile
+            .refine_goal(Goal::new("first child", 0), root_id, 0)
+            .unwrap();
+        let second_child_id = profile
+            .refine_goal(Goal::new("second child", 0), root_id, 0)
+            .unwrap();
+
+        let goals = profile.populate_goals();
+
+        let mut cursor = Cursor::default();
+        for action in [CursorAction::Down, CursorAction::In, CursorAction::Down] {
+            cursor.handle_action(action, &goals).unwrap();
+        }
+
+        let saved_cursor = cursor.save(&goals);
+        assert_eq!(Cursor::restore(saved_cursor.as_ref(), &goals), cursor);
+
+        profile.remove_goal(first_child_id);
+        let goals = profile.populate_goals();
+
+        let restored_cursor = Cursor::restore(saved_cursor.as_ref(), &goals);
+        assert_eq!(
+            restored_cursor.save(&goals).unwrap().goal_id,
+            second_child_id
+        );
+
+        profile.remove_goal(second_child_id);
+        let goals = profile.populate_goals();
+
+        assert_eq!(
+            Cursor::restore(saved_cursor.as_ref(), &goals),
+            Cursor::SelectedGoal(None)
+        );
+    }
+
+    #[test]
+    fn clamp_cursor() {
+        let mut profile = Profile::default();
+
+        let first_root_id = profile.add_goal(Goal::new("first root", 0)).unwrap();
+        let child_id = profile
+            .refine_goal(Goal::new("child", 0), first_root_id, 0)
+            .unwrap();
+        let grandchild_id = profile
+            .refine_goal(Goal::new("grandchild", 0), child_id, 0)
+            .unwrap();
+        let second_root_id = profile.add_goal(Goal::new("second root", 0)).unwrap();
+
+        let goals = profile.populate_goals();
+        let mut cursor = Cursor::SelectedGoal(find_selected_goal(grandchild_id, &goals));
+
+        profile.remove_goal(grandchild_id);
+        let goals = profile.populate_goals();
+        cursor.clamp_to(&goals);
+        assert_eq!(cursor.save(&goals).unwrap().goal_id, child_id);
+
+        profile.remove_goal(first_root_id);
+        let goals = profile.populate_goals();
+        cursor.clamp_to(&goals);
+        assert_eq!(cursor.save(&goals).unwrap().goal_id, second_root_id);
+
+        profile.remove_goal(second_root_id);
+        let goals = profile.populate_goals();
+        cursor.clamp_to(&goals);
+        assert_eq!(cursor, Cursor::SelectedGoal(None));
+    }
+
+    #[test]
+    fn select_root() {
+        let mut profile = Profile::default();
+
+        profile.add_goal(Goal::new("first root", 0)).unwrap();
+        let root_id = profile.add_goal(Goal::new("second root", 0)).unwrap();
+        let child_id = profile
+            .refine_goal(Goal::new("child", 0), root_id, 0)
+            .unwrap();
+        profile
+            .refine_goal(Goal::new("grandchild", 0), child_id, 0)
+            .unwrap();
+
+        let goals = profile.populate_goals();
+        let mut cursor = Cursor::SelectedGoal(find_selected_goal(child_id, &goals));
+        cursor.handle_action(CursorAction::In, &goals).unwrap();
+
+        cursor.handle_action(CursorAction::Root, &goals).unwrap();
+        assert_eq!(cursor.save(&goals).unwrap().goal_id, root_id);
+
+        cursor.handle_action(CursorAction::Root, &goals).unwrap();
+        assert_eq!(cursor.save(&goals).unwrap().goal_id, root_id);
+    }
+
+    #[test]
+    fn collapse_selected_goal() {
+        let mut profile = Profile::default();
+
+        let root_id = profile.add_goal(Goal::new("root", 0)).unwrap();
+        profile
+            .refine_goal(Goal::new("child", 0), root_id, 0)
+            .unwrap();
+
+        let goals = profile.populate_goals();
+        let mut cursor = Cursor::default();
+        cursor.handle_action(CursorAction::Down, &goals).unwrap();
+
+        assert!(cursor
+            .collapse_selected_goal(CursorAction::Collapse, &mut profile, &goals)
+            .unwrap());
+        let goals = profile.populate_goals();
+        assert!(goals[0].collapsed);
+
+        cursor.handle_action(CursorAction::In, &goals).unwrap();
+        assert_eq!(cursor.save(&goals).unwrap().goal_id, root_id);
+
+        assert!(cursor
+            .collapse_selected_goal(CursorAction::Expand, &mut profile, &goals)
+            .unwrap());
+        let goals = profile.populate_goals();
+        assert!(!goals[0].collapsed);
+    }
+}
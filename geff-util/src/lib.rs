@@ -1,7 +1,8 @@
 mod cursor;
 pub use cursor::{
-    get_selected_goal, get_selected_goal_id, Cursor, CursorAction, CursorError, SelectedGoal,
+    get_selected_goal, get_selected_goal_id, populate_goals_with_selection, Cursor, CursorAction,
+    CursorError, SavedCursor, SelectedGoal,
 };
 
 mod persistent_state;
-pub use persistent_state::{LoadError, PersistentState, SaveError};
+pub use persistent_state::{LoadError, PersistentState, SaveError, DEFAULT_PROFILE_NAME};
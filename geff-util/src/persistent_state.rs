@@ -1,16 +1,23 @@
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::path::PathBuf;
 
-use geff_core::goal::GoalEvent;
+use geff_core::goal::{GoalEvent, TimedGoalEvent};
 use geff_core::profile::Profile;
+use geff_core::{DateTime, Duration, Utc};
 
 use serde::de::DeserializeOwned;
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::SavedCursor;
 
 mod native;
 mod wasm;
 
+/// The name of the profile stored at the data path of an app.
+pub const DEFAULT_PROFILE_NAME: &str = "default";
+
 #[allow(unused)]
 #[derive(thiserror::Error, Debug, Clone, Serialize, Deserialize)]
 pub enum LoadError {
@@ -24,6 +31,13 @@ pub enum LoadError {
     MalformedProfileDataFile(PathBuf, String),
     #[error("Failed to write default data to new file at {0}: {1}")]
     FailureToWriteDefaultData(PathBuf, String),
+    #[error("Profile data at {path} is corrupt and could not be recovered (backup available: {backup_available})")]
+    Corrupt {
+        path: PathBuf,
+        backup_available: bool,
+    },
+    #[error("Invalid profile name {0:?}, profile names must be non-empty and can't contain path separators or '..'")]
+    InvalidProfileName(String),
 }
 
 #[derive(thiserror::Error, Debug, Clone, Serialize, Deserialize)]
@@ -40,14 +54,365 @@ where
     C: std::fmt::Debug + Serialize + Clone + Default,
 {
     pub profile: Profile,
-    pub goal_event_history: Vec<GoalEvent>,
+    #[serde(deserialize_with = "deserialize_history")]
+    pub goal_event_history: Vec<TimedGoalEvent>,
     pub config: C,
+    #[serde(default)]
+    pub last_cursor: Option<SavedCursor>,
+}
+
+/// An entry of a saved history, which did not record when events happened before
+/// every event was timed.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedHistoryEntry {
+    Timed(TimedGoalEvent),
+    Untimed(GoalEvent),
+}
+
+/// Deserialize a history where events saved without a time are timed at the epoch.
+fn deserialize_history<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<TimedGoalEvent>, D::Error> {
+    let entries = Vec::<SavedHistoryEntry>::deserialize(deserializer)?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| match entry {
+            SavedHistoryEntry::Timed(timed_event) => timed_event,
+            SavedHistoryEntry::Untimed(event) => TimedGoalEvent {
+                at: DateTime::<Utc>::default(),
+                event,
+            },
+        })
+        .collect())
+}
+
+const CSV_HEADER: &str = "id,parent_id,name,effort_to_date,effort_to_complete,depth,focused";
+
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl<C> PersistentState<C>
+where
+    C: std::fmt::Debug + Serialize + Clone + Default,
+{
+    /// Build state from its parts without touching the filesystem, with no saved
+    /// cursor.
+    pub fn from_parts(
+        profile: Profile,
+        goal_event_history: Vec<TimedGoalEvent>,
+        config: C,
+    ) -> Self {
+        PersistentState {
+            profile,
+            goal_event_history,
+            config,
+            last_cursor: None,
+        }
+    }
+
+    /// Repair problems left in state by older versions, run after loading.
+    fn repaired(mut self) -> Self {
+        self.profile.prune_dangling_relationships();
+        self
+    }
+
+    /// Empty state that is never loaded from or saved to disk unless asked to.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Replace the config with one derived from the current config, keeping the
+    /// profile, history and cursor. Useful for loading state without a config and
+    /// attaching one later, or for migrating between config types.
+    pub fn map_config<D, F>(self, f: F) -> PersistentState<D>
+    where
+        D: std::fmt::Debug + Serialize + Clone + Default,
+        F: FnOnce(C) -> D,
+    {
+        PersistentState {
+            profile: self.profile,
+            goal_event_history: self.goal_event_history,
+            config: f(self.config),
+            last_cursor: self.last_cursor,
+        }
+    }
+
+    /// Export every goal in the profile as CSV, one row per goal in pre-order
+    /// with a header row first. Depth is zero for root goals.
+    pub fn export_csv(&self) -> String {
+        let focused_goals = self.profile.focused_goals();
+        let mut csv = format!("{CSV_HEADER}\n");
+
+        for root_goal in self.profile.populate_goals() {
+            for (depth, goal) in root_goal.flatten() {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{}\n",
+                    goal.id.0,
+                    goal.parent_goal_id
+                        .map(|id| id.0.to_string())
+                        .unwrap_or_default(),
+                    escape_csv_field(&goal.name),
+                    goal.effort_to_date,
+                    goal.effort_to_complete,
+                    depth,
+                    focused_goals.contains(&goal.id),
+                ));
+            }
+        }
+
+        csv
+    }
+
+    /// Sum the net effort of the effort events in the goal event history into buckets
+    /// of length `bucket`, starting from the earliest event. Only buckets that effort
+    /// events fall into are included, and removed effort can bring a bucket down to
+    /// zero but not below. Effort events recorded before events carried a time are
+    /// skipped. Returns nothing if `bucket` is shorter than a millisecond.
+    pub fn effort_timeline(&self, bucket: Duration) -> Vec<(DateTime<Utc>, u32)> {
+        let bucket_milliseconds = bucket.num_milliseconds();
+        if bucket_milliseconds <= 0 {
+            return vec![];
+        }
+
+        let effort_changes: Vec<(DateTime<Utc>, i64)> = self
+            .goal_event_history
+            .iter()
+            .filter_map(|timed_event| timed_event.event.effort_change())
+            .filter(|(_, _, datetime)| *datetime != DateTime::<Utc>::default())
+            .map(|(_, effort, datetime)| (datetime, effort))
+            .collect();
+
+        let Some(start) = effort_changes.iter().map(|(datetime, _)| *datetime).min() else {
+            return vec![];
+        };
+
+        // Keyed by bucket index so that sparse histories don't allocate empty buckets
+        let mut buckets: BTreeMap<i64, i64> = BTreeMap::new();
+        for (datetime, effort) in effort_changes {
+            let index = (datetime - start).num_milliseconds() / bucket_milliseconds;
+            *buckets.entry(index).or_default() += effort;
+        }
+
+        buckets
+            .into_iter()
+            .filter_map(|(index, effort)| {
+                let offset = Duration::milliseconds(index.checked_mul(bucket_milliseconds)?);
+
+                Some((
+                    start.checked_add_signed(offset)?,
+                    effort.clamp(0, u32::MAX as i64) as u32,
+                ))
+            })
+            .collect()
+    }
+
+    /// The events of the goal event history that happened from `start` up to but not
+    /// including `end`, in the order they were recorded. Events saved before every
+    /// event was timed are treated as happening at the epoch.
+    pub fn history_between(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Vec<&TimedGoalEvent> {
+        self.goal_event_history
+            .iter()
+            .filter(|timed_event| start <= timed_event.at && timed_event.at < end)
+            .collect()
+    }
 }
 
 impl<C: std::fmt::Debug + Serialize + Clone + Default + DeserializeOwned> From<PersistentState<C>>
-    for (Profile, Vec<GoalEvent>, C)
+    for (Profile, Vec<TimedGoalEvent>, C)
 {
     fn from(value: PersistentState<C>) -> Self {
         (value.profile, value.goal_event_history, value.config)
     }
 }
+
+impl<C: std::fmt::Debug + Serialize + Clone + Default> From<(Profile, Vec<TimedGoalEvent>, C)>
+    for PersistentState<C>
+{
+    fn from((profile, goal_event_history, config): (Profile, Vec<TimedGoalEvent>, C)) -> Self {
+        PersistentState::from_parts(profile, goal_event_history, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use geff_core::goal::{Goal, GoalEvent, GoalId, TimedGoalEvent};
+    use geff_core::profile::Profile;
+    use geff_core::{DateTime, Duration, TimeZone, Utc};
+    use serde::Serialize;
+
+    use super::{PersistentState, CSV_HEADER};
+
+    fn timed(at: DateTime<Utc>, event: GoalEvent) -> TimedGoalEvent {
+        TimedGoalEvent { at, event }
+    }
+
+    #[test]
+    fn load_effort_events_without_datetime() {
+        #[derive(Serialize)]
+        enum LegacyGoalEvent {
+            AddEffort { goal_id: GoalId, effort: u32 },
+        }
+
+        let legacy_history = rmp_serde::to_vec(&vec![LegacyGoalEvent::AddEffort {
+            goal_id: GoalId(0),
+            effort: 3,
+        }])
+        .unwrap();
+
+        let history: Vec<GoalEvent> = rmp_serde::from_slice(&legacy_history).unwrap();
+        assert_eq!(
+            history,
+            vec![GoalEvent::AddEffort {
+                goal_id: GoalId(0),
+                effort: 3,
+                datetime: DateTime::<Utc>::default(),
+            }]
+        );
+    }
+
+    #[test]
+    fn load_history_without_times() {
+        let untimed_state = (
+            Profile::default(),
+            vec![GoalEvent::FocusSingle(GoalId(0))],
+            3u32,
+            None::<()>,
+        );
+        let state: PersistentState<u32> =
+            rmp_serde::from_slice(&rmp_serde::to_vec(&untimed_state).unwrap()).unwrap();
+        assert_eq!(
+            state.goal_event_history,
+            vec![timed(
+                DateTime::<Utc>::default(),
+                GoalEvent::FocusSingle(GoalId(0))
+            )]
+        );
+
+        let state: PersistentState<u32> =
+            rmp_serde::from_slice(&rmp_serde::to_vec(&state).unwrap()).unwrap();
+        assert_eq!(state.goal_event_history.len(), 1);
+    }
+
+    #[test]
+    fn history_between() {
+        let start = Utc.with_ymd_and_hms(2023, 1, 2, 9, 0, 0).unwrap();
+        let event_at = |hours| {
+            timed(
+                start + Duration::hours(hours),
+                GoalEvent::FocusAll {
+                    focused_goals: HashSet::new(),
+                },
+            )
+        };
+
+        let state = PersistentState::<()> {
+            goal_event_history: vec![event_at(0), event_at(1), event_at(2), event_at(3)],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            state.history_between(start + Duration::hours(1), start + Duration::hours(3)),
+            vec![&event_at(1), &event_at(2)]
+        );
+        assert!(state.history_between(start, start).is_empty());
+    }
+
+    #[test]
+    fn from_parts() {
+        let mut profile = Profile::default();
+        let goal_id = profile.add_goal(Goal::new("goal", 1)).unwrap();
+        let history = vec![timed(Utc::now(), GoalEvent::FocusSingle(goal_id))];
+
+        let state = PersistentState::from_parts(profile, history.clone(), 3u32);
+        assert!(state.last_cursor.is_none());
+
+        let (profile, goal_event_history, config) = state.into();
+        assert_eq!(profile.goal_ids(), HashSet::from([goal_id]));
+        assert_eq!(goal_event_history, history);
+        assert_eq!(config, 3);
+
+        assert_eq!(PersistentState::<()>::in_memory().profile.goal_count(), 0);
+    }
+
+    #[test]
+    fn export_csv() {
+        let mut state = PersistentState::<()>::default();
+
+        let root_id = state
+            .profile
+            .add_goal(Goal::new("root, with comma", 10))
+            .unwrap();
+        state
+            .profile
+            .refine_goal(Goal::new("child", 4), root_id, 4)
+            .unwrap();
+        state.profile.add_goal(Goal::new("other root", 2)).unwrap();
+
+        let csv = state.export_csv();
+        let mut lines = csv.lines();
+
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        assert_eq!(lines.count(), state.profile.goal_count());
+        assert!(csv.contains("\"root, with comma\""));
+    }
+
+    #[test]
+    fn effort_timeline() {
+        let start = Utc.with_ymd_and_hms(2023, 1, 2, 9, 0, 0).unwrap();
+        let effort_event = |hours, effort: i64| {
+            let (goal_id, datetime) = (GoalId(0), start + Duration::hours(hours));
+            let event = if effort >= 0 {
+                GoalEvent::AddEffort {
+                    goal_id,
+                    effort: effort as u32,
+                    datetime,
+                }
+            } else {
+                GoalEvent::RemoveEffort {
+                    goal_id,
+                    effort: -effort as u32,
+                    datetime,
+                }
+            };
+
+            timed(datetime, event)
+        };
+
+        let state = PersistentState::<()> {
+            goal_event_history: vec![
+                effort_event(0, 2),
+                timed(start, GoalEvent::FocusSingle(GoalId(0))),
+                effort_event(5, 3),
+                effort_event(6, -1),
+                effort_event(25, 1),
+                effort_event(30, -4),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            state.effort_timeline(Duration::days(1)),
+            vec![(start, 4), (start + Duration::days(1), 0)]
+        );
+        // The bucket from 12 to 24 hours has no effort events, so it is left out
+        assert_eq!(
+            state.effort_timeline(Duration::hours(12)),
+            vec![(start, 4), (start + Duration::hours(24), 0)]
+        );
+        assert!(state.effort_timeline(Duration::zero()).is_empty());
+        assert!(state.effort_timeline(-Duration::days(1)).is_empty());
+    }
+}
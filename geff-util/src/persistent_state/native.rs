@@ -1,10 +1,16 @@
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 
 use serde::de::DeserializeOwned;
 
 use serde::Serialize;
 
-use super::{LoadError, PersistentState, SaveError};
+use super::{LoadError, PersistentState, SaveError, DEFAULT_PROFILE_NAME};
+
+#[cfg(not(target_arch = "wasm32"))]
+const PROFILE_EXTENSION: &str = "geff";
+#[cfg(not(target_arch = "wasm32"))]
+const DATA_PATH_OVERRIDE_VAR: &str = "GEFF_CORE_ICED_DATA_PATH";
 
 #[cfg(not(target_arch = "wasm32"))]
 use std::io::{Read, Write};
@@ -42,10 +48,123 @@ impl<C: std::fmt::Debug + Serialize + Clone + Default + DeserializeOwned> Persis
         )))
     }
 
+    /// Resolve the data file of an app. If the `GEFF_CORE_ICED_DATA_PATH` environment
+    /// variable is set it takes precedence over the platform default, and the data file
+    /// is placed at `<GEFF_CORE_ICED_DATA_PATH>/<app_name>/data` so that apps sharing
+    /// the override do not share data.
     pub fn data_path<S: AsRef<str>>(app_name: S) -> Result<PathBuf, LoadError> {
-        std::env::var("GEFF_CORE_ICED_DATA_PATH")
-            .map(|p| Ok(PathBuf::from(p)))
-            .unwrap_or(Self::default_data_path(app_name))
+        match std::env::var_os(DATA_PATH_OVERRIDE_VAR) {
+            Some(data_dir) => Ok(PathBuf::from(data_dir).join(app_name.as_ref()).join("data")),
+            None => Self::default_data_path(app_name),
+        }
+    }
+
+    /// The directory an app keeps its named profiles in. It sits next to the default
+    /// data file and is named after it, so that apps sharing a parent directory do not
+    /// see each other's profiles.
+    pub fn profiles_dir<S: AsRef<str>>(app_name: S) -> Result<PathBuf, LoadError> {
+        let data_path = Self::data_path(app_name)?;
+        let mut dir_name = data_path
+            .file_name()
+            .map(OsString::from)
+            .unwrap_or_default();
+        dir_name.push("-profiles");
+
+        Ok(data_path.with_file_name(dir_name))
+    }
+
+    /// Resolve the data file of a named profile. Profiles other than the default
+    /// profile are stored as `<profile_name>.geff` in
+    /// [profiles_dir](PersistentState::profiles_dir). Names that are empty or could
+    /// point outside of that directory are rejected.
+    pub fn data_path_for<S: AsRef<str>, T: AsRef<str>>(
+        app_name: S,
+        profile_name: T,
+    ) -> Result<PathBuf, LoadError> {
+        let profile_name = profile_name.as_ref();
+
+        if profile_name == DEFAULT_PROFILE_NAME {
+            return Self::data_path(app_name);
+        }
+
+        if profile_name.is_empty()
+            || profile_name.contains("..")
+            || profile_name.chars().any(std::path::is_separator)
+        {
+            return Err(LoadError::InvalidProfileName(profile_name.to_string()));
+        }
+
+        Ok(Self::profiles_dir(app_name)?.join(format!("{profile_name}.{PROFILE_EXTENSION}")))
+    }
+
+    /// List the names of the profiles that exist for an app, sorted by name.
+    pub fn list_profiles<S: AsRef<str>>(app_name: S) -> Result<Vec<String>, LoadError> {
+        let mut profiles = vec![];
+
+        if Self::data_path(app_name.as_ref())?.exists() {
+            profiles.push(DEFAULT_PROFILE_NAME.to_string());
+        }
+
+        if let Ok(entries) = std::fs::read_dir(Self::profiles_dir(app_name)?) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if !path.is_file()
+                    || path.extension().and_then(|e| e.to_str()) != Some(PROFILE_EXTENSION)
+                {
+                    continue;
+                }
+
+                if let Some(profile_name) = path.file_stem().and_then(|s| s.to_str()) {
+                    profiles.push(profile_name.to_string());
+                }
+            }
+        }
+
+        profiles.sort();
+        profiles.dedup();
+
+        Ok(profiles)
+    }
+
+    /// The path a backup of the data file at `profile_data_path` is kept at, which is
+    /// the data file path with `.bak` appended.
+    pub fn backup_path<P: AsRef<Path>>(profile_data_path: P) -> PathBuf {
+        let mut file_name = profile_data_path
+            .as_ref()
+            .file_name()
+            .map(OsString::from)
+            .unwrap_or_default();
+        file_name.push(".bak");
+
+        profile_data_path.as_ref().with_file_name(file_name)
+    }
+
+    /// Load state like [load](PersistentState::load), falling back to the backup at
+    /// [backup_path](PersistentState::backup_path) if the data file is malformed. If
+    /// the backup can't be loaded either, a [Corrupt](LoadError::Corrupt) error is
+    /// returned so that frontends can offer to inspect or restore the data rather than
+    /// starting fresh.
+    pub async fn try_load_with_recovery<P: AsRef<Path>>(
+        profile_data_path: P,
+    ) -> Result<Self, LoadError> {
+        match Self::load(&profile_data_path).await {
+            Err(LoadError::MalformedProfileDataFile(path, _)) => {
+                let backup_path = Self::backup_path(&path);
+                let backup_available = backup_path.exists();
+
+                if backup_available {
+                    if let Ok(persistent_state) = Self::load(&backup_path).await {
+                        return Ok(persistent_state);
+                    }
+                }
+
+                Err(LoadError::Corrupt {
+                    path,
+                    backup_available,
+                })
+            }
+            result => result,
+        }
     }
 
     pub async fn save_to_file<P: AsRef<Path>>(&self, p: P) -> Result<(), SaveError> {
@@ -118,15 +237,26 @@ impl<C: std::fmt::Debug + Serialize + Clone + Default + DeserializeOwned> Persis
                 )
             })?;
 
-        rmp_serde::decode::from_slice(&profile_bytes).map_err(|e| {
-            LoadError::MalformedProfileDataFile(
-                profile_data_path.as_ref().to_path_buf(),
-                e.to_string(),
-            )
-        })
+        rmp_serde::decode::from_slice(&profile_bytes)
+            .map(Self::repaired)
+            .map_err(|e| {
+                LoadError::MalformedProfileDataFile(
+                    profile_data_path.as_ref().to_path_buf(),
+                    e.to_string(),
+                )
+            })
     }
 
     pub fn blocking_load<P: AsRef<Path>>(profile_data_path: P) -> Result<Self, LoadError> {
+        Self::blocking_load_unrepaired(profile_data_path).map(Self::repaired)
+    }
+
+    /// Load state like [blocking_load](PersistentState::blocking_load) without
+    /// repairing it, so that tools which validate or repair profiles see the state as
+    /// it was saved.
+    pub fn blocking_load_unrepaired<P: AsRef<Path>>(
+        profile_data_path: P,
+    ) -> Result<Self, LoadError> {
         use std::fs;
 
         if !profile_data_path.as_ref().exists() {
@@ -179,3 +309,174 @@ impl<C: std::fmt::Debug + Serialize + Clone + Default + DeserializeOwned> Persis
         })
     }
 }
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use geff_core::event::Event;
+    use geff_core::goal::{Goal, GoalId, GoalRelationship};
+    use geff_core::profile::validation::ProfileInconsistency;
+    use geff_core::Utc;
+
+    use std::ffi::OsString;
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::{LoadError, PersistentState, DATA_PATH_OVERRIDE_VAR, DEFAULT_PROFILE_NAME};
+
+    /// Serializes tests that set the data path override, since it is process wide.
+    static DATA_PATH_OVERRIDE_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Sets the data path override for the lifetime of the guard, restoring the
+    /// previous value when dropped.
+    struct DataPathOverride {
+        previous: Option<OsString>,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl DataPathOverride {
+        fn set(data_dir: &std::path::Path) -> Self {
+            let lock = DATA_PATH_OVERRIDE_LOCK
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            let previous = std::env::var_os(DATA_PATH_OVERRIDE_VAR);
+            std::env::set_var(DATA_PATH_OVERRIDE_VAR, data_dir);
+
+            Self {
+                previous,
+                _lock: lock,
+            }
+        }
+    }
+
+    impl Drop for DataPathOverride {
+        fn drop(&mut self) {
+            match self.previous.take() {
+                Some(previous) => std::env::set_var(DATA_PATH_OVERRIDE_VAR, previous),
+                None => std::env::remove_var(DATA_PATH_OVERRIDE_VAR),
+            }
+        }
+    }
+
+    #[test]
+    fn load_with_recovery() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let data_dir = std::env::temp_dir().join(format!("geff-recovery-{}", std::process::id()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+
+        let data_path = data_dir.join("data");
+        let backup_path = PersistentState::<()>::backup_path(&data_path);
+        assert_eq!(backup_path, data_dir.join("data.bak"));
+
+        std::fs::write(&data_path, b"not a profile").unwrap();
+        assert!(matches!(
+            runtime.block_on(PersistentState::<()>::try_load_with_recovery(&data_path)),
+            Err(LoadError::Corrupt {
+                backup_available: false,
+                ..
+            })
+        ));
+
+        let mut backup = PersistentState::<()>::default();
+        backup.profile.add_goal(Goal::new("backed up", 1)).unwrap();
+        runtime.block_on(backup.save_to_file(&backup_path)).unwrap();
+
+        let recovered = runtime
+            .block_on(PersistentState::<()>::try_load_with_recovery(&data_path))
+            .unwrap();
+        assert_eq!(recovered.profile.goal_count(), 1);
+
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn unrepaired_load_keeps_dangling_relationships() {
+        let data_dir = std::env::temp_dir().join(format!("geff-unrepaired-{}", std::process::id()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        let data_path = data_dir.join("data");
+
+        let mut persistent_state = PersistentState::<()>::default();
+        persistent_state.profile.add_event(Event::instant_event(
+            Utc::now(),
+            vec![GoalRelationship::WorksOn(GoalId(100))],
+        ));
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(persistent_state.save_to_file(&data_path))
+            .unwrap();
+
+        let unrepaired = PersistentState::<()>::blocking_load_unrepaired(&data_path).unwrap();
+        assert!(matches!(
+            unrepaired.profile.validate()[..],
+            [ProfileInconsistency::DanglingEventRelationship { .. }]
+        ));
+        let repaired = PersistentState::<()>::blocking_load(&data_path).unwrap();
+        assert!(repaired.profile.validate().is_empty());
+
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn data_path_override_is_scoped_to_app() {
+        let _data_path_override = DataPathOverride::set("/tmp/geff-data".as_ref());
+
+        let tui_path = PersistentState::<()>::data_path("geff-tui").unwrap();
+        let tauri_path = PersistentState::<()>::data_path("geff-tauri").unwrap();
+
+        assert_ne!(tui_path, tauri_path);
+        assert!(tui_path.starts_with("/tmp/geff-data"));
+        assert!(tauri_path.starts_with("/tmp/geff-data"));
+    }
+
+    #[test]
+    fn named_profiles() {
+        let data_dir = std::env::temp_dir().join(format!("geff-profiles-{}", std::process::id()));
+        let _data_path_override = DataPathOverride::set(&data_dir);
+
+        assert_eq!(
+            PersistentState::<()>::data_path_for("geff-tauri", DEFAULT_PROFILE_NAME).unwrap(),
+            PersistentState::<()>::data_path("geff-tauri").unwrap()
+        );
+        let work_path = PersistentState::<()>::data_path_for("geff-tauri", "work").unwrap();
+        assert_eq!(
+            work_path,
+            data_dir
+                .join("geff-tauri")
+                .join("data-profiles")
+                .join("work.geff")
+        );
+        assert_ne!(
+            work_path,
+            PersistentState::<()>::data_path_for("geff-tui", "work").unwrap()
+        );
+        for invalid_name in ["", "..", "../work", "nested/work"] {
+            assert!(matches!(
+                PersistentState::<()>::data_path_for("geff-tauri", invalid_name),
+                Err(LoadError::InvalidProfileName(_))
+            ));
+        }
+
+        assert!(PersistentState::<()>::list_profiles("geff-tauri")
+            .unwrap()
+            .is_empty());
+
+        PersistentState::<()>::blocking_load(
+            PersistentState::<()>::data_path("geff-tauri").unwrap(),
+        )
+        .unwrap();
+        PersistentState::<()>::blocking_load(&work_path).unwrap();
+        PersistentState::<()>::blocking_load(
+            PersistentState::<()>::data_path_for("geff-tui", "other app").unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            PersistentState::<()>::list_profiles("geff-tauri").unwrap(),
+            vec![DEFAULT_PROFILE_NAME.to_string(), "work".to_string()]
+        );
+        assert_eq!(
+            PersistentState::<()>::list_profiles("geff-tui").unwrap(),
+            vec!["other app".to_string()]
+        );
+
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
@@ -1,12 +1,81 @@
+#[cfg(target_arch = "wasm32")]
+use std::path::{Path, PathBuf};
+
+#[cfg(target_arch = "wasm32")]
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
 #[cfg(target_arch = "wasm32")]
 use serde::de::DeserializeOwned;
 
 #[cfg(target_arch = "wasm32")]
 use serde::Serialize;
 
+#[cfg(target_arch = "wasm32")]
+use super::{LoadError, PersistentState, SaveError};
+
+/// On wasm, state is kept in the browser's `localStorage` as base64 encoded
+/// MessagePack. Data paths are used as storage keys so that frontends can share the
+/// same loading and saving code between native and wasm builds.
 #[cfg(target_arch = "wasm32")]
 impl<C: std::fmt::Debug + Serialize + Clone + Default + DeserializeOwned> PersistentState<C> {
-    pub async fn load() -> Self {
-        todo!()
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok().flatten()
+    }
+
+    fn storage_key<P: AsRef<Path>>(p: P) -> String {
+        p.as_ref().to_string_lossy().into_owned()
+    }
+
+    pub fn data_path<S: AsRef<str>>(app_name: S) -> Result<PathBuf, LoadError> {
+        Ok(PathBuf::from(app_name.as_ref()).join("data"))
+    }
+
+    pub async fn save_to_file<P: AsRef<Path>>(&self, p: P) -> Result<(), SaveError> {
+        let storage = Self::local_storage()
+            .ok_or_else(|| SaveError::WriteError("localStorage is not available".to_string()))?;
+
+        let data = BASE64
+            .encode(rmp_serde::to_vec(self).map_err(|e| SaveError::SerializeError(e.to_string()))?);
+
+        storage
+            .set_item(&Self::storage_key(p), &data)
+            .map_err(|e| SaveError::WriteError(format!("{e:?}")))
+    }
+
+    /// Load state saved with [save_to_file](PersistentState::save_to_file), or the
+    /// default state if nothing has been saved yet.
+    pub async fn load<P: AsRef<Path>>(profile_data_path: P) -> Result<Self, LoadError> {
+        let storage = Self::local_storage().ok_or_else(|| {
+            LoadError::NoAppDataOrHomeDirectory("localStorage is not available".to_string())
+        })?;
+
+        let data = storage
+            .get_item(&Self::storage_key(&profile_data_path))
+            .map_err(|e| {
+                LoadError::ProfileDataFileRead(
+                    profile_data_path.as_ref().to_path_buf(),
+                    format!("{e:?}"),
+                )
+            })?;
+
+        let Some(data) = data else {
+            return Ok(Self::default());
+        };
+
+        let profile_bytes = BASE64.decode(data).map_err(|e| {
+            LoadError::MalformedProfileDataFile(
+                profile_data_path.as_ref().to_path_buf(),
+                e.to_string(),
+            )
+        })?;
+
+        rmp_serde::decode::from_slice(&profile_bytes)
+            .map(Self::repaired)
+            .map_err(|e| {
+                LoadError::MalformedProfileDataFile(
+                    profile_data_path.as_ref().to_path_buf(),
+                    e.to_string(),
+                )
+            })
     }
 }